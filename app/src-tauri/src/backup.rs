@@ -0,0 +1,438 @@
+use crate::db::{self, Database, DbError};
+use crate::settings::SettingsState;
+use crate::vault::{self, VaultState};
+use rusqlite::backup::Backup;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+const FILE_PREFIX: &str = "promptpack-";
+const FILE_SUFFIX: &str = ".db";
+
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+// Columns shared by every schema version that can appear in a backup
+const PROMPT_COLUMNS: &str = "id, text, header, source, url, folder_id, is_favorite, use_count, \
+                              created_at, updated_at, sync_status, cloud_id";
+
+/// Why a snapshot was taken. Retention is applied per kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BackupKind {
+    Daily,
+    Weekly,
+    Manual,
+    PreImport,
+    PreMigration,
+    PreRestore,
+}
+
+impl BackupKind {
+    const ALL: [BackupKind; 6] = [
+        BackupKind::Daily,
+        BackupKind::Weekly,
+        BackupKind::Manual,
+        BackupKind::PreImport,
+        BackupKind::PreMigration,
+        BackupKind::PreRestore,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BackupKind::Daily => "daily",
+            BackupKind::Weekly => "weekly",
+            BackupKind::Manual => "manual",
+            BackupKind::PreImport => "pre-import",
+            BackupKind::PreMigration => "pre-migration",
+            BackupKind::PreRestore => "pre-restore",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|k| k.as_str() == s)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupInfo {
+    /// File name inside the backup directory, used as the backup id
+    pub id: String,
+    pub kind: BackupKind,
+    pub created_at: i64,
+    pub size_bytes: u64,
+    pub encrypted: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BackupDiff {
+    pub backup_count: i64,
+    pub current_count: i64,
+    /// Prompts in the current library that the backup does not have
+    pub added_ids: Vec<String>,
+    /// Prompts in the backup that are gone from the current library
+    pub removed_ids: Vec<String>,
+    /// Prompts present in both whose content differs
+    pub changed_ids: Vec<String>,
+}
+
+fn file_name(kind: BackupKind, created_at: i64) -> String {
+    format!("{}{}-{}{}", FILE_PREFIX, kind.as_str(), created_at, FILE_SUFFIX)
+}
+
+/// Parse `promptpack-<kind>-<millis>.db`. Anything else is not one of ours,
+/// which also keeps ids from the frontend from escaping the backup dir.
+fn parse_file_name(name: &str) -> Option<(BackupKind, i64)> {
+    let stem = name.strip_prefix(FILE_PREFIX)?.strip_suffix(FILE_SUFFIX)?;
+    let (kind, created_at) = stem.rsplit_once('-')?;
+    Some((BackupKind::parse(kind)?, created_at.parse().ok()?))
+}
+
+fn backup_info(path: &Path) -> Option<BackupInfo> {
+    let name = path.file_name()?.to_str()?;
+    let (kind, created_at) = parse_file_name(name)?;
+    let size_bytes = std::fs::metadata(path).ok()?.len();
+
+    Some(BackupInfo {
+        id: name.to_string(),
+        kind,
+        created_at,
+        size_bytes,
+        encrypted: vault::is_encrypted(path).unwrap_or(false),
+    })
+}
+
+/// Resolve a backup id to its file, refusing anything that is not a backup
+pub fn backup_path(dir: &Path, id: &str) -> Result<PathBuf, DbError> {
+    if parse_file_name(id).is_none() {
+        return Err(DbError::NotFound("Backup"));
+    }
+    let path = dir.join(id);
+    if !path.is_file() {
+        return Err(DbError::NotFound("Backup"));
+    }
+    Ok(path)
+}
+
+/// Snapshot the database behind `conn` using SQLite's online backup API.
+///
+/// The copy is encrypted with the same key as the live library, so a vault
+/// never leaks into a plaintext backup.
+pub fn create_backup(
+    conn: &Connection,
+    dir: &Path,
+    kind: BackupKind,
+    key: Option<&str>,
+) -> Result<BackupInfo, DbError> {
+    std::fs::create_dir_all(dir)?;
+
+    let created_at = chrono::Utc::now().timestamp_millis();
+    let path = dir.join(file_name(kind, created_at));
+    let tmp_path = dir.join(format!("{}.partial", file_name(kind, created_at)));
+
+    {
+        let mut dest = Connection::open(&tmp_path)?;
+        if let Some(key) = key {
+            vault::apply_key(&dest, key)?;
+        }
+        let backup = Backup::new(conn, &mut dest)?;
+        backup.run_to_completion(256, Duration::from_millis(5), None)?;
+    }
+    std::fs::rename(&tmp_path, &path)?;
+
+    backup_info(&path).ok_or(DbError::NotFound("Backup"))
+}
+
+/// All backups in `dir`, newest first
+pub fn list_backups(dir: &Path) -> Result<Vec<BackupInfo>, DbError> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut backups = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        if let Some(info) = backup_info(&entry?.path()) {
+            backups.push(info);
+        }
+    }
+    backups.sort_by_key(|b| std::cmp::Reverse(b.created_at));
+    Ok(backups)
+}
+
+/// Delete the oldest backups of `kind` beyond the newest `keep`
+pub fn prune_backups(dir: &Path, kind: BackupKind, keep: usize) -> Result<usize, DbError> {
+    let stale: Vec<BackupInfo> = list_backups(dir)?
+        .into_iter()
+        .filter(|b| b.kind == kind)
+        .skip(keep)
+        .collect();
+
+    for backup in &stale {
+        std::fs::remove_file(dir.join(&backup.id))?;
+    }
+    Ok(stale.len())
+}
+
+/// Scheduled kinds whose newest snapshot is older than their interval
+pub fn due_kinds(backups: &[BackupInfo], now: i64) -> Vec<BackupKind> {
+    [(BackupKind::Daily, DAY_MS), (BackupKind::Weekly, 7 * DAY_MS)]
+        .into_iter()
+        .filter(|(kind, interval)| {
+            backups
+                .iter()
+                .filter(|b| b.kind == *kind)
+                .map(|b| b.created_at)
+                .max()
+                .map_or(true, |latest| now - latest >= *interval)
+        })
+        .map(|(kind, _)| kind)
+        .collect()
+}
+
+/// Attach a backup as `snapshot` for the duration of `f`
+fn with_snapshot<T, F>(
+    conn: &mut Connection,
+    path: &Path,
+    key: Option<&str>,
+    f: F,
+) -> Result<T, DbError>
+where
+    F: FnOnce(&mut Connection) -> Result<T, DbError>,
+{
+    // An empty KEY attaches a plaintext file
+    conn.execute(
+        "ATTACH DATABASE ?1 AS snapshot KEY ?2",
+        rusqlite::params![path.to_string_lossy(), key.unwrap_or("")],
+    )?;
+    let result = f(conn);
+    conn.execute("DETACH DATABASE snapshot", [])?;
+    result
+}
+
+fn query_ids(conn: &Connection, sql: &str) -> Result<Vec<String>, DbError> {
+    let mut stmt = conn.prepare(sql)?;
+    let ids = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<Vec<String>, _>>()?;
+    Ok(ids)
+}
+
+/// Compare the prompts in a backup with the current library
+pub fn diff_backup(
+    conn: &mut Connection,
+    path: &Path,
+    key: Option<&str>,
+) -> Result<BackupDiff, DbError> {
+    with_snapshot(conn, path, key, |conn| {
+        let backup_count =
+            conn.query_row("SELECT COUNT(*) FROM snapshot.prompts", [], |row| row.get(0))?;
+        let current_count =
+            conn.query_row("SELECT COUNT(*) FROM main.prompts", [], |row| row.get(0))?;
+
+        Ok(BackupDiff {
+            backup_count,
+            current_count,
+            added_ids: query_ids(
+                conn,
+                "SELECT id FROM main.prompts
+                 WHERE id NOT IN (SELECT id FROM snapshot.prompts)",
+            )?,
+            removed_ids: query_ids(
+                conn,
+                "SELECT id FROM snapshot.prompts
+                 WHERE id NOT IN (SELECT id FROM main.prompts)",
+            )?,
+            changed_ids: query_ids(
+                conn,
+                "SELECT m.id FROM main.prompts m
+                 JOIN snapshot.prompts s ON s.id = m.id
+                 WHERE m.text IS NOT s.text
+                    OR m.header IS NOT s.header
+                    OR m.updated_at IS NOT s.updated_at",
+            )?,
+        })
+    })
+}
+
+/// Copy selected prompts (and their tags) from a backup into the library,
+/// overwriting the current version of each. Returns how many were restored.
+pub fn restore_prompts(
+    conn: &mut Connection,
+    path: &Path,
+    key: Option<&str>,
+    prompt_ids: &[String],
+) -> Result<usize, DbError> {
+    if prompt_ids.is_empty() {
+        return Ok(0);
+    }
+
+    with_snapshot(conn, path, key, |conn| {
+        let tx = conn.transaction()?;
+        tx.execute_batch(
+            "CREATE TEMP TABLE IF NOT EXISTS restore_ids (id TEXT PRIMARY KEY);
+             DELETE FROM temp.restore_ids;",
+        )?;
+        {
+            let mut insert = tx.prepare("INSERT OR IGNORE INTO temp.restore_ids (id) VALUES (?)")?;
+            for id in prompt_ids {
+                insert.execute([id])?;
+            }
+        }
+        // Ids the backup doesn't have are left alone. Purged prompts that come
+        // back must not be deleted again by the next sync.
+        tx.execute_batch(
+            "DELETE FROM temp.restore_ids WHERE id NOT IN (SELECT id FROM snapshot.prompts);
+             DELETE FROM main.sync_tombstones WHERE prompt_id IN (SELECT id FROM temp.restore_ids);",
+        )?;
+
+        // Update in place rather than delete + insert, so rows that reference
+        // the prompt through ON DELETE CASCADE survive the restore
+        let updated = tx.execute(
+            &format!(
+                "UPDATE main.prompts SET ({cols}) = (
+                     SELECT {cols} FROM snapshot.prompts s WHERE s.id = main.prompts.id
+                 )
+                 WHERE id IN (SELECT id FROM temp.restore_ids)",
+                cols = PROMPT_COLUMNS
+            ),
+            [],
+        )?;
+        let inserted = tx.execute(
+            &format!(
+                "INSERT INTO main.prompts ({cols})
+                 SELECT {cols} FROM snapshot.prompts
+                 WHERE id IN (SELECT id FROM temp.restore_ids)
+                   AND id NOT IN (SELECT id FROM main.prompts)",
+                cols = PROMPT_COLUMNS
+            ),
+            [],
+        )?;
+
+        // Restored prompts come out of the trash, and the backup may point at
+        // folders that are gone or trashed by now
+        tx.execute(
+            "UPDATE main.prompts SET
+                 deleted_at = NULL,
+                 folder_id = CASE
+                     WHEN folder_id IN (SELECT id FROM main.folders WHERE deleted_at IS NULL)
+                     THEN folder_id
+                 END
+             WHERE id IN (SELECT id FROM temp.restore_ids)",
+            [],
+        )?;
+
+        // Tags are matched by name, since ids can differ between libraries
+        tx.execute_batch(
+            "DELETE FROM main.prompt_tags WHERE prompt_id IN (SELECT id FROM temp.restore_ids);
+             INSERT OR IGNORE INTO main.tags (id, name, color)
+             SELECT t.id, t.name, t.color FROM snapshot.tags t
+             WHERE t.id IN (
+                 SELECT tag_id FROM snapshot.prompt_tags
+                 WHERE prompt_id IN (SELECT id FROM temp.restore_ids)
+             )
+             AND t.name NOT IN (SELECT name FROM main.tags);
+             INSERT OR IGNORE INTO main.prompt_tags (prompt_id, tag_id)
+             SELECT pt.prompt_id, mt.id
+             FROM snapshot.prompt_tags pt
+             JOIN snapshot.tags st ON st.id = pt.tag_id
+             JOIN main.tags mt ON mt.name = st.name
+             WHERE pt.prompt_id IN (SELECT id FROM main.prompts)
+               AND pt.prompt_id IN (SELECT id FROM temp.restore_ids);
+             DELETE FROM temp.restore_ids;",
+        )?;
+
+        tx.commit()?;
+        Ok(updated + inserted)
+    })
+}
+
+// ============ App glue ============
+
+pub fn get_backup_dir(app_handle: &AppHandle) -> Result<PathBuf, DbError> {
+    let dir = db::get_db_path(app_handle)?.with_file_name("backups");
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Snapshot the open library and apply the retention for `kind`
+pub async fn snapshot(app_handle: &AppHandle, kind: BackupKind) -> Result<BackupInfo, DbError> {
+    let dir = get_backup_dir(app_handle)?;
+    let key = app_handle.state::<VaultState>().key();
+    let keep = app_handle
+        .state::<SettingsState>()
+        .get()
+        .backup
+        .retention_for(kind);
+
+    app_handle
+        .state::<Database>()
+        .run_background(move |conn| {
+            let info = create_backup(conn, &dir, kind, key.as_deref())?;
+            prune_backups(&dir, kind, keep)?;
+            Ok(info)
+        })
+        .await
+}
+
+/// Take whichever daily or weekly snapshots are due
+pub async fn run_scheduled(app_handle: &AppHandle) -> Result<(), DbError> {
+    if !app_handle.state::<SettingsState>().get().backup.enabled {
+        return Ok(());
+    }
+
+    let dir = get_backup_dir(app_handle)?;
+    let now = chrono::Utc::now().timestamp_millis();
+    for kind in due_kinds(&list_backups(&dir)?, now) {
+        let info = snapshot(app_handle, kind).await?;
+        log::info!("Created {} backup {}", kind.as_str(), info.id);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::{CreatePromptInput, PromptRepository};
+    use crate::trash;
+
+    fn create(conn: &Connection, text: &str) -> String {
+        let input = CreatePromptInput {
+            text: text.to_string(),
+            ..Default::default()
+        };
+        PromptRepository::new(conn).create(input, 1).unwrap().id
+    }
+
+    #[test]
+    fn restore_only_touches_prompts_in_the_backup() {
+        let mut conn = db::open_in_memory();
+        let purged = create(&conn, "In the backup");
+        let dir = std::env::temp_dir().join(format!("promptpack-backup-{}", uuid::Uuid::new_v4()));
+        let backup = create_backup(&conn, &dir, BackupKind::Manual, None).unwrap();
+
+        let newer = create(&conn, "Made later");
+        conn.execute_batch(&format!(
+            "INSERT INTO tags (id, name) VALUES ('t1', 'keep');
+             INSERT INTO prompt_tags (prompt_id, tag_id) VALUES ('{newer}', 't1');"
+        ))
+        .unwrap();
+        let prompts = PromptRepository::new(&conn);
+        prompts.delete(&newer, 2).unwrap();
+        prompts.delete(&purged, 2).unwrap();
+        trash::purge_prompt(&conn, &purged).unwrap();
+
+        let ids = [purged.clone(), newer.clone()];
+        let restored = restore_prompts(&mut conn, &dir.join(&backup.id), None, &ids).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(restored, 1);
+
+        let prompts = PromptRepository::new(&conn);
+        assert!(prompts.get(&purged).unwrap().unwrap().deleted_at.is_none());
+        let tombstones = trash::tombstones(&conn, 0).unwrap();
+        assert!(tombstones.iter().all(|t| t.prompt_id != purged));
+        let newer = prompts.get(&newer).unwrap().unwrap();
+        assert!(newer.deleted_at.is_some());
+        assert_eq!(prompts.tag_names(&newer.id).unwrap(), ["keep"]);
+    }
+}
@@ -0,0 +1,5 @@
+//! Headless `promptpack-cli` command. The desktop app is the default binary.
+
+fn main() -> std::process::ExitCode {
+    promptpack_lib::cli::run()
+}
@@ -0,0 +1,453 @@
+//! Opt-in HTTP bridge on 127.0.0.1 that lets the browser extension read and
+//! save prompts in the desktop library.
+//!
+//! Every request except `GET /v1/ping` must carry the pairing token as
+//! `Authorization: Bearer <token>`. Requests addressed to any host other
+//! than the loopback address are refused, so a web page can't reach the
+//! bridge through DNS rebinding.
+//!
+//! | Method | Path                    | Returns                  |
+//! |--------|-------------------------|--------------------------|
+//! | GET    | `/v1/ping`              | app name and version     |
+//! | GET    | `/v1/prompts`           | one page of prompts      |
+//! | GET    | `/v1/prompts/search?q=` | best matches             |
+//! | POST   | `/v1/prompts`           | the created prompt (201) |
+//! | POST   | `/v1/prompts/<id>/use`  | the used prompt          |
+
+use crate::db::Database;
+use crate::error::{CommandError, ErrorCode};
+use crate::repository::{CreatePromptInput, PromptPageQuery, PromptRepository};
+use crate::settings::SettingsState;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use http_body_util::{BodyExt, Full, Limited};
+use hyper::body::{Bytes, Incoming};
+use hyper::header::{self, HeaderMap, HeaderValue};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use rand::RngCore;
+use serde::Serialize;
+use std::convert::Infallible;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Manager};
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+
+/// Largest request body accepted
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+
+/// Origins allowed to call the bridge from a browser
+const EXTENSION_ORIGINS: &[&str] = &["chrome-extension://", "moz-extension://"];
+
+/// Where the bridge finds the library: the app's managed database, or a
+/// standalone one in tests
+pub trait LibraryHandle: Clone + Send + Sync + 'static {
+    fn database(&self) -> &Database;
+}
+
+impl LibraryHandle for AppHandle {
+    fn database(&self) -> &Database {
+        self.state::<Database>().inner()
+    }
+}
+
+impl LibraryHandle for Arc<Database> {
+    fn database(&self) -> &Database {
+        self
+    }
+}
+
+/// A running bridge. Dropping it stops accepting connections.
+pub struct BridgeServer {
+    addr: SocketAddr,
+    token: Arc<Mutex<String>>,
+    _shutdown: oneshot::Sender<()>,
+}
+
+impl BridgeServer {
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Require a new pairing token from the next request on
+    pub fn set_token(&self, token: String) {
+        if let Ok(mut t) = self.token.lock() {
+            *t = token;
+        }
+    }
+}
+
+/// Holds the bridge while it runs
+#[derive(Default)]
+pub struct BridgeState {
+    server: Mutex<Option<BridgeServer>>,
+}
+
+impl BridgeState {
+    fn addr(&self) -> Option<SocketAddr> {
+        self.server
+            .lock()
+            .ok()
+            .and_then(|s| s.as_ref().map(BridgeServer::addr))
+    }
+
+    fn replace(&self, server: Option<BridgeServer>) {
+        if let Ok(mut s) = self.server.lock() {
+            *s = server;
+        }
+    }
+
+    fn set_token(&self, token: String) {
+        if let Ok(Some(server)) = self.server.lock().as_deref() {
+            server.set_token(token);
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct BridgeStatus {
+    pub enabled: bool,
+    pub running: bool,
+    pub port: u16,
+    /// Pairing token to enter in the extension
+    pub token: String,
+}
+
+pub fn status(app_handle: &AppHandle) -> BridgeStatus {
+    let settings = app_handle.state::<SettingsState>().get().bridge;
+    let addr = app_handle.state::<BridgeState>().addr();
+    BridgeStatus {
+        enabled: settings.enabled,
+        running: addr.is_some(),
+        port: addr.map_or(settings.port, |a| a.port()),
+        token: settings.token,
+    }
+}
+
+/// Start the bridge if it was left enabled
+pub async fn start_saved(app_handle: &AppHandle) {
+    let settings = app_handle.state::<SettingsState>().get().bridge;
+    if !settings.enabled || settings.token.is_empty() {
+        return;
+    }
+    match serve(app_handle.clone(), settings.port, settings.token).await {
+        Ok(server) => {
+            log::info!("Extension bridge listening on {}", server.addr());
+            app_handle.state::<BridgeState>().replace(Some(server));
+        }
+        Err(e) => log::warn!("Failed to start the extension bridge: {}", e),
+    }
+}
+
+/// Start the bridge, or move it to `port`, and remember it as enabled,
+/// creating a pairing token the first time
+pub async fn enable(
+    app_handle: &AppHandle,
+    port: Option<u16>,
+) -> Result<BridgeStatus, CommandError> {
+    let settings = app_handle.state::<SettingsState>();
+    let mut bridge = settings.get().bridge;
+    if let Some(port) = port {
+        bridge.port = port;
+    }
+    if bridge.token.is_empty() {
+        bridge.token = generate_token();
+    }
+
+    // Keep serving on the same port, otherwise move only once the new port
+    // is bound
+    let state = app_handle.state::<BridgeState>();
+    if state.addr().is_some_and(|a| a.port() == bridge.port) {
+        state.set_token(bridge.token.clone());
+    } else {
+        let server = serve(app_handle.clone(), bridge.port, bridge.token.clone()).await?;
+        state.replace(Some(server));
+    }
+
+    bridge.enabled = true;
+    settings.update(|s| s.bridge = bridge)?;
+    Ok(status(app_handle))
+}
+
+pub fn disable(app_handle: &AppHandle) -> Result<BridgeStatus, CommandError> {
+    app_handle.state::<BridgeState>().replace(None);
+    app_handle
+        .state::<SettingsState>()
+        .update(|s| s.bridge.enabled = false)?;
+    Ok(status(app_handle))
+}
+
+/// Issue a new pairing token, unpairing every extension
+pub fn regenerate_token(app_handle: &AppHandle) -> Result<BridgeStatus, CommandError> {
+    let token = generate_token();
+    app_handle
+        .state::<SettingsState>()
+        .update(|s| s.bridge.token = token.clone())?;
+    app_handle.state::<BridgeState>().set_token(token);
+    Ok(status(app_handle))
+}
+
+fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Listen on 127.0.0.1:`port` (0 picks a free port) until the returned
+/// server is dropped
+pub async fn serve<L: LibraryHandle>(
+    library: L,
+    port: u16,
+    token: String,
+) -> std::io::Result<BridgeServer> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port)).await?;
+    let addr = listener.local_addr()?;
+    let (shutdown, mut stopped) = oneshot::channel::<()>();
+    let token = Arc::new(Mutex::new(token));
+    let server = BridgeServer {
+        addr,
+        token: token.clone(),
+        _shutdown: shutdown,
+    };
+
+    tokio::spawn(async move {
+        loop {
+            let stream = tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        log::warn!("Bridge failed to accept a connection: {}", e);
+                        continue;
+                    }
+                },
+                _ = &mut stopped => break,
+            };
+
+            let library = library.clone();
+            let token = token.clone();
+            let service = service_fn(move |req| {
+                let library = library.clone();
+                let token = token.clone();
+                async move {
+                    let token = token.lock().map(|t| t.clone()).unwrap_or_default();
+                    Ok::<_, Infallible>(respond(&library, &token, addr.port(), req).await)
+                }
+            });
+            tokio::spawn(async move {
+                if let Err(e) = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await
+                {
+                    log::debug!("Bridge connection closed: {}", e);
+                }
+            });
+        }
+    });
+
+    Ok(server)
+}
+
+async fn respond<L: LibraryHandle>(
+    library: &L,
+    token: &str,
+    port: u16,
+    req: Request<Incoming>,
+) -> Response<Full<Bytes>> {
+    let origin = req
+        .headers()
+        .get(header::ORIGIN)
+        .filter(|o| {
+            o.to_str()
+                .is_ok_and(|o| EXTENSION_ORIGINS.iter().any(|p| o.starts_with(p)))
+        })
+        .cloned();
+
+    let mut response = if !is_loopback_host(req.headers(), port) {
+        error_response(CommandError::new(
+            ErrorCode::Validation,
+            "Requests must be addressed to 127.0.0.1",
+        ))
+    } else if req.method() == Method::OPTIONS {
+        empty_response(StatusCode::NO_CONTENT)
+    } else {
+        match route(library, token, req).await {
+            Ok(response) => response,
+            Err(e) => error_response(e),
+        }
+    };
+
+    if let Some(origin) = origin {
+        let headers = response.headers_mut();
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_METHODS,
+            HeaderValue::from_static("GET, POST, OPTIONS"),
+        );
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_HEADERS,
+            HeaderValue::from_static("authorization, content-type"),
+        );
+    }
+    response
+}
+
+async fn route<L: LibraryHandle>(
+    library: &L,
+    token: &str,
+    req: Request<Incoming>,
+) -> Result<Response<Full<Bytes>>, CommandError> {
+    let path = req.uri().path().trim_matches('/').to_string();
+    let segments: Vec<&str> = path.split('/').collect();
+    let method = req.method().clone();
+
+    if method == Method::GET && segments == ["v1", "ping"] {
+        return json_response(
+            StatusCode::OK,
+            &serde_json::json!({ "app": "promptpack", "version": env!("CARGO_PKG_VERSION") }),
+        );
+    }
+    if !is_authorized(req.headers(), token) {
+        return Err(CommandError::new(
+            ErrorCode::InvalidToken,
+            "Missing or wrong pairing token",
+        ));
+    }
+
+    let params: Vec<(String, String)> = req
+        .uri()
+        .query()
+        .map(|q| {
+            url::form_urlencoded::parse(q.as_bytes())
+                .into_owned()
+                .collect()
+        })
+        .unwrap_or_default();
+    let param = |name: &str| {
+        params
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.clone())
+    };
+    let database = library.database();
+    let now = chrono::Utc::now().timestamp_millis();
+
+    match (method, segments.as_slice()) {
+        (Method::GET, ["v1", "prompts"]) => {
+            let mut query = PromptPageQuery {
+                cursor: param("cursor"),
+                preview: param("preview").as_deref() == Some("true"),
+                ..Default::default()
+            };
+            query.filter.folder_id = param("folder_id");
+            query.filter.favorite = param("favorite").map(|f| f == "true");
+            query.filter.tags = params
+                .iter()
+                .filter(|(k, _)| k == "tag")
+                .map(|(_, v)| v.clone())
+                .collect();
+            if let Some(limit) = param("limit") {
+                query.limit = Some(
+                    limit
+                        .parse()
+                        .map_err(|_| CommandError::validation("limit must be a number"))?,
+                );
+            }
+            let page = database
+                .run_background(move |conn| PromptRepository::new(conn).page(&query, now))
+                .await?;
+            json_response(StatusCode::OK, &page)
+        }
+        (Method::GET, ["v1", "prompts", "search"]) => {
+            let q = param("q").unwrap_or_default();
+            let prompts = database
+                .run_background(move |conn| PromptRepository::new(conn).search(&q))
+                .await?;
+            json_response(StatusCode::OK, &prompts)
+        }
+        (Method::POST, ["v1", "prompts"]) => {
+            let input: CreatePromptInput = serde_json::from_slice(&read_body(req).await?)?;
+            let prompt = database
+                .run(move |conn| {
+                    let tx = conn.transaction()?;
+                    let prompt = PromptRepository::new(&tx).create(input, now)?;
+                    tx.commit()?;
+                    Ok(prompt)
+                })
+                .await?;
+            json_response(StatusCode::CREATED, &prompt)
+        }
+        (Method::POST, ["v1", "prompts", id, "use"]) => {
+            let id = id.to_string();
+            let prompt = database
+                .run(move |conn| PromptRepository::new(conn).record_use(&id, now))
+                .await?;
+            json_response(StatusCode::OK, &prompt)
+        }
+        _ => Err(CommandError::new(ErrorCode::NotFound, "No such endpoint")),
+    }
+}
+
+/// Whether the Host header names the loopback address we listen on
+pub(crate) fn is_loopback_host(headers: &HeaderMap, port: u16) -> bool {
+    let host = headers
+        .get(header::HOST)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or_default();
+    [format!("127.0.0.1:{port}"), format!("localhost:{port}")].contains(&host.to_string())
+}
+
+/// Whether the request carries `token` as a bearer token
+pub(crate) fn is_authorized(headers: &HeaderMap, token: &str) -> bool {
+    !token.is_empty()
+        && headers
+            .get(header::AUTHORIZATION)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.strip_prefix("Bearer "))
+            .is_some_and(|given| constant_time_eq(given.as_bytes(), token.as_bytes()))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+pub(crate) async fn read_body(req: Request<Incoming>) -> Result<Bytes, CommandError> {
+    Limited::new(req.into_body(), MAX_BODY_BYTES)
+        .collect()
+        .await
+        .map(|body| body.to_bytes())
+        .map_err(|_| CommandError::validation("Request body is too large or incomplete"))
+}
+
+pub(crate) fn json_response<T: Serialize>(
+    status: StatusCode,
+    body: &T,
+) -> Result<Response<Full<Bytes>>, CommandError> {
+    let mut response = Response::new(Full::new(Bytes::from(serde_json::to_vec(body)?)));
+    *response.status_mut() = status;
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    Ok(response)
+}
+
+pub(crate) fn empty_response(status: StatusCode) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::new(Bytes::new()));
+    *response.status_mut() = status;
+    response
+}
+
+pub(crate) fn error_response(error: CommandError) -> Response<Full<Bytes>> {
+    let status = match error.code {
+        ErrorCode::NotFound => StatusCode::NOT_FOUND,
+        ErrorCode::Validation | ErrorCode::InvalidFile => StatusCode::BAD_REQUEST,
+        ErrorCode::InvalidToken => StatusCode::UNAUTHORIZED,
+        ErrorCode::Conflict => StatusCode::CONFLICT,
+        ErrorCode::VaultLocked | ErrorCode::PasswordRequired => StatusCode::LOCKED,
+        ErrorCode::DatabaseBusy | ErrorCode::DatabaseNotReady => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    json_response(status, &error).unwrap_or_else(|_| empty_response(status))
+}
@@ -0,0 +1,271 @@
+use crate::db::DbError;
+use crate::journal::{self, EntityKind};
+use crate::trash;
+use crate::validation::Validator;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// A change applied to every prompt in a bulk operation
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum BulkAction {
+    /// Move into a folder, or out of all folders with None
+    MoveToFolder {
+        folder_id: Option<String>,
+    },
+    SetFavorite {
+        favorite: bool,
+    },
+    SetSource {
+        source: String,
+    },
+    AddTags {
+        tag_ids: Vec<String>,
+    },
+    RemoveTags {
+        tag_ids: Vec<String>,
+    },
+    /// Move to the trash
+    Delete,
+}
+
+impl BulkAction {
+    fn label(&self) -> &'static str {
+        match self {
+            BulkAction::MoveToFolder { .. } => "Move prompts",
+            BulkAction::SetFavorite { favorite: true } => "Favorite prompts",
+            BulkAction::SetFavorite { favorite: false } => "Unfavorite prompts",
+            BulkAction::SetSource { .. } => "Change prompt source",
+            BulkAction::AddTags { .. } => "Tag prompts",
+            BulkAction::RemoveTags { .. } => "Untag prompts",
+            BulkAction::Delete => "Delete prompts",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct BulkItemResult {
+    pub id: String,
+    pub ok: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct BulkResult {
+    /// Journal entry that undoes the whole batch; None if nothing changed
+    pub operation_id: Option<i64>,
+    pub updated: usize,
+    pub results: Vec<BulkItemResult>,
+}
+
+/// Apply `action` to each prompt in `ids` and journal the batch as a single
+/// operation. Repeated ids are applied once. Prompts that are missing or trashed are reported per id; an
+/// invalid folder, source or tag fails the whole batch. Callers should run
+/// this inside a transaction.
+pub fn apply(
+    conn: &Connection,
+    ids: &[String],
+    action: &BulkAction,
+    now: i64,
+) -> Result<BulkResult, DbError> {
+    let mut validator = Validator::new();
+    match action {
+        BulkAction::MoveToFolder { folder_id } => {
+            validator.folder(conn, "folder_id", folder_id.as_deref())?;
+        }
+        BulkAction::SetSource { source } => {
+            validator.source("source", Some(source));
+        }
+        BulkAction::AddTags { tag_ids } | BulkAction::RemoveTags { tag_ids } => {
+            validator.tags(conn, "tag_ids", tag_ids)?;
+        }
+        _ => {}
+    }
+    validator.finish()?;
+
+    // Each prompt is reported once, however often the request lists it
+    let mut seen = HashSet::new();
+    let mut live = Vec::new();
+    let mut results = Vec::with_capacity(ids.len());
+    for id in ids.iter().filter(|id| seen.insert(id.as_str())) {
+        let found: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM prompts WHERE id = ?1 AND deleted_at IS NULL)",
+            [id],
+            |row| row.get(0),
+        )?;
+        if found {
+            live.push(id.clone());
+        }
+        results.push(BulkItemResult {
+            id: id.clone(),
+            ok: found,
+            error: (!found).then(|| DbError::NotFound("Prompt").to_string()),
+        });
+    }
+
+    let entities: Vec<(EntityKind, String)> = live
+        .iter()
+        .map(|id| (EntityKind::Prompt, id.clone()))
+        .collect();
+    let (_, changes) = journal::capture(conn, &entities, |conn| {
+        for id in &live {
+            apply_one(conn, id, action, now)?;
+        }
+        Ok(())
+    })?;
+
+    let operation_id = journal::record(conn, action.label(), &changes, now)?;
+    Ok(BulkResult {
+        operation_id,
+        updated: changes.len(),
+        results,
+    })
+}
+
+fn apply_one(conn: &Connection, id: &str, action: &BulkAction, now: i64) -> Result<(), DbError> {
+    match action {
+        BulkAction::MoveToFolder { folder_id } => {
+            conn.execute(
+                "UPDATE prompts SET folder_id = ?2, updated_at = ?3
+                 WHERE id = ?1 AND folder_id IS NOT ?2",
+                rusqlite::params![id, folder_id, now],
+            )?;
+        }
+        BulkAction::SetFavorite { favorite } => {
+            conn.execute(
+                "UPDATE prompts SET is_favorite = ?2, updated_at = ?3
+                 WHERE id = ?1 AND is_favorite IS NOT ?2",
+                rusqlite::params![id, i32::from(*favorite), now],
+            )?;
+        }
+        BulkAction::SetSource { source } => {
+            conn.execute(
+                "UPDATE prompts SET source = ?2, updated_at = ?3
+                 WHERE id = ?1 AND source IS NOT ?2",
+                rusqlite::params![id, source, now],
+            )?;
+        }
+        BulkAction::AddTags { tag_ids } => {
+            for tag_id in tag_ids {
+                conn.execute(
+                    "INSERT OR IGNORE INTO prompt_tags (prompt_id, tag_id) VALUES (?1, ?2)",
+                    [id, tag_id.as_str()],
+                )?;
+            }
+        }
+        BulkAction::RemoveTags { tag_ids } => {
+            for tag_id in tag_ids {
+                conn.execute(
+                    "DELETE FROM prompt_tags WHERE prompt_id = ?1 AND tag_id = ?2",
+                    [id, tag_id.as_str()],
+                )?;
+            }
+        }
+        BulkAction::Delete => trash::trash_prompt(conn, id, now)?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::journal::Direction;
+    use crate::repository::{CreatePromptInput, PromptRepository};
+
+    fn create(conn: &Connection, text: &str) -> String {
+        let input = CreatePromptInput {
+            text: text.to_string(),
+            ..Default::default()
+        };
+        PromptRepository::new(conn).create(input, 1).unwrap().id
+    }
+
+    fn operations(conn: &Connection) -> i64 {
+        conn.query_row("SELECT COUNT(*) FROM operation_journal", [], |row| {
+            row.get(0)
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn results_cover_each_requested_prompt_once() {
+        let conn = db::open_in_memory();
+        let prompts = PromptRepository::new(&conn);
+        let live = create(&conn, "Live");
+        let trashed = create(&conn, "Trashed");
+        prompts.delete(&trashed, 2).unwrap();
+
+        let ids = [
+            live.clone(),
+            trashed.clone(),
+            live.clone(),
+            "missing".to_string(),
+        ];
+        let action = BulkAction::SetFavorite { favorite: true };
+        let result = apply(&conn, &ids, &action, 3).unwrap();
+
+        let results: Vec<_> = result
+            .results
+            .iter()
+            .map(|r| (r.id.as_str(), r.ok, r.error.is_some()))
+            .collect();
+        assert_eq!(
+            results,
+            [
+                (live.as_str(), true, false),
+                (trashed.as_str(), false, true),
+                ("missing", false, true),
+            ]
+        );
+        assert_eq!(result.updated, 1);
+        assert!(prompts.get(&live).unwrap().unwrap().is_favorite);
+        assert!(!prompts.get(&trashed).unwrap().unwrap().is_favorite);
+    }
+
+    #[test]
+    fn batch_is_one_operation() {
+        let conn = db::open_in_memory();
+        let prompts = PromptRepository::new(&conn);
+        let ids = [create(&conn, "One"), create(&conn, "Two")];
+        let before = operations(&conn);
+
+        let result = apply(&conn, &ids, &BulkAction::Delete, 2).unwrap();
+        assert_eq!(result.updated, 2);
+        assert!(result.operation_id.is_some());
+        assert_eq!(operations(&conn), before + 1);
+
+        let undone = journal::apply(&conn, Direction::Undo, 3).unwrap().unwrap();
+        assert_eq!(undone.label, "Delete prompts");
+        assert_eq!(undone.entities.len(), 2);
+        for id in &ids {
+            assert_eq!(prompts.get(id).unwrap().unwrap().deleted_at, None);
+        }
+    }
+
+    #[test]
+    fn nothing_changed_records_nothing() {
+        let conn = db::open_in_memory();
+        let ids = [create(&conn, "One")];
+        let before = operations(&conn);
+
+        let action = BulkAction::SetFavorite { favorite: false };
+        let result = apply(&conn, &ids, &action, 2).unwrap();
+        assert_eq!((result.operation_id, result.updated), (None, 0));
+        assert_eq!(operations(&conn), before);
+    }
+
+    #[test]
+    fn unknown_tag_fails_the_batch() {
+        let conn = db::open_in_memory();
+        let ids = [create(&conn, "One")];
+        let action = BulkAction::AddTags {
+            tag_ids: vec!["missing".to_string()],
+        };
+        assert!(matches!(
+            apply(&conn, &ids, &action, 2),
+            Err(DbError::Validation(_))
+        ));
+    }
+}
@@ -0,0 +1,225 @@
+//! Saving whatever text is on the clipboard as a prompt.
+
+use crate::db::{Database, DbError};
+use crate::error::CommandError;
+use crate::repository::{CreatePromptInput, Folder, FolderRepository, Prompt, PromptRepository};
+use crate::settings::{CaptureSettings, SettingsState};
+use crate::validation::PromptSource;
+use rusqlite::Connection;
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_clipboard_manager::ClipboardExt;
+
+/// Longest header taken from the first line
+const HEADER_CHARS: usize = 60;
+
+/// Characters stripped from the start of the first line, such as Markdown
+/// heading and list markers
+const LINE_MARKERS: &[char] = &['#', '>', '-', '*', '•'];
+
+#[derive(Debug, Serialize, Clone)]
+pub struct CaptureResult {
+    pub prompt: Prompt,
+    /// The text was already saved, so the existing prompt is returned
+    pub duplicate: bool,
+}
+
+/// Read the clipboard and save it as a prompt, telling every window about
+/// the result
+pub async fn capture_clipboard(app_handle: &AppHandle) -> Result<CaptureResult, CommandError> {
+    let text = app_handle.clipboard().read_text()?;
+    if text.trim().is_empty() {
+        return Err(CommandError::validation(
+            "The clipboard has no text to save",
+        ));
+    }
+
+    let settings = app_handle.state::<SettingsState>().get().capture;
+    let result = app_handle
+        .state::<Database>()
+        .run(move |conn| {
+            let now = chrono::Utc::now().timestamp_millis();
+            let tx = conn.transaction()?;
+            let result = capture(&tx, &text, &settings, now)?;
+            tx.commit()?;
+            Ok(result)
+        })
+        .await?;
+
+    let _ = app_handle.emit("clipboard-captured", result.clone());
+    Ok(result)
+}
+
+/// Save `text` as a clipboard prompt, unless a live prompt already has the
+/// same text
+pub fn capture(
+    conn: &Connection,
+    text: &str,
+    settings: &CaptureSettings,
+    now: i64,
+) -> Result<CaptureResult, DbError> {
+    let prompts = PromptRepository::new(conn);
+    if let Some(prompt) = prompts.find_by_text(text)? {
+        return Ok(CaptureResult {
+            prompt,
+            duplicate: true,
+        });
+    }
+
+    let folders = FolderRepository::new(conn).list()?;
+    let input = CreatePromptInput {
+        text: text.trim().to_string(),
+        header: header_from(text),
+        source: Some(PromptSource::Clipboard.as_str().to_string()),
+        folder_id: suggest_folder(text, settings, &folders),
+        ..Default::default()
+    };
+    Ok(CaptureResult {
+        prompt: prompts.create(input, now)?,
+        duplicate: false,
+    })
+}
+
+/// The first non-blank line without list or heading markers, cut to
+/// `HEADER_CHARS` at a word boundary
+fn header_from(text: &str) -> Option<String> {
+    let line = text
+        .lines()
+        .map(|l| l.trim().trim_start_matches(LINE_MARKERS).trim())
+        .find(|l| !l.is_empty())?;
+    let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+    if line.chars().count() <= HEADER_CHARS {
+        return Some(line);
+    }
+
+    let cut: String = line.chars().take(HEADER_CHARS).collect();
+    let cut = match cut.rfind(' ') {
+        Some(space) if space > 0 => &cut[..space],
+        _ => cut.as_str(),
+    };
+    Some(format!(
+        "{}…",
+        cut.trim_end_matches(|c: char| !c.is_alphanumeric())
+    ))
+}
+
+/// Folder for captured text: the first rule with a keyword in the text,
+/// then a folder whose name is in the text. Rules for deleted folders are
+/// skipped.
+fn suggest_folder(text: &str, settings: &CaptureSettings, folders: &[Folder]) -> Option<String> {
+    let words = words(text);
+    let exists = |id: &str| folders.iter().any(|f| f.id == id);
+
+    settings
+        .rules
+        .iter()
+        .filter(|rule| exists(&rule.folder_id))
+        .find(|rule| rule.keywords.iter().any(|k| contains_phrase(&words, k)))
+        .map(|rule| rule.folder_id.clone())
+        .or_else(|| {
+            folders
+                .iter()
+                .filter(|_| settings.match_folder_names)
+                .find(|f| contains_phrase(&words, &f.name))
+                .map(|f| f.id.clone())
+        })
+}
+
+/// Lowercase alphanumeric words
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Whether `phrase` appears in `words` as whole, consecutive words
+fn contains_phrase(words: &[String], phrase: &str) -> bool {
+    let phrase = self::words(phrase);
+    !phrase.is_empty() && words.windows(phrase.len()).any(|w| w == phrase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::FolderRule;
+
+    fn folder(id: &str, name: &str) -> Folder {
+        Folder {
+            id: id.to_string(),
+            name: name.to_string(),
+            icon: None,
+            color: None,
+            parent_id: None,
+            sort_order: 0,
+            created_at: 0,
+            deleted_at: None,
+        }
+    }
+
+    fn rule(folder_id: &str, keywords: &[&str]) -> FolderRule {
+        FolderRule {
+            folder_id: folder_id.to_string(),
+            keywords: keywords.iter().map(|k| k.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn header_is_the_first_line_without_markers() {
+        assert_eq!(
+            header_from("\n  ## Code review\n\nReview this diff").as_deref(),
+            Some("Code review")
+        );
+        assert_eq!(
+            header_from("- Summarize   the\tarticle").as_deref(),
+            Some("Summarize the article")
+        );
+        assert_eq!(header_from(" \n ## \n"), None);
+    }
+
+    #[test]
+    fn long_header_is_cut_at_a_word() {
+        let text = "Write a detailed product description for an ergonomic office chair, with pros and cons";
+        let header = header_from(text).unwrap();
+        assert_eq!(
+            header,
+            "Write a detailed product description for an ergonomic…"
+        );
+        assert!(header.chars().count() <= HEADER_CHARS + 1);
+    }
+
+    #[test]
+    fn first_matching_rule_picks_the_folder() {
+        let folders = [folder("code", "Code"), folder("mail", "Email")];
+        let settings = CaptureSettings {
+            rules: vec![
+                rule("gone", &["review"]),
+                rule("mail", &["reply to"]),
+                rule("code", &["pull request", "review"]),
+            ],
+            match_folder_names: true,
+        };
+
+        let suggest = |text| suggest_folder(text, &settings, &folders);
+        assert_eq!(suggest("Review this PULL REQUEST").as_deref(), Some("code"));
+        assert_eq!(suggest("Draft a reply to Sam").as_deref(), Some("mail"));
+        assert_eq!(suggest("Reply later to Sam").as_deref(), None);
+        assert_eq!(suggest("Reviewer notes"), None);
+    }
+
+    #[test]
+    fn folder_names_are_the_fallback() {
+        let folders = [folder("mail", "Email"), folder("seo", "SEO Ideas")];
+        let mut settings = CaptureSettings::default();
+
+        let suggest = |text, settings: &CaptureSettings| suggest_folder(text, settings, &folders);
+        assert_eq!(
+            suggest("Ten seo ideas for a bakery", &settings).as_deref(),
+            Some("seo")
+        );
+        assert_eq!(suggest("Emails to answer", &settings), None);
+
+        settings.match_folder_names = false;
+        assert_eq!(suggest("Polish this email", &settings), None);
+    }
+}
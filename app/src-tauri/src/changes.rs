@@ -0,0 +1,242 @@
+//! Change feed for the library.
+//!
+//! Temporary triggers on each pooled connection log the rows a command
+//! touches, and `Database::run` drains that log after every command. Nothing
+//! has to remember to report its changes, whichever path made them.
+
+use crate::db::DbError;
+use crate::journal::EntityKind;
+use rusqlite::Connection;
+use serde::Serialize;
+use std::collections::HashMap;
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeOp {
+    Created,
+    Updated,
+    /// Moved to the trash
+    Trashed,
+    /// Brought back from the trash
+    Restored,
+    /// Gone for good
+    Deleted,
+}
+
+/// One entry of the `library-changed` event
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct LibraryChange {
+    pub entity: EntityKind,
+    pub id: String,
+    pub op: ChangeOp,
+}
+
+/// Log table and triggers, created per connection. TEMP objects live and die
+/// with the connection, and are rolled back along with the command's writes.
+const CHANGE_LOG: &str = r#"
+    CREATE TEMP TABLE IF NOT EXISTS library_changes (
+        seq INTEGER PRIMARY KEY AUTOINCREMENT,
+        entity TEXT NOT NULL,
+        id TEXT NOT NULL,
+        op TEXT NOT NULL
+    );
+
+    CREATE TEMP TRIGGER IF NOT EXISTS library_prompts_insert AFTER INSERT ON main.prompts BEGIN
+        INSERT INTO library_changes (entity, id, op) VALUES ('prompt', NEW.id, 'created');
+    END;
+    CREATE TEMP TRIGGER IF NOT EXISTS library_prompts_update AFTER UPDATE ON main.prompts BEGIN
+        INSERT INTO library_changes (entity, id, op) VALUES ('prompt', NEW.id, CASE
+            WHEN OLD.deleted_at IS NULL AND NEW.deleted_at IS NOT NULL THEN 'trashed'
+            WHEN OLD.deleted_at IS NOT NULL AND NEW.deleted_at IS NULL THEN 'restored'
+            ELSE 'updated' END);
+    END;
+    CREATE TEMP TRIGGER IF NOT EXISTS library_prompts_delete AFTER DELETE ON main.prompts BEGIN
+        INSERT INTO library_changes (entity, id, op) VALUES ('prompt', OLD.id, 'deleted');
+    END;
+
+    CREATE TEMP TRIGGER IF NOT EXISTS library_prompt_tags_insert AFTER INSERT ON main.prompt_tags BEGIN
+        INSERT INTO library_changes (entity, id, op) VALUES ('prompt', NEW.prompt_id, 'updated');
+    END;
+    CREATE TEMP TRIGGER IF NOT EXISTS library_prompt_tags_delete AFTER DELETE ON main.prompt_tags BEGIN
+        INSERT INTO library_changes (entity, id, op) VALUES ('prompt', OLD.prompt_id, 'updated');
+    END;
+
+    CREATE TEMP TRIGGER IF NOT EXISTS library_folders_insert AFTER INSERT ON main.folders BEGIN
+        INSERT INTO library_changes (entity, id, op) VALUES ('folder', NEW.id, 'created');
+    END;
+    CREATE TEMP TRIGGER IF NOT EXISTS library_folders_update AFTER UPDATE ON main.folders BEGIN
+        INSERT INTO library_changes (entity, id, op) VALUES ('folder', NEW.id, CASE
+            WHEN OLD.deleted_at IS NULL AND NEW.deleted_at IS NOT NULL THEN 'trashed'
+            WHEN OLD.deleted_at IS NOT NULL AND NEW.deleted_at IS NULL THEN 'restored'
+            ELSE 'updated' END);
+    END;
+    CREATE TEMP TRIGGER IF NOT EXISTS library_folders_delete AFTER DELETE ON main.folders BEGIN
+        INSERT INTO library_changes (entity, id, op) VALUES ('folder', OLD.id, 'deleted');
+    END;
+
+    CREATE TEMP TRIGGER IF NOT EXISTS library_smart_folders_insert AFTER INSERT ON main.smart_folders BEGIN
+        INSERT INTO library_changes (entity, id, op) VALUES ('smart_folder', NEW.id, 'created');
+    END;
+    CREATE TEMP TRIGGER IF NOT EXISTS library_smart_folders_update AFTER UPDATE ON main.smart_folders BEGIN
+        INSERT INTO library_changes (entity, id, op) VALUES ('smart_folder', NEW.id, 'updated');
+    END;
+    CREATE TEMP TRIGGER IF NOT EXISTS library_smart_folders_delete AFTER DELETE ON main.smart_folders BEGIN
+        INSERT INTO library_changes (entity, id, op) VALUES ('smart_folder', OLD.id, 'deleted');
+    END;
+"#;
+
+/// Start logging changes on `conn`. Needs the library schema to exist.
+pub fn install(conn: &Connection) -> Result<(), DbError> {
+    conn.execute_batch(CHANGE_LOG)?;
+    Ok(())
+}
+
+/// Take everything logged on `conn` so far, one entry per entity
+pub fn drain(conn: &Connection) -> Result<Vec<LibraryChange>, DbError> {
+    let mut stmt = conn.prepare("SELECT entity, id, op FROM temp.library_changes ORDER BY seq")?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    conn.execute("DELETE FROM temp.library_changes", [])?;
+
+    let mut changes: Vec<Option<LibraryChange>> = Vec::new();
+    let mut index: HashMap<(EntityKind, String), usize> = HashMap::new();
+    for (entity, id, op) in rows {
+        let (Some(entity), Some(op)) = (parse_entity(&entity), parse_op(&op)) else {
+            continue;
+        };
+        match index.get(&(entity, id.clone())) {
+            Some(&i) => {
+                // A change after two that cancelled out starts afresh
+                let merged = match &changes[i] {
+                    Some(prev) => merge(prev.op, op),
+                    None => Some(op),
+                };
+                changes[i] = merged.map(|op| LibraryChange {
+                    entity,
+                    id: id.clone(),
+                    op,
+                });
+            }
+            None => {
+                index.insert((entity, id.clone()), changes.len());
+                changes.push(Some(LibraryChange { entity, id, op }));
+            }
+        }
+    }
+    Ok(changes.into_iter().flatten().collect())
+}
+
+/// Net effect of two changes to the same entity; None when they cancel out
+fn merge(prev: ChangeOp, next: ChangeOp) -> Option<ChangeOp> {
+    use ChangeOp::*;
+    match (prev, next) {
+        (Created, Deleted) => None,
+        (Created, _) => Some(Created),
+        (_, Deleted) => Some(Deleted),
+        (Deleted, _) | (Trashed, Restored) | (Restored, Trashed) => Some(Updated),
+        (Trashed | Restored, Updated) => Some(prev),
+        (_, next) => Some(next),
+    }
+}
+
+fn parse_entity(entity: &str) -> Option<EntityKind> {
+    match entity {
+        "prompt" => Some(EntityKind::Prompt),
+        "folder" => Some(EntityKind::Folder),
+        "smart_folder" => Some(EntityKind::SmartFolder),
+        _ => None,
+    }
+}
+
+fn parse_op(op: &str) -> Option<ChangeOp> {
+    match op {
+        "created" => Some(ChangeOp::Created),
+        "updated" => Some(ChangeOp::Updated),
+        "trashed" => Some(ChangeOp::Trashed),
+        "restored" => Some(ChangeOp::Restored),
+        "deleted" => Some(ChangeOp::Deleted),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use ChangeOp::*;
+
+    fn ops(conn: &Connection) -> Vec<(String, ChangeOp)> {
+        drain(conn)
+            .unwrap()
+            .into_iter()
+            .map(|change| (change.id, change.op))
+            .collect()
+    }
+
+    fn insert(conn: &Connection, id: &str) {
+        conn.execute(
+            "INSERT INTO prompts (id, text, created_at, updated_at) VALUES (?1, 'Text', 1, 1)",
+            [id],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn merge_keeps_the_net_effect() {
+        assert_eq!(merge(Created, Updated), Some(Created));
+        assert_eq!(merge(Created, Trashed), Some(Created));
+        assert_eq!(merge(Created, Deleted), None);
+        assert_eq!(merge(Updated, Deleted), Some(Deleted));
+        assert_eq!(merge(Trashed, Deleted), Some(Deleted));
+        assert_eq!(merge(Deleted, Created), Some(Updated));
+        assert_eq!(merge(Trashed, Restored), Some(Updated));
+        assert_eq!(merge(Restored, Trashed), Some(Updated));
+        assert_eq!(merge(Trashed, Updated), Some(Trashed));
+        assert_eq!(merge(Restored, Updated), Some(Restored));
+        assert_eq!(merge(Updated, Trashed), Some(Trashed));
+        assert_eq!(merge(Updated, Updated), Some(Updated));
+    }
+
+    #[test]
+    fn drain_reports_one_change_per_entity() {
+        let conn = db::open_in_memory();
+        install(&conn).unwrap();
+        insert(&conn, "a");
+        insert(&conn, "b");
+        conn.execute("UPDATE prompts SET text = 'New' WHERE id = 'a'", [])
+            .unwrap();
+        conn.execute("UPDATE prompts SET deleted_at = 2 WHERE id = 'b'", [])
+            .unwrap();
+        assert_eq!(ops(&conn), [("a".into(), Created), ("b".into(), Created)]);
+
+        conn.execute("UPDATE prompts SET deleted_at = 3 WHERE id = 'a'", [])
+            .unwrap();
+        conn.execute("UPDATE prompts SET text = 'New' WHERE id = 'a'", [])
+            .unwrap();
+        assert_eq!(ops(&conn), [("a".into(), Trashed)]);
+        assert!(ops(&conn).is_empty());
+    }
+
+    #[test]
+    fn drain_drops_a_create_and_delete_but_not_what_follows() {
+        let conn = db::open_in_memory();
+        install(&conn).unwrap();
+        insert(&conn, "a");
+        conn.execute("DELETE FROM prompts WHERE id = 'a'", [])
+            .unwrap();
+        assert!(ops(&conn).is_empty());
+
+        insert(&conn, "a");
+        conn.execute("DELETE FROM prompts WHERE id = 'a'", [])
+            .unwrap();
+        insert(&conn, "a");
+        assert_eq!(ops(&conn), [("a".into(), Created)]);
+    }
+}
@@ -0,0 +1,654 @@
+//! The `promptpack-cli` command-line interface.
+//!
+//! Works on the same `promptpack.db` as the desktop app. Connections use WAL
+//! and a busy timeout, and writes take the lock up front, so it is safe to
+//! run while the app is open. The CLI never migrates the schema; a library
+//! from an older release has to be opened in the app once first.
+
+use crate::backup::{self, BackupKind};
+use crate::crypto;
+use crate::db::{self, DbError};
+use crate::error::{CommandError, ErrorCode};
+use crate::mcp::{self, McpServer};
+use crate::repository::{
+    self, CreatePromptInput, Prompt, PromptFilter, PromptPageQuery, PromptRepository,
+    UpdatePromptInput,
+};
+use crate::revisions;
+use crate::settings::SettingsState;
+use crate::template;
+use crate::vault::{self, VaultError};
+use clap::{Parser, Subcommand};
+use rusqlite::{Connection, TransactionBehavior};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{ExitCode, Stdio};
+
+/// Bundle identifier from `tauri.conf.json`, which names the app data directory
+const APP_IDENTIFIER: &str = "com.promptpack.desktop";
+
+/// Characters of a prompt shown when it has no header
+const TITLE_CHARS: usize = 60;
+
+#[derive(Parser, Clone)]
+#[command(
+    name = "promptpack-cli",
+    version,
+    about = "Manage your PromptPack library"
+)]
+struct Cli {
+    /// Library file to use instead of the desktop app's
+    #[arg(long, global = true, env = "PROMPTPACK_DB")]
+    db: Option<PathBuf>,
+
+    /// Vault password, when the library is encrypted
+    #[arg(
+        long,
+        global = true,
+        env = "PROMPTPACK_PASSWORD",
+        hide_env_values = true
+    )]
+    password: Option<String>,
+
+    /// Print JSON instead of text
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Clone)]
+enum Command {
+    /// List prompts, newest first
+    List {
+        /// Only prompts in this folder
+        #[arg(long)]
+        folder: Option<String>,
+        /// With --folder, include its subfolders
+        #[arg(long)]
+        subfolders: bool,
+        #[arg(long)]
+        favorites: bool,
+        /// Only prompts carrying this tag; repeat for several
+        #[arg(long)]
+        tag: Vec<String>,
+        #[arg(long)]
+        source: Option<String>,
+        #[arg(long)]
+        limit: Option<u32>,
+        /// `next_cursor` from a previous page
+        #[arg(long)]
+        cursor: Option<String>,
+    },
+    /// Full-text search over text and headers
+    Search { query: String },
+    /// Show one prompt
+    Show { id: String },
+    /// Add a prompt. The text is read from stdin when omitted or "-".
+    Add {
+        text: Option<String>,
+        #[arg(long)]
+        header: Option<String>,
+        #[arg(long)]
+        folder: Option<String>,
+        #[arg(long)]
+        source: Option<String>,
+        #[arg(long)]
+        url: Option<String>,
+    },
+    /// Change fields of a prompt. Pass "-" as --text to read it from stdin.
+    Edit {
+        id: String,
+        #[arg(long)]
+        text: Option<String>,
+        #[arg(long)]
+        header: Option<String>,
+        #[arg(long)]
+        folder: Option<String>,
+        #[arg(long)]
+        source: Option<String>,
+        #[arg(long)]
+        url: Option<String>,
+        #[arg(long)]
+        favorite: Option<bool>,
+    },
+    /// Print a prompt with its {Variables} filled in
+    Render {
+        id: String,
+        /// NAME=VALUE; repeat for each variable
+        #[arg(short = 'v', long = "var", value_parser = parse_var)]
+        vars: Vec<(String, String)>,
+    },
+    /// Import a .pmtpk pack into the library
+    Import {
+        file: PathBuf,
+        #[arg(long)]
+        pack_password: Option<String>,
+    },
+    /// Export prompts to a .pmtpk pack
+    Export {
+        /// Prompts to export; the whole library when none are given
+        ids: Vec<String>,
+        #[arg(short, long)]
+        output: PathBuf,
+        /// Encrypt the pack with this password
+        #[arg(long)]
+        pack_password: Option<String>,
+    },
+    /// Describe a .pmtpk pack without importing it
+    Inspect {
+        file: PathBuf,
+        #[arg(long)]
+        pack_password: Option<String>,
+    },
+    /// Copy a rendered prompt to the clipboard and count it as used
+    Copy {
+        id: String,
+        /// NAME=VALUE; repeat for each variable
+        #[arg(short = 'v', long = "var", value_parser = parse_var)]
+        vars: Vec<(String, String)>,
+    },
+    /// Serve prompts to AI clients over the Model Context Protocol, on stdio
+    /// unless --http is given
+    Mcp {
+        /// Only prompts in this folder and its subfolders
+        #[arg(long)]
+        folder: Option<String>,
+        /// Only prompts carrying this tag; repeat for several
+        #[arg(long)]
+        tag: Vec<String>,
+        /// Listen on 127.0.0.1:PORT instead of stdio
+        #[arg(long, value_name = "PORT")]
+        http: Option<u16>,
+        /// Token HTTP clients must send as `Authorization: Bearer <token>`
+        #[arg(
+            long,
+            env = "PROMPTPACK_MCP_TOKEN",
+            hide_env_values = true,
+            requires = "http"
+        )]
+        token: Option<String>,
+    },
+}
+
+fn parse_var(arg: &str) -> Result<(String, String), String> {
+    arg.split_once('=')
+        .map(|(name, value)| (name.trim().to_string(), value.to_string()))
+        .filter(|(name, _)| !name.is_empty())
+        .ok_or_else(|| format!("expected NAME=VALUE, got \"{arg}\""))
+}
+
+/// Parse the arguments, run the command and report any error on stderr
+pub fn run() -> ExitCode {
+    let cli = Cli::parse();
+    match execute(&cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            if cli.json {
+                eprintln!("{}", json!({ "error": e }));
+            } else {
+                eprintln!("error: {e}");
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// The library file and what is needed to open it
+struct Library {
+    path: PathBuf,
+    key: Option<String>,
+}
+
+impl Library {
+    fn locate(cli: &Cli) -> Result<Self, CommandError> {
+        let path = match &cli.db {
+            Some(path) => path.clone(),
+            None => dirs::data_dir()
+                .ok_or_else(|| CommandError::internal("No data directory on this system"))?
+                .join(APP_IDENTIFIER)
+                .join("promptpack.db"),
+        };
+        if !path.is_file() {
+            return Err(CommandError::new(
+                ErrorCode::NotFound,
+                format!("No library at {}", path.display()),
+            ));
+        }
+
+        let key = cli.password.clone().filter(|p| !p.is_empty());
+        if key.is_none() && vault::is_encrypted(&path)? {
+            return Err(CommandError::from(VaultError::Locked)
+                .with_details(json!({ "hint": "pass --password or set PROMPTPACK_PASSWORD" })));
+        }
+        Ok(Self { path, key })
+    }
+
+    fn open(&self) -> Result<Connection, CommandError> {
+        if let Some(key) = &self.key {
+            vault::verify_key(&self.path, key)?;
+        }
+        let conn = db::open_connection(&self.path, self.key.as_deref())?;
+        if !db::is_current(&conn)? {
+            return Err(CommandError::new(
+                ErrorCode::DatabaseNotReady,
+                "The library is from a different release; open it in the desktop app first",
+            ));
+        }
+        Ok(conn)
+    }
+
+    /// Settings live next to the library, as the app keeps them
+    fn settings(&self) -> SettingsState {
+        SettingsState::load(self.path.with_file_name("settings.json"))
+    }
+
+    fn backup_dir(&self) -> PathBuf {
+        self.path.with_file_name("backups")
+    }
+}
+
+/// Run `f` in a transaction that holds the write lock from the start, so it
+/// waits for the app's writer instead of failing halfway through
+fn write<T>(
+    conn: &mut Connection,
+    f: impl FnOnce(&Connection) -> Result<T, DbError>,
+) -> Result<T, CommandError> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let result = f(&tx)?;
+    tx.commit()?;
+    Ok(result)
+}
+
+fn now() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+fn execute(cli: &Cli) -> Result<(), CommandError> {
+    let out = Output { json: cli.json };
+
+    // Inspecting a pack doesn't need the library
+    if let Command::Inspect {
+        file,
+        pack_password,
+    } = &cli.command
+    {
+        return inspect(&out, file, pack_password.as_deref());
+    }
+
+    // The server opens the library on first use, so a locked vault is
+    // reported to the client instead of stopping the server
+    if let Command::Mcp {
+        folder,
+        tag,
+        http,
+        token,
+    } = &cli.command
+    {
+        let scope = PromptFilter {
+            folder_id: folder.clone(),
+            include_subfolders: true,
+            tags: tag.clone(),
+            ..Default::default()
+        };
+        let cli = cli.clone();
+        let server = McpServer::new(move || Library::locate(&cli)?.open(), scope);
+        return match http {
+            Some(port) => mcp::serve_http(server, *port, token.clone()),
+            None => mcp::serve_stdio(&server),
+        };
+    }
+
+    let library = Library::locate(cli)?;
+    let mut conn = library.open()?;
+
+    match &cli.command {
+        Command::List {
+            folder,
+            subfolders,
+            favorites,
+            tag,
+            source,
+            limit,
+            cursor,
+        } => {
+            let query = PromptPageQuery {
+                filter: PromptFilter {
+                    folder_id: folder.clone(),
+                    include_subfolders: *subfolders,
+                    favorite: favorites.then_some(true),
+                    tags: tag.clone(),
+                    source: source.clone(),
+                    ..Default::default()
+                },
+                limit: *limit,
+                cursor: cursor.clone(),
+                ..Default::default()
+            };
+            let page = PromptRepository::new(&conn).page(&query, now())?;
+            out.print(&page, |page| {
+                let mut text = prompt_lines(&page.prompts);
+                if page.next_cursor.is_some() {
+                    text.push_str(&format!(
+                        "({} of {}; pass --json for the next cursor)\n",
+                        page.prompts.len(),
+                        page.total
+                    ));
+                }
+                text
+            })
+        }
+        Command::Search { query } => {
+            let prompts = PromptRepository::new(&conn).search(query)?;
+            out.print(&prompts, |prompts| prompt_lines(prompts))
+        }
+        Command::Show { id } => {
+            let prompt = find(&conn, id)?;
+            out.print(&prompt, describe)
+        }
+        Command::Add {
+            text,
+            header,
+            folder,
+            source,
+            url,
+        } => {
+            let text = match text.as_deref() {
+                None | Some("-") => read_stdin()?,
+                Some(text) => text.to_string(),
+            };
+            let input = CreatePromptInput {
+                text,
+                header: header.clone(),
+                source: source.clone(),
+                url: url.clone(),
+                folder_id: folder.clone(),
+            };
+            let prompt = write(&mut conn, |conn| {
+                PromptRepository::new(conn).create(input, now())
+            })?;
+            out.print(&prompt, |p| format!("{}\n", p.id))
+        }
+        Command::Edit {
+            id,
+            text,
+            header,
+            folder,
+            source,
+            url,
+            favorite,
+        } => {
+            let text = match text.as_deref() {
+                Some("-") => Some(read_stdin()?),
+                text => text.map(str::to_string),
+            };
+            let input = UpdatePromptInput {
+                text,
+                header: header.clone(),
+                source: source.clone(),
+                url: url.clone(),
+                folder_id: folder.clone(),
+                is_favorite: *favorite,
+            };
+            let history = library.settings().get().history;
+            let prompt = write(&mut conn, |conn| {
+                let now = now();
+                let prompt = PromptRepository::new(conn).update(id, input, now)?;
+                revisions::compact(conn, Some(id), &history, now)?;
+                Ok(prompt)
+            })?;
+            out.print(&prompt, describe)
+        }
+        Command::Render { id, vars } => {
+            let rendered = render(&conn, id, vars)?;
+            if !cli.json {
+                for name in &rendered.missing {
+                    eprintln!("warning: no value for {{{name}}}");
+                }
+            }
+            out.print(&rendered, |r| format!("{}\n", r.text))
+        }
+        Command::Copy { id, vars } => {
+            let rendered = render(&conn, id, vars)?;
+            copy_to_clipboard(&rendered.text)?;
+            write(&mut conn, |conn| {
+                PromptRepository::new(conn).record_use(id, now())
+            })?;
+            out.print(&rendered, |r| match r.missing.len() {
+                0 => "Copied\n".to_string(),
+                _ => format!("Copied, with {} unfilled\n", r.missing.join(", ")),
+            })
+        }
+        Command::Import {
+            file,
+            pack_password,
+        } => {
+            let data = std::fs::read(file)?;
+            let json = crypto::decode_pack(&data, pack_password.as_deref())?;
+            let entries = repository::pack_entries(&json)?;
+
+            let settings = library.settings().get();
+            let keep = settings.backup.retention_for(BackupKind::PreImport);
+            backup::create_backup(
+                &conn,
+                &library.backup_dir(),
+                BackupKind::PreImport,
+                library.key.as_deref(),
+            )?;
+            backup::prune_backups(&library.backup_dir(), BackupKind::PreImport, keep)?;
+
+            let imported = write(&mut conn, |conn| {
+                PromptRepository::new(conn).import(&entries, now())
+            })?;
+            let result = json!({
+                "count": imported.prompts.len(),
+                "prompts": imported.prompts,
+                "errors": imported.errors,
+            });
+            out.print(&result, |_| {
+                let mut text = format!("Imported {} prompts\n", imported.prompts.len());
+                for error in &imported.errors {
+                    text += &format!("Skipped {}: {}\n", error.location, error.message);
+                }
+                text
+            })
+        }
+        Command::Export {
+            ids,
+            output,
+            pack_password,
+        } => {
+            let ids = (!ids.is_empty()).then_some(ids.as_slice());
+            let json = PromptRepository::new(&conn).export(ids, now())?;
+            let count = repository::pack_entries(&json)?.len();
+            let data = crypto::encode_pack(&json, pack_password.as_deref())?;
+            std::fs::write(output, data)?;
+
+            let result = json!({ "count": count, "path": output });
+            out.print(&result, |_| {
+                format!("Exported {count} prompts to {}\n", output.display())
+            })
+        }
+        Command::Inspect { .. } | Command::Mcp { .. } => {
+            unreachable!("handled before opening the library")
+        }
+    }
+}
+
+/// How results reach stdout
+struct Output {
+    json: bool,
+}
+
+impl Output {
+    fn print<T: Serialize>(
+        &self,
+        value: &T,
+        text: impl FnOnce(&T) -> String,
+    ) -> Result<(), CommandError> {
+        let mut stdout = std::io::stdout().lock();
+        if self.json {
+            serde_json::to_writer_pretty(&mut stdout, value)?;
+            writeln!(stdout)?;
+        } else {
+            write!(stdout, "{}", text(value))?;
+        }
+        Ok(())
+    }
+}
+
+fn find(conn: &Connection, id: &str) -> Result<Prompt, CommandError> {
+    PromptRepository::new(conn)
+        .get(id)?
+        .ok_or_else(|| DbError::NotFound("Prompt").into())
+}
+
+fn read_stdin() -> Result<String, CommandError> {
+    let mut text = String::new();
+    std::io::stdin().read_to_string(&mut text)?;
+    Ok(text.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Header, or the start of the first line when there is none
+pub(crate) fn title(header: Option<&str>, text: &str) -> String {
+    repository::title(header, text, TITLE_CHARS)
+}
+
+/// One `id  title` line per prompt
+fn prompt_lines(prompts: &[Prompt]) -> String {
+    prompts
+        .iter()
+        .map(|p| {
+            let star = if p.is_favorite { "★ " } else { "" };
+            format!("{}  {star}{}\n", p.id, title(p.header.as_deref(), &p.text))
+        })
+        .collect()
+}
+
+fn describe(prompt: &Prompt) -> String {
+    let mut text = format!("{}\n", title(prompt.header.as_deref(), &prompt.text));
+    text.push_str(&format!("id:      {}\n", prompt.id));
+    text.push_str(&format!("source:  {}\n", prompt.source));
+    if let Some(url) = &prompt.url {
+        text.push_str(&format!("url:     {url}\n"));
+    }
+    if let Some(folder_id) = &prompt.folder_id {
+        text.push_str(&format!("folder:  {folder_id}\n"));
+    }
+    text.push_str(&format!("used:    {} times\n", prompt.use_count));
+    if prompt.deleted_at.is_some() {
+        text.push_str("status:  in trash\n");
+    }
+    text.push('\n');
+    text.push_str(&prompt.text);
+    text.push('\n');
+    text
+}
+
+#[derive(Serialize)]
+struct Rendered {
+    id: String,
+    text: String,
+    /// Variables left as placeholders for lack of a value
+    missing: Vec<String>,
+}
+
+fn render(
+    conn: &Connection,
+    id: &str,
+    vars: &[(String, String)],
+) -> Result<Rendered, CommandError> {
+    let prompt = find(conn, id)?;
+    let values: HashMap<String, String> = vars.iter().cloned().collect();
+    Ok(Rendered {
+        text: template::render(&prompt.text, &values),
+        missing: template::missing(&prompt.text, &values),
+        id: prompt.id,
+    })
+}
+
+fn inspect(out: &Output, file: &Path, password: Option<&str>) -> Result<(), CommandError> {
+    let data = std::fs::read(file)?;
+    let encrypted = crypto::is_encrypted(&data)?;
+    if encrypted && password.is_none() {
+        let result = json!({ "encrypted": true });
+        return out.print(&result, |_| {
+            "Encrypted pack; pass --pack-password to list its prompts\n".to_string()
+        });
+    }
+
+    let json = crypto::decode_pack(&data, password)?;
+    let pack: serde_json::Value = serde_json::from_str(&json)?;
+    let entries = repository::pack_entries(&json)?;
+    let headers: Vec<String> = entries
+        .iter()
+        .map(|entry| {
+            let text = entry.get("text").and_then(|t| t.as_str()).unwrap_or("");
+            let header = entry.get("header").and_then(|h| h.as_str());
+            title(header, text)
+        })
+        .collect();
+
+    let result = json!({
+        "encrypted": encrypted,
+        "version": pack.get("version"),
+        "exported_at": pack.get("exportedAt"),
+        "count": entries.len(),
+        "prompts": headers,
+    });
+    out.print(&result, |_| {
+        let mut text = format!(
+            "{} prompts{}\n",
+            entries.len(),
+            if encrypted { ", encrypted" } else { "" }
+        );
+        for header in &headers {
+            text.push_str(&format!("  {header}\n"));
+        }
+        text
+    })
+}
+
+/// Hand `text` to the platform's clipboard tool
+fn copy_to_clipboard(text: &str) -> Result<(), CommandError> {
+    let tools: &[(&str, &[&str])] = if cfg!(target_os = "macos") {
+        &[("pbcopy", &[])]
+    } else if cfg!(windows) {
+        &[("clip", &[])]
+    } else {
+        &[
+            ("wl-copy", &[]),
+            ("xclip", &["-selection", "clipboard"]),
+            ("xsel", &["--clipboard", "--input"]),
+        ]
+    };
+
+    for (program, args) in tools {
+        let mut child = match std::process::Command::new(program)
+            .args(*args)
+            .stdin(Stdio::piped())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes())?;
+        }
+        let status = child.wait()?;
+        if !status.success() {
+            return Err(CommandError::internal(format!(
+                "{program} failed ({status})"
+            )));
+        }
+        return Ok(());
+    }
+    Err(CommandError::internal(
+        "No clipboard tool found; install wl-copy, xclip or xsel",
+    ))
+}
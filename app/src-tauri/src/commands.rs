@@ -1,10 +1,38 @@
 use crate::auth::{self, AuthSession};
+use crate::backup::{self, BackupDiff, BackupInfo, BackupKind};
+use crate::bridge::{self, BridgeStatus};
+use crate::bulk::{self, BulkAction, BulkResult};
+use crate::capture::{self, CaptureResult};
 use crate::crypto;
-use crate::db;
+use crate::db::{self, Database, DbError, DbStatus};
+use crate::deep_link::{DeepLinkAction, DeepLinkState};
+use crate::error::CommandError;
+use crate::filter::{PromptFilter, PromptPageQuery};
+use crate::folders::{DeleteFolderMode, DeleteFolderResult};
+use crate::interchange::{self, CsvOptions};
+use crate::journal::{self, AppliedOperation, Direction, EntityKind, UndoState};
+use crate::markdown;
+use crate::repository::{
+    self, CreateFolderInput, CreatePromptInput, Folder, FolderNode, FolderRepository,
+    ImportReport, Prompt, PromptPage, PromptRepository, RecordError, RecordSet,
+    UpdateFolderInput, UpdatePromptInput,
+};
+use crate::revisions::{self, DiffMode, PromptRevision, RevisionDiff};
+use crate::settings::{
+    BackupSettings, CaptureSettings, HistorySettings, SettingsState, ShortcutSettings,
+    TrashSettings,
+};
+use crate::shortcuts::{self, ShortcutManager, ShortcutStatus};
+use crate::smart_folders::{self, SmartFolder, SmartFolderInput, UpdateSmartFolderInput};
+use crate::template;
+use crate::trash::{self, PurgeResult, Tombstone};
+use crate::vault::{self, VaultError, VaultState};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::Path;
 use std::sync::Mutex;
-use tauri::{AppHandle, Manager, State};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tauri_plugin_clipboard_manager::ClipboardExt;
 
 // ============================================================================
 // CENTRALIZED CONFIGURATION
@@ -20,60 +48,6 @@ const WEB_APP_URL: &str = "https://pmtpk.com";
 /// Desktop auth page URL - where the OAuth popup opens for sign-in
 const DESKTOP_AUTH_URL: &str = const_format::concatcp!(WEB_APP_URL, "/desktop-auth");
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Prompt {
-    pub id: String,
-    pub text: String,
-    pub header: Option<String>,
-    pub source: String,
-    pub url: Option<String>,
-    pub folder_id: Option<String>,
-    pub is_favorite: bool,
-    pub use_count: i32,
-    pub created_at: i64,
-    pub updated_at: i64,
-    pub sync_status: String,
-    pub cloud_id: Option<String>,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Folder {
-    pub id: String,
-    pub name: String,
-    pub icon: Option<String>,
-    pub color: Option<String>,
-    pub parent_id: Option<String>,
-    pub sort_order: i32,
-    pub created_at: i64,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct CreatePromptInput {
-    pub text: String,
-    pub header: Option<String>,
-    pub source: Option<String>,
-    pub url: Option<String>,
-    pub folder_id: Option<String>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct UpdatePromptInput {
-    pub text: Option<String>,
-    pub header: Option<String>,
-    pub source: Option<String>,
-    pub url: Option<String>,
-    pub folder_id: Option<String>,
-    pub is_favorite: Option<bool>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct CreateFolderInput {
-    pub name: String,
-    pub icon: Option<String>,
-    pub color: Option<String>,
-    pub parent_id: Option<String>,
-}
-
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ExportPackInput {
     pub prompt_ids: Vec<String>,
@@ -84,439 +58,1216 @@ pub struct ExportPackInput {
 pub struct ImportResult {
     pub prompts: Vec<Prompt>,
     pub count: usize,
+    /// Pack entries that were skipped as invalid
+    pub errors: Vec<RecordError>,
 }
 
 // ============ Prompt Commands ============
 
+/// List prompts one page at a time. Without a query this returns the newest
+/// prompts first.
 #[tauri::command]
-pub fn get_prompts(app_handle: AppHandle) -> Result<Vec<Prompt>, String> {
-    let conn = db::get_connection(&app_handle).map_err(|e| e.to_string())?;
-
-    let mut stmt = conn
-        .prepare(
-            "SELECT id, text, header, source, url, folder_id, is_favorite, use_count,
-                    created_at, updated_at, sync_status, cloud_id
-             FROM prompts ORDER BY created_at DESC",
-        )
-        .map_err(|e| e.to_string())?;
-
-    let prompts = stmt
-        .query_map([], |row| {
-            Ok(Prompt {
-                id: row.get(0)?,
-                text: row.get(1)?,
-                header: row.get(2)?,
-                source: row.get(3)?,
-                url: row.get(4)?,
-                folder_id: row.get(5)?,
-                is_favorite: row.get::<_, i32>(6)? != 0,
-                use_count: row.get(7)?,
-                created_at: row.get(8)?,
-                updated_at: row.get(9)?,
-                sync_status: row.get(10)?,
-                cloud_id: row.get(11)?,
-            })
+pub async fn get_prompts(
+    database: State<'_, Database>,
+    query: Option<PromptPageQuery>,
+) -> Result<PromptPage, CommandError> {
+    let query = query.unwrap_or_default();
+
+    database
+        .run(move |conn| {
+            PromptRepository::new(conn).page(&query, chrono::Utc::now().timestamp_millis())
         })
-        .map_err(|e| e.to_string())?
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|e| e.to_string())?;
-
-    Ok(prompts)
-}
-
-#[tauri::command]
-pub fn get_prompt(app_handle: AppHandle, id: String) -> Result<Option<Prompt>, String> {
-    let conn = db::get_connection(&app_handle).map_err(|e| e.to_string())?;
-
-    let mut stmt = conn
-        .prepare(
-            "SELECT id, text, header, source, url, folder_id, is_favorite, use_count,
-                    created_at, updated_at, sync_status, cloud_id
-             FROM prompts WHERE id = ?",
-        )
-        .map_err(|e| e.to_string())?;
-
-    let prompt = stmt
-        .query_row([&id], |row| {
-            Ok(Prompt {
-                id: row.get(0)?,
-                text: row.get(1)?,
-                header: row.get(2)?,
-                source: row.get(3)?,
-                url: row.get(4)?,
-                folder_id: row.get(5)?,
-                is_favorite: row.get::<_, i32>(6)? != 0,
-                use_count: row.get(7)?,
-                created_at: row.get(8)?,
-                updated_at: row.get(9)?,
-                sync_status: row.get(10)?,
-                cloud_id: row.get(11)?,
-            })
+        .await
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub async fn search_prompts(
+    database: State<'_, Database>,
+    query: String,
+) -> Result<Vec<Prompt>, CommandError> {
+    database
+        .run(move |conn| PromptRepository::new(conn).search(&query))
+        .await
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub async fn get_prompt(
+    database: State<'_, Database>,
+    id: String,
+) -> Result<Option<Prompt>, CommandError> {
+    database
+        .run(move |conn| PromptRepository::new(conn).get(&id))
+        .await
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub async fn create_prompt(
+    database: State<'_, Database>,
+    input: CreatePromptInput,
+) -> Result<Prompt, CommandError> {
+    database
+        .run(move |conn| {
+            let now = chrono::Utc::now().timestamp_millis();
+            let tx = conn.transaction()?;
+            let prompt = PromptRepository::new(&tx).create(input, now)?;
+            tx.commit()?;
+            Ok(prompt)
         })
-        .ok();
-
-    Ok(prompt)
-}
-
-#[tauri::command]
-pub fn create_prompt(app_handle: AppHandle, input: CreatePromptInput) -> Result<Prompt, String> {
-    let conn = db::get_connection(&app_handle).map_err(|e| e.to_string())?;
-
-    let id = uuid::Uuid::new_v4().to_string();
-    let now = chrono::Utc::now().timestamp_millis();
-    let source = input.source.unwrap_or_else(|| "manual".to_string());
-
-    conn.execute(
-        "INSERT INTO prompts (id, text, header, source, url, folder_id, is_favorite, use_count, created_at, updated_at, sync_status)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, 0, 0, ?7, ?7, 'local-only')",
-        rusqlite::params![id, input.text, input.header, source, input.url, input.folder_id, now],
-    )
-    .map_err(|e| e.to_string())?;
-
-    Ok(Prompt {
-        id,
-        text: input.text,
-        header: input.header,
-        source,
-        url: input.url,
-        folder_id: input.folder_id,
-        is_favorite: false,
-        use_count: 0,
-        created_at: now,
-        updated_at: now,
-        sync_status: "local-only".to_string(),
-        cloud_id: None,
-    })
+        .await
+        .map_err(CommandError::from)
 }
 
 #[tauri::command]
-pub fn update_prompt(
-    app_handle: AppHandle,
+pub async fn update_prompt(
+    database: State<'_, Database>,
+    settings: State<'_, SettingsState>,
     id: String,
     input: UpdatePromptInput,
-) -> Result<Prompt, String> {
-    let conn = db::get_connection(&app_handle).map_err(|e| e.to_string())?;
-    let now = chrono::Utc::now().timestamp_millis();
+) -> Result<Prompt, CommandError> {
+    let history = settings.get().history;
+
+    database
+        .run(move |conn| {
+            let now = chrono::Utc::now().timestamp_millis();
+            let tx = conn.transaction()?;
+            let prompt = PromptRepository::new(&tx).update(&id, input, now)?;
+            revisions::compact(&tx, Some(&id), &history, now)?;
+            tx.commit()?;
+            Ok(prompt)
+        })
+        .await
+        .map_err(CommandError::from)
+}
 
-    // Build dynamic update query
-    let mut updates = vec!["updated_at = ?1".to_string()];
-    let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![Box::new(now)];
+/// Move a prompt to the trash
+#[tauri::command]
+pub async fn delete_prompt(database: State<'_, Database>, id: String) -> Result<(), CommandError> {
+    database
+        .run(move |conn| {
+            let now = chrono::Utc::now().timestamp_millis();
+            let tx = conn.transaction()?;
+            PromptRepository::new(&tx).delete(&id, now)?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+        .map_err(CommandError::from)
+}
 
-    if let Some(text) = &input.text {
-        updates.push(format!("text = ?{}", params.len() + 1));
-        params.push(Box::new(text.clone()));
-    }
-    if let Some(header) = &input.header {
-        updates.push(format!("header = ?{}", params.len() + 1));
-        params.push(Box::new(header.clone()));
-    }
-    if let Some(source) = &input.source {
-        updates.push(format!("source = ?{}", params.len() + 1));
-        params.push(Box::new(source.clone()));
-    }
-    if let Some(url) = &input.url {
-        updates.push(format!("url = ?{}", params.len() + 1));
-        params.push(Box::new(url.clone()));
-    }
-    if let Some(folder_id) = &input.folder_id {
-        updates.push(format!("folder_id = ?{}", params.len() + 1));
-        params.push(Box::new(folder_id.clone()));
-    }
-    if let Some(is_favorite) = input.is_favorite {
-        updates.push(format!("is_favorite = ?{}", params.len() + 1));
-        params.push(Box::new(if is_favorite { 1i32 } else { 0i32 }));
-    }
+/// Apply one action to many prompts in a single transaction. The batch is
+/// journaled as one operation so it can be undone as a whole.
+#[tauri::command]
+pub async fn bulk_update_prompts(
+    database: State<'_, Database>,
+    ids: Vec<String>,
+    action: BulkAction,
+) -> Result<BulkResult, CommandError> {
+    database
+        .run(move |conn| {
+            let now = chrono::Utc::now().timestamp_millis();
+            let tx = conn.transaction()?;
+            let result = bulk::apply(&tx, &ids, &action, now)?;
+            tx.commit()?;
+            Ok(result)
+        })
+        .await
+        .map_err(CommandError::from)
+}
 
-    let sql = format!(
-        "UPDATE prompts SET {} WHERE id = ?{}",
-        updates.join(", "),
-        params.len() + 1
-    );
-    params.push(Box::new(id.clone()));
+/// Bump a prompt's use count when it is copied or inserted
+#[tauri::command]
+pub async fn record_prompt_use(
+    database: State<'_, Database>,
+    id: String,
+) -> Result<Prompt, CommandError> {
+    database
+        .run(move |conn| {
+            PromptRepository::new(conn).record_use(&id, chrono::Utc::now().timestamp_millis())
+        })
+        .await
+        .map_err(CommandError::from)
+}
 
-    let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
-    conn.execute(&sql, param_refs.as_slice())
-        .map_err(|e| e.to_string())?;
+// ============ Undo Commands ============
+
+/// Undo or redo one journaled operation and tell the frontend which entities
+/// it touched
+async fn apply_operation(
+    app_handle: &AppHandle,
+    direction: Direction,
+) -> Result<Option<AppliedOperation>, CommandError> {
+    let applied = app_handle
+        .state::<Database>()
+        .run(move |conn| {
+            let tx = conn.transaction()?;
+            let now = chrono::Utc::now().timestamp_millis();
+            let applied = journal::apply(&tx, direction, now)?;
+            tx.commit()?;
+            Ok(applied)
+        })
+        .await?;
 
-    // Fetch and return the updated prompt
-    get_prompt(app_handle, id)?.ok_or_else(|| "Prompt not found".to_string())
+    if let Some(applied) = &applied {
+        let _ = app_handle.emit("operation-applied", applied);
+    }
+    Ok(applied)
 }
 
+/// Undo the most recent library change. Returns None when there is nothing
+/// to undo.
 #[tauri::command]
-pub fn delete_prompt(app_handle: AppHandle, id: String) -> Result<(), String> {
-    let conn = db::get_connection(&app_handle).map_err(|e| e.to_string())?;
+pub async fn undo(app_handle: AppHandle) -> Result<Option<AppliedOperation>, CommandError> {
+    apply_operation(&app_handle, Direction::Undo).await
+}
 
-    conn.execute("DELETE FROM prompts WHERE id = ?", [&id])
-        .map_err(|e| e.to_string())?;
+/// Redo the most recently undone change
+#[tauri::command]
+pub async fn redo(app_handle: AppHandle) -> Result<Option<AppliedOperation>, CommandError> {
+    apply_operation(&app_handle, Direction::Redo).await
+}
 
-    Ok(())
+#[tauri::command]
+pub async fn get_undo_state(database: State<'_, Database>) -> Result<UndoState, CommandError> {
+    database
+        .run(|conn| journal::undo_state(conn))
+        .await
+        .map_err(CommandError::from)
 }
 
-// ============ Folder Commands ============
+// ============ Revision Commands ============
 
 #[tauri::command]
-pub fn get_folders(app_handle: AppHandle) -> Result<Vec<Folder>, String> {
-    let conn = db::get_connection(&app_handle).map_err(|e| e.to_string())?;
+pub async fn get_prompt_revisions(
+    database: State<'_, Database>,
+    prompt_id: String,
+) -> Result<Vec<PromptRevision>, CommandError> {
+    database
+        .run(move |conn| revisions::list_revisions(conn, &prompt_id))
+        .await
+        .map_err(CommandError::from)
+}
 
-    let mut stmt = conn
-        .prepare("SELECT id, name, icon, color, parent_id, sort_order, created_at FROM folders ORDER BY sort_order")
-        .map_err(|e| e.to_string())?;
+#[tauri::command]
+pub async fn diff_prompt_revisions(
+    database: State<'_, Database>,
+    from_id: i64,
+    to_id: i64,
+    mode: Option<DiffMode>,
+) -> Result<RevisionDiff, CommandError> {
+    database
+        .run(move |conn| revisions::diff_revisions(conn, from_id, to_id, mode.unwrap_or_default()))
+        .await
+        .map_err(CommandError::from)
+}
 
-    let folders = stmt
-        .query_map([], |row| {
-            Ok(Folder {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                icon: row.get(2)?,
-                color: row.get(3)?,
-                parent_id: row.get(4)?,
-                sort_order: row.get(5)?,
-                created_at: row.get(6)?,
-            })
+/// Restore an earlier revision, recorded as a new revision of its own
+#[tauri::command]
+pub async fn revert_prompt(
+    database: State<'_, Database>,
+    settings: State<'_, SettingsState>,
+    prompt_id: String,
+    revision_id: i64,
+) -> Result<Prompt, CommandError> {
+    let history = settings.get().history;
+
+    database
+        .run(move |conn| {
+            let now = chrono::Utc::now().timestamp_millis();
+            let tx = conn.transaction()?;
+            let prompts = PromptRepository::new(&tx);
+            if prompts.revert(&prompt_id, revision_id, now)? {
+                revisions::compact(&tx, Some(&prompt_id), &history, now)?;
+            }
+            let prompt = prompts
+                .get(&prompt_id)?
+                .ok_or(DbError::NotFound("Prompt"))?;
+            tx.commit()?;
+            Ok(prompt)
         })
-        .map_err(|e| e.to_string())?
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|e| e.to_string())?;
+        .await
+        .map_err(CommandError::from)
+}
 
-    Ok(folders)
+#[tauri::command]
+pub fn get_history_settings(settings: State<'_, SettingsState>) -> HistorySettings {
+    settings.get().history
 }
 
 #[tauri::command]
-pub fn create_folder(app_handle: AppHandle, input: CreateFolderInput) -> Result<Folder, String> {
-    let conn = db::get_connection(&app_handle).map_err(|e| e.to_string())?;
+pub fn update_history_settings(
+    settings: State<'_, SettingsState>,
+    history: HistorySettings,
+) -> Result<HistorySettings, CommandError> {
+    settings
+        .update(|s| s.history = history)
+        .map(|s| s.history)
+        .map_err(CommandError::from)
+}
 
-    let id = uuid::Uuid::new_v4().to_string();
-    let now = chrono::Utc::now().timestamp_millis();
+// ============ Folder Commands ============
 
-    // Get max sort_order
-    let max_order: i32 = conn
-        .query_row("SELECT COALESCE(MAX(sort_order), 0) FROM folders", [], |row| {
-            row.get(0)
+/// An entry in the folder list: either a regular folder or a saved search
+#[derive(Debug, Serialize, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FolderEntry {
+    Folder(Folder),
+    Smart(SmartFolder),
+}
+
+/// Regular folders followed by smart folders with their live counts
+#[tauri::command]
+pub async fn get_folders(database: State<'_, Database>) -> Result<Vec<FolderEntry>, CommandError> {
+    database
+        .run(|conn| {
+            let folders = FolderRepository::new(conn).list()?;
+            let smart = smart_folders::list(conn, chrono::Utc::now().timestamp_millis())?;
+
+            Ok(folders
+                .into_iter()
+                .map(FolderEntry::Folder)
+                .chain(smart.into_iter().map(FolderEntry::Smart))
+                .collect())
         })
-        .unwrap_or(0);
-
-    conn.execute(
-        "INSERT INTO folders (id, name, icon, color, parent_id, sort_order, created_at)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-        rusqlite::params![id, input.name, input.icon, input.color, input.parent_id, max_order + 1, now],
-    )
-    .map_err(|e| e.to_string())?;
-
-    Ok(Folder {
-        id,
-        name: input.name,
-        icon: input.icon,
-        color: input.color,
-        parent_id: input.parent_id,
-        sort_order: max_order + 1,
-        created_at: now,
-    })
+        .await
+        .map_err(CommandError::from)
 }
 
 #[tauri::command]
-pub fn update_folder(
-    app_handle: AppHandle,
+pub async fn create_folder(
+    database: State<'_, Database>,
+    input: CreateFolderInput,
+) -> Result<Folder, CommandError> {
+    database
+        .run(move |conn| {
+            let now = chrono::Utc::now().timestamp_millis();
+            let tx = conn.transaction()?;
+            let folder = FolderRepository::new(&tx).create(input, now)?;
+            tx.commit()?;
+            Ok(folder)
+        })
+        .await
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub async fn update_folder(
+    database: State<'_, Database>,
     id: String,
     name: Option<String>,
     icon: Option<String>,
     color: Option<String>,
-) -> Result<Folder, String> {
-    let conn = db::get_connection(&app_handle).map_err(|e| e.to_string())?;
+) -> Result<Folder, CommandError> {
+    database
+        .run(move |conn| {
+            let now = chrono::Utc::now().timestamp_millis();
+            let input = UpdateFolderInput { name, icon, color };
+            let tx = conn.transaction()?;
+            let folder = FolderRepository::new(&tx).update(&id, input, now)?;
+            tx.commit()?;
+            Ok(folder)
+        })
+        .await
+        .map_err(CommandError::from)
+}
 
-    let mut updates = Vec::new();
-    let mut params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
+/// Move a folder under another one (or to the top level with no parent),
+/// optionally at a given position among its new siblings
+#[tauri::command]
+pub async fn move_folder(
+    database: State<'_, Database>,
+    id: String,
+    parent_id: Option<String>,
+    position: Option<usize>,
+) -> Result<(), CommandError> {
+    database
+        .run(move |conn| {
+            let now = chrono::Utc::now().timestamp_millis();
+            let tx = conn.transaction()?;
+            FolderRepository::new(&tx).move_to(&id, parent_id.as_deref(), position, now)?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+        .map_err(CommandError::from)
+}
 
-    if let Some(n) = &name {
-        updates.push(format!("name = ?{}", params.len() + 1));
-        params.push(Box::new(n.clone()));
-    }
-    if let Some(i) = &icon {
-        updates.push(format!("icon = ?{}", params.len() + 1));
-        params.push(Box::new(i.clone()));
-    }
-    if let Some(c) = &color {
-        updates.push(format!("color = ?{}", params.len() + 1));
-        params.push(Box::new(c.clone()));
-    }
+/// Reorder the children of one parent
+#[tauri::command]
+pub async fn reorder_folders(
+    database: State<'_, Database>,
+    parent_id: Option<String>,
+    ordered_ids: Vec<String>,
+) -> Result<(), CommandError> {
+    database
+        .run(move |conn| {
+            let now = chrono::Utc::now().timestamp_millis();
+            let tx = conn.transaction()?;
+            FolderRepository::new(&tx).reorder(parent_id.as_deref(), &ordered_ids, now)?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+        .map_err(CommandError::from)
+}
 
-    if !updates.is_empty() {
-        let sql = format!(
-            "UPDATE folders SET {} WHERE id = ?{}",
-            updates.join(", "),
-            params.len() + 1
-        );
-        params.push(Box::new(id.clone()));
-
-        let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
-        conn.execute(&sql, param_refs.as_slice())
-            .map_err(|e| e.to_string())?;
-    }
+#[tauri::command]
+pub async fn get_folder_tree(
+    database: State<'_, Database>,
+) -> Result<Vec<FolderNode>, CommandError> {
+    database
+        .run(|conn| FolderRepository::new(conn).tree())
+        .await
+        .map_err(CommandError::from)
+}
+
+/// Prompts in a folder, optionally including everything in its subfolders
+#[tauri::command]
+pub async fn get_folder_prompts(
+    database: State<'_, Database>,
+    folder_id: String,
+    include_subfolders: bool,
+) -> Result<Vec<Prompt>, CommandError> {
+    database
+        .run(move |conn| PromptRepository::new(conn).in_folder(&folder_id, include_subfolders))
+        .await
+        .map_err(CommandError::from)
+}
 
-    // Fetch and return the updated folder
-    let mut stmt = conn
-        .prepare("SELECT id, name, icon, color, parent_id, sort_order, created_at FROM folders WHERE id = ?")
-        .map_err(|e| e.to_string())?;
-
-    stmt.query_row([&id], |row| {
-        Ok(Folder {
-            id: row.get(0)?,
-            name: row.get(1)?,
-            icon: row.get(2)?,
-            color: row.get(3)?,
-            parent_id: row.get(4)?,
-            sort_order: row.get(5)?,
-            created_at: row.get(6)?,
+/// Move a folder to the trash. By default its subfolders and prompts go with
+/// it; `mode` can move them elsewhere or refuse when the folder isn't empty.
+#[tauri::command]
+pub async fn delete_folder(
+    database: State<'_, Database>,
+    id: String,
+    mode: Option<DeleteFolderMode>,
+) -> Result<DeleteFolderResult, CommandError> {
+    let mode = mode.unwrap_or_default();
+
+    database
+        .run(move |conn| {
+            let now = chrono::Utc::now().timestamp_millis();
+            let tx = conn.transaction()?;
+            let result = FolderRepository::new(&tx).delete(&id, &mode, now)?;
+            tx.commit()?;
+            Ok(result)
         })
-    })
-    .map_err(|e| e.to_string())
+        .await
+        .map_err(CommandError::from)
 }
 
+// ============ Smart Folder Commands ============
+
 #[tauri::command]
-pub fn delete_folder(app_handle: AppHandle, id: String) -> Result<(), String> {
-    let conn = db::get_connection(&app_handle).map_err(|e| e.to_string())?;
+pub async fn create_smart_folder(
+    database: State<'_, Database>,
+    input: SmartFolderInput,
+) -> Result<SmartFolder, CommandError> {
+    database
+        .run(move |conn| {
+            let id = uuid::Uuid::new_v4().to_string();
+            let now = chrono::Utc::now().timestamp_millis();
+            let tx = conn.transaction()?;
+            let scope = [(EntityKind::SmartFolder, id.clone())];
+            let folder = journal::track(&tx, "Create smart folder", &scope, now, |conn| {
+                smart_folders::create(conn, &id, input, now)
+            })?;
+            tx.commit()?;
+            Ok(folder)
+        })
+        .await
+        .map_err(CommandError::from)
+}
 
-    // Move prompts in this folder to no folder
-    conn.execute(
-        "UPDATE prompts SET folder_id = NULL WHERE folder_id = ?",
-        [&id],
-    )
-    .map_err(|e| e.to_string())?;
+#[tauri::command]
+pub async fn update_smart_folder(
+    database: State<'_, Database>,
+    id: String,
+    input: UpdateSmartFolderInput,
+) -> Result<SmartFolder, CommandError> {
+    database
+        .run(move |conn| {
+            let now = chrono::Utc::now().timestamp_millis();
+            let tx = conn.transaction()?;
+            let scope = [(EntityKind::SmartFolder, id.clone())];
+            let folder = journal::track(&tx, "Edit smart folder", &scope, now, |conn| {
+                smart_folders::update(conn, &id, input, now)
+            })?;
+            tx.commit()?;
+            Ok(folder)
+        })
+        .await
+        .map_err(CommandError::from)
+}
 
-    conn.execute("DELETE FROM folders WHERE id = ?", [&id])
-        .map_err(|e| e.to_string())?;
+#[tauri::command]
+pub async fn delete_smart_folder(
+    database: State<'_, Database>,
+    id: String,
+) -> Result<(), CommandError> {
+    database
+        .run(move |conn| {
+            let now = chrono::Utc::now().timestamp_millis();
+            let tx = conn.transaction()?;
+            let scope = [(EntityKind::SmartFolder, id.clone())];
+            journal::track(&tx, "Delete smart folder", &scope, now, |conn| {
+                smart_folders::delete(conn, &id)
+            })?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+        .map_err(CommandError::from)
+}
 
-    Ok(())
+/// Evaluate a saved smart folder
+#[tauri::command]
+pub async fn get_smart_folder_prompts(
+    database: State<'_, Database>,
+    id: String,
+) -> Result<Vec<Prompt>, CommandError> {
+    database
+        .run(move |conn| {
+            let now = chrono::Utc::now().timestamp_millis();
+            let folder =
+                smart_folders::get(conn, &id, now)?.ok_or(DbError::NotFound("Smart folder"))?;
+            PromptRepository::new(conn).filtered(&folder.query, now)
+        })
+        .await
+        .map_err(CommandError::from)
+}
+
+/// Evaluate a filter before saving it as a smart folder
+#[tauri::command]
+pub async fn preview_smart_folder(
+    database: State<'_, Database>,
+    query: PromptFilter,
+) -> Result<Vec<Prompt>, CommandError> {
+    database
+        .run(move |conn| {
+            PromptRepository::new(conn).filtered(&query, chrono::Utc::now().timestamp_millis())
+        })
+        .await
+        .map_err(CommandError::from)
+}
+
+// ============ Trash Commands ============
+
+#[derive(Debug, Serialize)]
+pub struct Trash {
+    pub prompts: Vec<Prompt>,
+    pub folders: Vec<Folder>,
+}
+
+#[tauri::command]
+pub async fn get_trash(database: State<'_, Database>) -> Result<Trash, CommandError> {
+    database
+        .run(|conn| {
+            Ok(Trash {
+                prompts: PromptRepository::new(conn).trashed()?,
+                folders: FolderRepository::new(conn).trashed()?,
+            })
+        })
+        .await
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub async fn restore_prompt(
+    database: State<'_, Database>,
+    id: String,
+) -> Result<Prompt, CommandError> {
+    database
+        .run(move |conn| {
+            let now = chrono::Utc::now().timestamp_millis();
+            let tx = conn.transaction()?;
+            let prompt = PromptRepository::new(&tx).restore(&id, now)?;
+            tx.commit()?;
+            Ok(prompt)
+        })
+        .await
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub async fn restore_folder(database: State<'_, Database>, id: String) -> Result<(), CommandError> {
+    database
+        .run(move |conn| {
+            let now = chrono::Utc::now().timestamp_millis();
+            let tx = conn.transaction()?;
+            FolderRepository::new(&tx).restore(&id, now)?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub async fn purge_prompt(database: State<'_, Database>, id: String) -> Result<(), CommandError> {
+    database
+        .run(move |conn| {
+            let tx = conn.transaction()?;
+            trash::purge_prompt(&tx, &id)?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub async fn purge_folder(
+    database: State<'_, Database>,
+    id: String,
+) -> Result<PurgeResult, CommandError> {
+    database
+        .run(move |conn| {
+            let tx = conn.transaction()?;
+            let purged = trash::purge_folder(&tx, &id)?;
+            tx.commit()?;
+            Ok(purged)
+        })
+        .await
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub async fn empty_trash(database: State<'_, Database>) -> Result<PurgeResult, CommandError> {
+    database
+        .run(|conn| {
+            let tx = conn.transaction()?;
+            let purged = trash::empty_trash(&tx)?;
+            tx.commit()?;
+            Ok(purged)
+        })
+        .await
+        .map_err(CommandError::from)
+}
+
+/// Prompt deletions after `since` (ms), for pushing to the cloud copy
+#[tauri::command]
+pub async fn get_sync_tombstones(
+    database: State<'_, Database>,
+    since: Option<i64>,
+) -> Result<Vec<Tombstone>, CommandError> {
+    database
+        .run(move |conn| trash::tombstones(conn, since.unwrap_or(0)))
+        .await
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn get_trash_settings(settings: State<'_, SettingsState>) -> TrashSettings {
+    settings.get().trash
+}
+
+#[tauri::command]
+pub fn update_trash_settings(
+    settings: State<'_, SettingsState>,
+    trash: TrashSettings,
+) -> Result<TrashSettings, CommandError> {
+    settings
+        .update(|s| s.trash = trash)
+        .map(|s| s.trash)
+        .map_err(CommandError::from)
 }
 
 // ============ Import/Export Commands ============
 
 #[tauri::command]
-pub fn import_pack(
+pub async fn import_pack(
     app_handle: AppHandle,
+    database: State<'_, Database>,
     data: Vec<u8>,
     password: Option<String>,
-) -> Result<ImportResult, String> {
-    // Decode the pack file
-    let json_str =
-        crypto::decode_pack(&data, password.as_deref()).map_err(|e| e.to_string())?;
-
-    // Parse JSON
-    let pack_data: serde_json::Value =
-        serde_json::from_str(&json_str).map_err(|e| e.to_string())?;
-
-    let prompts_array = pack_data
-        .get("prompts")
-        .and_then(|p| p.as_array())
-        .ok_or("Invalid pack format: missing prompts array")?;
-
-    let conn = db::get_connection(&app_handle).map_err(|e| e.to_string())?;
-    let now = chrono::Utc::now().timestamp_millis();
-
-    let mut imported_prompts = Vec::new();
-
-    for prompt_value in prompts_array {
-        let id = uuid::Uuid::new_v4().to_string();
-        let text = prompt_value
-            .get("text")
-            .and_then(|t| t.as_str())
-            .unwrap_or("")
-            .to_string();
-        let header = prompt_value
-            .get("header")
-            .and_then(|h| h.as_str())
-            .map(|s| s.to_string());
-        let source = prompt_value
-            .get("source")
-            .and_then(|s| s.as_str())
-            .unwrap_or("manual")
-            .to_string();
-
-        if text.is_empty() {
-            continue;
-        }
+) -> Result<ImportResult, CommandError> {
+    let json_str = crypto::decode_pack(&data, password.as_deref())?;
+    let entries = repository::pack_entries(&json_str)?;
+
+    backup::snapshot(&app_handle, BackupKind::PreImport).await?;
+
+    database
+        .run(move |conn| {
+            let now = chrono::Utc::now().timestamp_millis();
+            let tx = conn.transaction()?;
+            let imported = PromptRepository::new(&tx).import(&entries, now)?;
+            tx.commit()?;
+
+            Ok(ImportResult {
+                count: imported.prompts.len(),
+                prompts: imported.prompts,
+                errors: imported.errors,
+            })
+        })
+        .await
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub async fn export_pack(
+    database: State<'_, Database>,
+    input: ExportPackInput,
+) -> Result<Vec<u8>, CommandError> {
+    let json_str = database
+        .run(move |conn| {
+            let now = chrono::Utc::now().timestamp_millis();
+            PromptRepository::new(conn).export(Some(&input.prompt_ids), now)
+        })
+        .await?;
+
+    crypto::encode_pack(&json_str, input.password.as_deref()).map_err(CommandError::from)
+}
+
+/// Write the library, or one folder's subtree, to `dir` as Markdown files.
+/// Returns the number of files written.
+#[tauri::command]
+pub async fn export_markdown(
+    database: State<'_, Database>,
+    dir: String,
+    folder_id: Option<String>,
+) -> Result<usize, CommandError> {
+    let records = database
+        .run(move |conn| PromptRepository::new(conn).records(folder_id.as_deref()))
+        .await?;
+    Ok(markdown::write(Path::new(&dir), &records)?)
+}
+
+/// Import the Markdown files below `dir` into the library, or into one
+/// folder. Files carrying the id of an existing prompt update it.
+#[tauri::command]
+pub async fn import_markdown(
+    app_handle: AppHandle,
+    database: State<'_, Database>,
+    dir: String,
+    folder_id: Option<String>,
+    dry_run: bool,
+) -> Result<ImportReport, CommandError> {
+    let set = markdown::read(Path::new(&dir))?;
+    import_record_set(&app_handle, &database, set, folder_id, dry_run).await
+}
+
+#[tauri::command]
+pub async fn export_json(
+    database: State<'_, Database>,
+    folder_id: Option<String>,
+) -> Result<String, CommandError> {
+    let records = database
+        .run(move |conn| PromptRepository::new(conn).records(folder_id.as_deref()))
+        .await?;
+    Ok(interchange::to_json(
+        &records,
+        chrono::Utc::now().timestamp_millis(),
+    )?)
+}
 
-        conn.execute(
-            "INSERT INTO prompts (id, text, header, source, is_favorite, use_count, created_at, updated_at, sync_status)
-             VALUES (?1, ?2, ?3, ?4, 0, 0, ?5, ?5, 'local-only')",
-            rusqlite::params![id, text, header, source, now],
-        )
-        .map_err(|e| e.to_string())?;
-
-        imported_prompts.push(Prompt {
-            id,
-            text,
-            header,
-            source,
-            url: None,
-            folder_id: None,
-            is_favorite: false,
-            use_count: 0,
-            created_at: now,
-            updated_at: now,
-            sync_status: "local-only".to_string(),
-            cloud_id: None,
-        });
+#[tauri::command]
+pub async fn import_json(
+    app_handle: AppHandle,
+    database: State<'_, Database>,
+    data: String,
+    folder_id: Option<String>,
+    dry_run: bool,
+) -> Result<ImportReport, CommandError> {
+    let set = interchange::from_json(&data)?;
+    import_record_set(&app_handle, &database, set, folder_id, dry_run).await
+}
+
+#[tauri::command]
+pub async fn export_csv(
+    database: State<'_, Database>,
+    folder_id: Option<String>,
+) -> Result<String, CommandError> {
+    let records = database
+        .run(move |conn| PromptRepository::new(conn).records(folder_id.as_deref()))
+        .await?;
+    Ok(interchange::to_csv(&records)?)
+}
+
+#[tauri::command]
+pub async fn import_csv(
+    app_handle: AppHandle,
+    database: State<'_, Database>,
+    data: String,
+    options: Option<CsvOptions>,
+    folder_id: Option<String>,
+    dry_run: bool,
+) -> Result<ImportReport, CommandError> {
+    let set = interchange::from_csv(&data, &options.unwrap_or_default())?;
+    import_record_set(&app_handle, &database, set, folder_id, dry_run).await
+}
+
+/// Import `set` in one transaction, after a backup. A dry run rolls the
+/// transaction back and reports what the import would have done.
+async fn import_record_set(
+    app_handle: &AppHandle,
+    database: &Database,
+    set: RecordSet,
+    folder_id: Option<String>,
+    dry_run: bool,
+) -> Result<ImportReport, CommandError> {
+    if !dry_run {
+        backup::snapshot(app_handle, BackupKind::PreImport).await?;
     }
 
-    Ok(ImportResult {
-        count: imported_prompts.len(),
-        prompts: imported_prompts,
-    })
+    database
+        .run(move |conn| {
+            let now = chrono::Utc::now().timestamp_millis();
+            let tx = conn.transaction()?;
+            let report =
+                PromptRepository::new(&tx).import_records(&set, folder_id.as_deref(), now)?;
+            if dry_run {
+                tx.rollback()?;
+            } else {
+                tx.commit()?;
+            }
+            Ok(report)
+        })
+        .await
+        .map_err(CommandError::from)
+}
+
+// ============ Quick Picker Commands ============
+
+#[derive(Debug, Serialize)]
+pub struct PickResult {
+    pub text: String,
+    /// Variables left as placeholders because no value was given
+    pub missing: Vec<String>,
+    pub pasted: bool,
 }
 
 #[tauri::command]
-pub fn export_pack(app_handle: AppHandle, input: ExportPackInput) -> Result<Vec<u8>, String> {
-    let conn = db::get_connection(&app_handle).map_err(|e| e.to_string())?;
+pub fn get_shortcut_settings(settings: State<'_, SettingsState>) -> ShortcutSettings {
+    settings.get().shortcuts
+}
 
-    // Fetch selected prompts
-    let placeholders: Vec<String> = input.prompt_ids.iter().map(|_| "?".to_string()).collect();
-    let sql = format!(
-        "SELECT text, header, source, created_at FROM prompts WHERE id IN ({})",
-        placeholders.join(", ")
-    );
+/// Whether each saved hotkey is bound, with the reason when it isn't
+#[tauri::command]
+pub fn get_shortcut_status(manager: State<'_, ShortcutManager>) -> Vec<ShortcutStatus> {
+    manager.statuses()
+}
 
-    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+/// Rebind hotkeys and save them. Conflicts fail with `shortcut_conflict`
+/// and leave the previous hotkeys in place.
+#[tauri::command]
+pub fn update_shortcut_settings(
+    app_handle: AppHandle,
+    shortcuts: ShortcutSettings,
+) -> Result<Vec<ShortcutStatus>, CommandError> {
+    shortcuts::update(&app_handle, shortcuts)
+}
 
-    let params: Vec<&dyn rusqlite::ToSql> = input
-        .prompt_ids
-        .iter()
-        .map(|id| id as &dyn rusqlite::ToSql)
-        .collect();
-
-    let prompts: Vec<serde_json::Value> = stmt
-        .query_map(params.as_slice(), |row| {
-            Ok(serde_json::json!({
-                "text": row.get::<_, String>(0)?,
-                "header": row.get::<_, Option<String>>(1)?,
-                "source": row.get::<_, String>(2)?,
-                "createdAt": row.get::<_, i64>(3)?,
-            }))
+#[tauri::command]
+pub fn toggle_quick_picker(app_handle: AppHandle) -> Result<(), CommandError> {
+    shortcuts::toggle_picker(&app_handle).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn hide_quick_picker(app_handle: AppHandle) -> Result<(), CommandError> {
+    shortcuts::hide_picker(&app_handle).map_err(CommandError::from)
+}
+
+/// Copy a prompt with its variables filled in, close the picker and paste
+/// into the previous app when auto-paste is on
+#[tauri::command]
+pub async fn pick_prompt(
+    app_handle: AppHandle,
+    database: State<'_, Database>,
+    settings: State<'_, SettingsState>,
+    id: String,
+    values: Option<HashMap<String, String>>,
+) -> Result<PickResult, CommandError> {
+    let values = values.unwrap_or_default();
+    let prompt = database
+        .run(move |conn| {
+            PromptRepository::new(conn)
+                .get(&id)?
+                .ok_or(DbError::NotFound("Prompt"))
         })
-        .map_err(|e| e.to_string())?
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|e| e.to_string())?;
+        .await?;
+
+    let text = template::render(&prompt.text, &values);
+    let missing = template::missing(&prompt.text, &values);
+    app_handle.clipboard().write_text(text.as_str())?;
+    database
+        .run(move |conn| {
+            let now = chrono::Utc::now().timestamp_millis();
+            PromptRepository::new(conn).record_use(&prompt.id, now)
+        })
+        .await?;
+
+    shortcuts::hide_picker(&app_handle)?;
+    let pasted = settings.get().shortcuts.auto_paste && shortcuts::paste().await;
+    Ok(PickResult {
+        text,
+        missing,
+        pasted,
+    })
+}
+
+// ============ Capture Commands ============
 
-    let pack_data = serde_json::json!({
-        "version": 1,
-        "exportedAt": chrono::Utc::now().timestamp_millis(),
-        "prompts": prompts,
-    });
+/// Save the clipboard text as a prompt, or return the prompt that already
+/// has it
+#[tauri::command]
+pub async fn capture_clipboard(app_handle: AppHandle) -> Result<CaptureResult, CommandError> {
+    capture::capture_clipboard(&app_handle).await
+}
 
-    let json_str = serde_json::to_string(&pack_data).map_err(|e| e.to_string())?;
+#[tauri::command]
+pub fn get_capture_settings(settings: State<'_, SettingsState>) -> CaptureSettings {
+    settings.get().capture
+}
 
-    crypto::encode_pack(&json_str, input.password.as_deref()).map_err(|e| e.to_string())
+#[tauri::command]
+pub fn update_capture_settings(
+    settings: State<'_, SettingsState>,
+    capture: CaptureSettings,
+) -> Result<CaptureSettings, CommandError> {
+    settings
+        .update(|s| s.capture = capture)
+        .map(|s| s.capture)
+        .map_err(CommandError::from)
+}
+
+// ============ Deep Link Commands ============
+
+/// Actions from links that arrived since the last call, oldest first
+#[tauri::command]
+pub fn take_deep_links(state: State<'_, DeepLinkState>) -> Vec<DeepLinkAction> {
+    state.take()
+}
+
+// ============ Extension Bridge Commands ============
+
+#[tauri::command]
+pub fn get_bridge_status(app_handle: AppHandle) -> BridgeStatus {
+    bridge::status(&app_handle)
+}
+
+/// Serve the library to the browser extension, optionally on a new port
+#[tauri::command]
+pub async fn enable_bridge(
+    app_handle: AppHandle,
+    port: Option<u16>,
+) -> Result<BridgeStatus, CommandError> {
+    bridge::enable(&app_handle, port).await
+}
+
+#[tauri::command]
+pub fn disable_bridge(app_handle: AppHandle) -> Result<BridgeStatus, CommandError> {
+    bridge::disable(&app_handle)
+}
+
+/// Replace the pairing token; paired extensions must be paired again
+#[tauri::command]
+pub fn regenerate_bridge_token(app_handle: AppHandle) -> Result<BridgeStatus, CommandError> {
+    bridge::regenerate_token(&app_handle)
 }
 
 // ============ Crypto Commands ============
 
 #[tauri::command]
-pub fn encrypt_data(data: String, password: String) -> Result<Vec<u8>, String> {
-    crypto::encode_pack(&data, Some(&password)).map_err(|e| e.to_string())
+pub fn encrypt_data(data: String, password: String) -> Result<Vec<u8>, CommandError> {
+    crypto::encode_pack(&data, Some(&password)).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub fn decrypt_data(data: Vec<u8>, password: Option<String>) -> Result<String, CommandError> {
+    crypto::decode_pack(&data, password.as_deref()).map_err(CommandError::from)
+}
+
+// ============ Database Commands ============
+
+#[tauri::command]
+pub fn get_database_status(database: State<'_, Database>) -> DbStatus {
+    database.status()
 }
 
 #[tauri::command]
-pub fn decrypt_data(data: Vec<u8>, password: Option<String>) -> Result<String, String> {
-    crypto::decode_pack(&data, password.as_deref()).map_err(|e| e.to_string())
+pub async fn retry_database_init(app_handle: AppHandle) -> Result<DbStatus, CommandError> {
+    db::init_database(&app_handle).await?;
+    Ok(app_handle.state::<Database>().status())
+}
+
+/// Start over with an empty library after a failed initialization. The
+/// broken file is kept next to the new one rather than deleted.
+#[tauri::command]
+pub async fn reset_database(app_handle: AppHandle) -> Result<DbStatus, CommandError> {
+    let database = app_handle.state::<Database>();
+    if !matches!(database.status(), DbStatus::Failed { .. }) {
+        return Err(CommandError::validation(
+            "Reset is only available after a failed initialization",
+        ));
+    }
+
+    let db_path = db::get_db_path(&app_handle)?;
+    database.close().await;
+    let aside = db::set_aside(&db_path, "broken")?;
+    log::warn!("Broken database moved to {:?}", aside);
+
+    // The new library starts out unencrypted
+    app_handle.state::<VaultState>().lock();
+    db::init_database(&app_handle).await?;
+    Ok(database.status())
+}
+
+/// Replace the library with a database file chosen by the user. The current
+/// file is kept next to it.
+#[tauri::command]
+pub async fn restore_database(
+    app_handle: AppHandle,
+    path: String,
+) -> Result<DbStatus, CommandError> {
+    let source = std::path::PathBuf::from(path);
+    replace_library(&app_handle, &source, true).await?;
+    Ok(app_handle.state::<Database>().status())
+}
+
+/// Swap the library file for `source` and reopen it. With `keep_current` the
+/// old file is moved aside instead of deleted.
+async fn replace_library(
+    app_handle: &AppHandle,
+    source: &std::path::Path,
+    keep_current: bool,
+) -> Result<(), CommandError> {
+    let key = app_handle.state::<VaultState>().key();
+    db::check_database_file(source, key.as_deref())?;
+
+    let db_path = db::get_db_path(app_handle)?;
+    app_handle.state::<Database>().close().await;
+
+    let replaced = (|| -> Result<(), DbError> {
+        if keep_current {
+            db::set_aside(&db_path, "replaced")?;
+        } else {
+            db::remove_database_files(&db_path)?;
+        }
+        std::fs::copy(source, &db_path)?;
+        Ok(())
+    })();
+
+    // Reopen whatever ended up on disk, even if the swap failed halfway
+    let reopened = db::init_database(app_handle).await;
+    replaced?;
+    reopened.map_err(CommandError::from)
+}
+
+// ============ Backup Commands ============
+
+#[derive(Debug, Serialize)]
+pub struct RestoreResult {
+    pub restored: usize,
+    /// Snapshot of the library taken right before the restore
+    pub safety_backup: Option<BackupInfo>,
+}
+
+#[tauri::command]
+pub fn list_backups(app_handle: AppHandle) -> Result<Vec<BackupInfo>, CommandError> {
+    let dir = backup::get_backup_dir(&app_handle)?;
+    backup::list_backups(&dir).map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub async fn create_backup(app_handle: AppHandle) -> Result<BackupInfo, CommandError> {
+    backup::snapshot(&app_handle, BackupKind::Manual)
+        .await
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+pub async fn diff_backup(app_handle: AppHandle, id: String) -> Result<BackupDiff, CommandError> {
+    let dir = backup::get_backup_dir(&app_handle)?;
+    let path = backup::backup_path(&dir, &id)?;
+    let key = app_handle.state::<VaultState>().key();
+
+    app_handle
+        .state::<Database>()
+        .run(move |conn| backup::diff_backup(conn, &path, key.as_deref()))
+        .await
+        .map_err(CommandError::from)
+}
+
+/// Restore the whole library, or only `prompt_ids`, from a backup
+#[tauri::command]
+pub async fn restore_backup(
+    app_handle: AppHandle,
+    id: String,
+    prompt_ids: Option<Vec<String>>,
+) -> Result<RestoreResult, CommandError> {
+    let dir = backup::get_backup_dir(&app_handle)?;
+    let path = backup::backup_path(&dir, &id)?;
+    let key = app_handle.state::<VaultState>().key();
+    let database = app_handle.state::<Database>();
+
+    // Keep a way back, unless the library is too broken to snapshot
+    let safety_backup = if database.status() == DbStatus::Ready {
+        Some(backup::snapshot(&app_handle, BackupKind::PreRestore).await?)
+    } else {
+        None
+    };
+
+    let restored = match prompt_ids {
+        Some(ids) => {
+            database
+                .run(move |conn| backup::restore_prompts(conn, &path, key.as_deref(), &ids))
+                .await?
+        }
+        None => {
+            replace_library(&app_handle, &path, safety_backup.is_none()).await?;
+            database
+                .run(|conn| {
+                    let count: i64 =
+                        conn.query_row("SELECT COUNT(*) FROM prompts", [], |row| row.get(0))?;
+                    Ok(count as usize)
+                })
+                .await?
+        }
+    };
+
+    Ok(RestoreResult {
+        restored,
+        safety_backup,
+    })
+}
+
+#[tauri::command]
+pub fn get_backup_settings(settings: State<'_, SettingsState>) -> BackupSettings {
+    settings.get().backup
+}
+
+#[tauri::command]
+pub fn update_backup_settings(
+    settings: State<'_, SettingsState>,
+    backup: BackupSettings,
+) -> Result<BackupSettings, CommandError> {
+    settings
+        .update(|s| s.backup = backup)
+        .map(|s| s.backup)
+        .map_err(CommandError::from)
+}
+
+// ============ Vault Commands ============
+
+#[derive(Debug, Serialize)]
+pub struct VaultStatus {
+    pub enabled: bool,
+    pub locked: bool,
+    pub auto_lock_minutes: u32,
+}
+
+fn vault_status(app_handle: &AppHandle) -> Result<VaultStatus, CommandError> {
+    let db_path = db::get_db_path(app_handle)?;
+    let enabled = vault::is_encrypted(&db_path)?;
+    let unlocked = app_handle.state::<VaultState>().is_unlocked();
+    let settings = app_handle.state::<SettingsState>().get();
+
+    Ok(VaultStatus {
+        enabled,
+        locked: enabled && !unlocked,
+        auto_lock_minutes: settings.vault.auto_lock_minutes,
+    })
+}
+
+/// Reopen whatever library is on disk now. A vault that is still locked
+/// simply stays closed.
+async fn reopen_database(app_handle: &AppHandle) -> Result<(), CommandError> {
+    match db::init_database(app_handle).await {
+        Ok(()) | Err(DbError::Vault(VaultError::Locked)) => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[tauri::command]
+pub fn get_vault_status(app_handle: AppHandle) -> Result<VaultStatus, CommandError> {
+    vault_status(&app_handle)
+}
+
+/// Encrypt the existing plaintext library and keep it unlocked
+#[tauri::command]
+pub async fn enable_vault(
+    app_handle: AppHandle,
+    password: String,
+) -> Result<VaultStatus, CommandError> {
+    let db_path = db::get_db_path(&app_handle)?;
+
+    // Release pooled connections so the file can be swapped out
+    app_handle.state::<Database>().close().await;
+    let encrypted = vault::encrypt_database(&db_path, &password);
+    if encrypted.is_ok() {
+        app_handle.state::<VaultState>().unlock(password);
+    }
+
+    reopen_database(&app_handle).await?;
+    encrypted?;
+
+    vault_status(&app_handle)
+}
+
+#[tauri::command]
+pub async fn unlock_vault(
+    app_handle: AppHandle,
+    password: String,
+) -> Result<VaultStatus, CommandError> {
+    let db_path = db::get_db_path(&app_handle)?;
+
+    vault::verify_key(&db_path, &password)?;
+    app_handle.state::<VaultState>().unlock(password);
+
+    // Initialization is skipped at startup while the vault is locked
+    db::init_database(&app_handle).await?;
+
+    let _ = app_handle.emit("vault-unlocked", ());
+    vault_status(&app_handle)
+}
+
+#[tauri::command]
+pub fn lock_vault(app_handle: AppHandle) -> Result<VaultStatus, CommandError> {
+    // A plaintext library has no key to unlock it again with
+    let db_path = db::get_db_path(&app_handle)?;
+    if !vault::is_encrypted(&db_path)? {
+        return Err(VaultError::NotEnabled.into());
+    }
+
+    app_handle.state::<VaultState>().lock();
+    app_handle.state::<Database>().lock();
+    let _ = app_handle.emit("vault-locked", ());
+    vault_status(&app_handle)
+}
+
+/// Decrypt the library back to a plain SQLite file
+#[tauri::command]
+pub async fn disable_vault(
+    app_handle: AppHandle,
+    password: String,
+) -> Result<VaultStatus, CommandError> {
+    let db_path = db::get_db_path(&app_handle)?;
+
+    app_handle.state::<Database>().close().await;
+    let decrypted = vault::decrypt_database(&db_path, &password);
+    if decrypted.is_ok() {
+        app_handle.state::<VaultState>().lock();
+    }
+
+    reopen_database(&app_handle).await?;
+    decrypted?;
+
+    vault_status(&app_handle)
+}
+
+#[tauri::command]
+pub fn set_vault_auto_lock(
+    app_handle: AppHandle,
+    minutes: u32,
+) -> Result<VaultStatus, CommandError> {
+    app_handle
+        .state::<SettingsState>()
+        .update(|s| s.vault.auto_lock_minutes = minutes)?;
+
+    vault_status(&app_handle)
 }
 
 // ============ Auth State ============
@@ -539,9 +1290,9 @@ impl Default for AuthState {
 pub fn verify_auth_token(
     token: String,
     auth_state: State<'_, AuthState>,
-) -> Result<AuthSession, String> {
+) -> Result<AuthSession, CommandError> {
     // Verify the token
-    let claims = auth::verify_session_token(&token).map_err(|e| e.to_string())?;
+    let claims = auth::verify_session_token(&token)?;
 
     // Create session from verified claims
     let session = AuthSession {
@@ -558,18 +1309,20 @@ pub fn verify_auth_token(
     let mut state_session = auth_state
         .session
         .lock()
-        .map_err(|_| "Failed to acquire lock")?;
+        .map_err(|_| CommandError::internal("Failed to acquire lock"))?;
     *state_session = Some(session.clone());
 
     Ok(session)
 }
 
 #[tauri::command]
-pub fn get_auth_session(auth_state: State<'_, AuthState>) -> Result<Option<AuthSession>, String> {
+pub fn get_auth_session(
+    auth_state: State<'_, AuthState>,
+) -> Result<Option<AuthSession>, CommandError> {
     let session = auth_state
         .session
         .lock()
-        .map_err(|_| "Failed to acquire lock")?;
+        .map_err(|_| CommandError::internal("Failed to acquire lock"))?;
 
     // Check if session exists and is not expired
     if let Some(ref s) = *session {
@@ -584,18 +1337,18 @@ pub fn get_auth_session(auth_state: State<'_, AuthState>) -> Result<Option<AuthS
 }
 
 #[tauri::command]
-pub fn logout(auth_state: State<'_, AuthState>) -> Result<(), String> {
+pub fn logout(auth_state: State<'_, AuthState>) -> Result<(), CommandError> {
     let mut session = auth_state
         .session
         .lock()
-        .map_err(|_| "Failed to acquire lock")?;
+        .map_err(|_| CommandError::internal("Failed to acquire lock"))?;
     *session = None;
     Ok(())
 }
 
 #[tauri::command]
-pub async fn open_auth_window(app_handle: AppHandle) -> Result<(), String> {
-    use tauri::{Emitter, WebviewUrl, WebviewWindowBuilder};
+pub async fn open_auth_window(app_handle: AppHandle) -> Result<(), CommandError> {
+    use tauri::{WebviewUrl, WebviewWindowBuilder};
 
     // Check if auth window already exists
     if let Some(window) = app_handle.get_webview_window("auth") {
@@ -673,14 +1426,13 @@ pub async fn open_auth_window(app_handle: AppHandle) -> Result<(), String> {
                 // Allow all other navigation
                 true
             })
-            .build()
-            .map_err(|e| e.to_string())?;
+            .build()?;
 
     Ok(())
 }
 
 #[tauri::command]
-pub fn close_auth_window(app_handle: AppHandle) -> Result<(), String> {
+pub fn close_auth_window(app_handle: AppHandle) -> Result<(), CommandError> {
     if let Some(window) = app_handle.get_webview_window("auth") {
         let _ = window.close();
     }
@@ -717,14 +1469,20 @@ pub struct ProxyFetchResponse {
 pub async fn proxy_fetch(
     request: ProxyFetchRequest,
     client: State<'_, HttpClient>,
-) -> Result<ProxyFetchResponse, String> {
+) -> Result<ProxyFetchResponse, CommandError> {
     // Validate URL against allowlist
-    if !ALLOWED_API_HOSTS.iter().any(|host| request.url.starts_with(host)) {
-        return Err(format!("URL not allowed: {}", request.url));
+    if !ALLOWED_API_HOSTS
+        .iter()
+        .any(|host| request.url.starts_with(host))
+    {
+        return Err(CommandError::validation(format!(
+            "URL not allowed: {}",
+            request.url
+        )));
     }
 
     let method: reqwest::Method = request.method.parse().map_err(|_| {
-        format!("Invalid HTTP method: {}", request.method)
+        CommandError::validation(format!("Invalid HTTP method: {}", request.method))
     })?;
 
     let mut req_builder = client.0.request(method, &request.url);
@@ -737,7 +1495,7 @@ pub async fn proxy_fetch(
         req_builder = req_builder.body(body);
     }
 
-    let response = req_builder.send().await.map_err(|e| e.to_string())?;
+    let response = req_builder.send().await?;
 
     let status = response.status().as_u16();
     let mut headers = HashMap::new();
@@ -747,7 +1505,7 @@ pub async fn proxy_fetch(
         }
     }
 
-    let body = response.text().await.map_err(|e| e.to_string())?;
+    let body = response.text().await?;
 
     Ok(ProxyFetchResponse {
         status,
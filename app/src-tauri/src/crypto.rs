@@ -197,7 +197,6 @@ pub fn decode_pack(data: &[u8], password: Option<&str>) -> Result<String, Crypto
 }
 
 /// Check if a .pmtpk file is encrypted
-#[allow(dead_code)]
 pub fn is_encrypted(data: &[u8]) -> Result<bool, CryptoError> {
     if data.len() < 4 {
         return Err(CryptoError::InvalidFormat);
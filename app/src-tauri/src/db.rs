@@ -1,7 +1,25 @@
-use rusqlite::Connection;
-use std::path::PathBuf;
-use tauri::{AppHandle, Manager};
+use crate::backup::{self, BackupKind};
+use crate::changes::{self, LibraryChange};
+use crate::settings::SettingsState;
+use crate::validation::ValidationError;
+use crate::vault::{self, VaultError, VaultState};
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{Connection, OpenFlags};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager};
 use thiserror::Error;
+use tokio::sync::{broadcast, watch};
+
+pub type DbPool = r2d2::Pool<SqliteConnectionManager>;
+
+/// Readers share the pool while WAL lets a single writer proceed alongside them
+const POOL_SIZE: u32 = 4;
+const BUSY_TIMEOUT_MS: u32 = 5_000;
+/// Change batches buffered for slow subscribers
+const CHANGE_FEED_CAPACITY: usize = 256;
 
 #[derive(Error, Debug)]
 pub enum DbError {
@@ -11,6 +29,263 @@ pub enum DbError {
     Io(#[from] std::io::Error),
     #[error("Path error: {0}")]
     Path(String),
+    #[error("{0}")]
+    Vault(#[from] VaultError),
+    #[error("Connection pool error: {0}")]
+    Pool(#[from] r2d2::Error),
+    #[error("Database is not open")]
+    Closed,
+    #[error("{0} not found")]
+    NotFound(&'static str),
+    #[error("{0}")]
+    Invalid(String),
+    #[error("{0}")]
+    Validation(#[from] ValidationError),
+    #[error("Background task failed: {0}")]
+    Task(String),
+    #[error("Database is not ready yet")]
+    NotReady,
+    #[error("Database failed to initialize: {0}")]
+    InitFailed(String),
+    #[error("Database file is damaged: {0}")]
+    Corrupt(String),
+    #[error("{0}")]
+    Conflict(String),
+}
+
+/// Lifecycle of the shared database, as reported to the frontend
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum DbStatus {
+    Initializing,
+    Ready,
+    Locked,
+    Failed { error: String },
+}
+
+/// Payload of the `database-init-failed` event
+#[derive(Debug, Clone, Serialize)]
+pub struct DbInitFailure {
+    pub error: String,
+    pub actions: Vec<&'static str>,
+}
+
+/// Recovery options offered to the user when initialization fails
+pub const RECOVERY_ACTIONS: &[&str] = &["retry", "reset", "restore"];
+
+/// How long a command waits for initialization before giving up
+const READY_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Shared handle to the library database, managed as Tauri state.
+///
+/// The pool is built once the library can be opened and dropped again when
+/// the vault locks, so no connection outlives the key. Commands wait on
+/// `status` so that nothing queries the file before its tables exist.
+pub struct Database {
+    pool: RwLock<Option<DbPool>>,
+    /// Bumped by `close`. Queries hold a read lock for as long as they use a
+    /// connection, and give up if the pool they took has been closed since.
+    generation: Arc<tokio::sync::RwLock<u64>>,
+    status: watch::Sender<DbStatus>,
+    changes: broadcast::Sender<Vec<LibraryChange>>,
+    last_activity: Mutex<Instant>,
+}
+
+impl Default for Database {
+    fn default() -> Self {
+        Self {
+            pool: RwLock::new(None),
+            generation: Arc::default(),
+            status: watch::Sender::new(DbStatus::Initializing),
+            changes: broadcast::Sender::new(CHANGE_FEED_CAPACITY),
+            last_activity: Mutex::new(Instant::now()),
+        }
+    }
+}
+
+impl Database {
+    /// Build a fresh pool for `path`, replacing any existing one
+    pub fn open(&self, path: &Path, key: Option<String>) -> Result<(), DbError> {
+        let pool = build_pool(path, key)?;
+        if let Ok(mut guard) = self.pool.write() {
+            *guard = Some(pool);
+        }
+        self.touch();
+        Ok(())
+    }
+
+    /// Drop the pool ahead of a reopen. Commands arriving in the meantime wait
+    /// for the new pool instead of failing. Returns once every query that was
+    /// already running has finished, so the file can be swapped out.
+    pub async fn close(&self) {
+        self.set_status(DbStatus::Initializing);
+        self.drop_pool();
+        *self.generation.write().await += 1;
+    }
+
+    /// Drop the pool because the vault locked
+    pub fn lock(&self) {
+        self.drop_pool();
+        self.set_status(DbStatus::Locked);
+    }
+
+    fn drop_pool(&self) {
+        if let Ok(mut guard) = self.pool.write() {
+            *guard = None;
+        }
+    }
+
+    pub fn status(&self) -> DbStatus {
+        self.status.borrow().clone()
+    }
+
+    pub fn set_status(&self, status: DbStatus) {
+        self.status.send_replace(status);
+    }
+
+    pub fn pool(&self) -> Result<DbPool, DbError> {
+        self.pool
+            .read()
+            .ok()
+            .and_then(|guard| guard.clone())
+            .ok_or(DbError::Closed)
+    }
+
+    /// Wait until initialization has finished, one way or the other
+    pub async fn wait_ready(&self) -> Result<(), DbError> {
+        let mut rx = self.status.subscribe();
+        let status = tokio::time::timeout(
+            READY_TIMEOUT,
+            rx.wait_for(|s| *s != DbStatus::Initializing),
+        )
+        .await
+        .map_err(|_| DbError::NotReady)?
+        .map_err(|_| DbError::NotReady)?
+        .clone();
+
+        match status {
+            DbStatus::Ready => Ok(()),
+            DbStatus::Locked => Err(VaultError::Locked.into()),
+            DbStatus::Failed { error } => Err(DbError::InitFailed(error)),
+            DbStatus::Initializing => Err(DbError::NotReady),
+        }
+    }
+
+    /// Run a query on a pooled connection off the main thread, once the
+    /// database is ready. Whatever it changed is published to
+    /// `subscribe_changes` afterwards. Counts as user activity for the vault
+    /// auto-lock.
+    pub async fn run<T, F>(&self, f: F) -> Result<T, DbError>
+    where
+        F: FnOnce(&mut Connection) -> Result<T, DbError> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.touch();
+        self.run_background(f).await
+    }
+
+    /// Like `run`, for work nobody asked for just now (housekeeping, tray
+    /// refreshes, extension polling). Doesn't postpone the vault auto-lock.
+    pub async fn run_background<T, F>(&self, f: F) -> Result<T, DbError>
+    where
+        F: FnOnce(&mut Connection) -> Result<T, DbError> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.wait_ready().await?;
+        let feed = self.changes.clone();
+        self.run_unchecked(move |conn| {
+            changes::install(conn)?;
+            let result = f(conn);
+            // A failed command may still have written outside a transaction
+            match changes::drain(conn) {
+                Ok(batch) if !batch.is_empty() => {
+                    let _ = feed.send(batch);
+                }
+                Ok(_) => {}
+                Err(e) => log::warn!("Failed to read library changes: {}", e),
+            }
+            result
+        })
+        .await
+    }
+
+    /// Batches of library changes made through `run`
+    pub fn subscribe_changes(&self) -> broadcast::Receiver<Vec<LibraryChange>> {
+        self.changes.subscribe()
+    }
+
+    /// Like `run`, without waiting for readiness. Only initialization itself
+    /// should need this.
+    async fn run_unchecked<T, F>(&self, f: F) -> Result<T, DbError>
+    where
+        F: FnOnce(&mut Connection) -> Result<T, DbError> + Send + 'static,
+        T: Send + 'static,
+    {
+        // Read before taking the pool, so a close in between is noticed
+        let generation = self.generation.clone();
+        let opened = *generation.read().await;
+        let pool = self.pool()?;
+
+        tauri::async_runtime::spawn_blocking(move || {
+            let current = generation.blocking_read();
+            if *current != opened {
+                return Err(DbError::Closed);
+            }
+            let mut conn = pool.get()?;
+            f(&mut conn)
+        })
+        .await
+        .map_err(|e| DbError::Task(e.to_string()))?
+    }
+
+    /// Record library activity, postponing the vault auto-lock
+    pub fn touch(&self) {
+        if let Ok(mut t) = self.last_activity.lock() {
+            *t = Instant::now();
+        }
+    }
+
+    pub fn idle_for(&self) -> Duration {
+        self.last_activity
+            .lock()
+            .map(|t| t.elapsed())
+            .unwrap_or_default()
+    }
+}
+
+/// Build a connection pool with the per-connection pragmas applied once
+pub fn build_pool(path: &Path, key: Option<String>) -> Result<DbPool, DbError> {
+    let manager =
+        SqliteConnectionManager::file(path).with_init(move |conn| configure(conn, key.as_deref()));
+
+    let pool = r2d2::Pool::builder().max_size(POOL_SIZE).build(manager)?;
+
+    // journal_mode is stored in the file, so setting it once is enough
+    pool.get()?
+        .pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))?;
+
+    Ok(pool)
+}
+
+/// Open a single connection to an existing library, configured like the
+/// pooled ones so it can share the file with a running app
+pub fn open_connection(path: &Path, key: Option<&str>) -> Result<Connection, DbError> {
+    let conn = Connection::open_with_flags(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )?;
+    configure(&conn, key)?;
+    Ok(conn)
+}
+
+fn configure(conn: &Connection, key: Option<&str>) -> rusqlite::Result<()> {
+    // The key has to be the first statement on an encrypted connection
+    if let Some(key) = key {
+        vault::apply_key(conn, key)?;
+    }
+    conn.pragma_update(None, "foreign_keys", true)?;
+    conn.pragma_update(None, "busy_timeout", BUSY_TIMEOUT_MS)?;
+    conn.pragma_update(None, "synchronous", "NORMAL")
 }
 
 pub fn get_db_path(app_handle: &AppHandle) -> Result<PathBuf, DbError> {
@@ -23,13 +298,141 @@ pub fn get_db_path(app_handle: &AppHandle) -> Result<PathBuf, DbError> {
     Ok(app_data_dir.join("promptpack.db"))
 }
 
+/// Open the library and make sure its schema exists.
+///
+/// The outcome is published through the `Database` status so waiting
+/// commands resume, and a failure is also emitted to the frontend together
+/// with the recovery actions it can offer.
 pub async fn init_database(app_handle: &AppHandle) -> Result<(), DbError> {
+    let database = app_handle.state::<Database>();
+    database.set_status(DbStatus::Initializing);
+
+    let result = open_database(app_handle, &database).await;
+    match &result {
+        Ok(()) => {
+            database.set_status(DbStatus::Ready);
+            let _ = app_handle.emit("database-ready", ());
+        }
+        Err(DbError::Vault(VaultError::Locked)) => {
+            log::info!("Vault is locked, database init deferred until unlock");
+            database.lock();
+        }
+        Err(e) => {
+            log::error!("Failed to initialize database: {}", e);
+            database.drop_pool();
+            database.set_status(DbStatus::Failed {
+                error: e.to_string(),
+            });
+            let _ = app_handle.emit(
+                "database-init-failed",
+                DbInitFailure {
+                    error: e.to_string(),
+                    actions: RECOVERY_ACTIONS.to_vec(),
+                },
+            );
+        }
+    }
+    result
+}
+
+async fn open_database(app_handle: &AppHandle, database: &Database) -> Result<(), DbError> {
     let db_path = get_db_path(app_handle)?;
-    let conn = Connection::open(&db_path)?;
 
-    // Create tables
-    conn.execute_batch(
-        r#"
+    // An encrypted library stays closed until the vault is unlocked
+    let key = app_handle.state::<VaultState>().key();
+    if key.is_none() && vault::is_encrypted(&db_path)? {
+        return Err(VaultError::Locked.into());
+    }
+
+    let backup_dir = backup::get_backup_dir(app_handle)?;
+    let keep = app_handle
+        .state::<SettingsState>()
+        .get()
+        .backup
+        .retention_for(BackupKind::PreMigration);
+
+    database.open(&db_path, key.clone())?;
+    database
+        .run_unchecked(move |conn| {
+            if needs_upgrade(conn)? {
+                backup::create_backup(conn, &backup_dir, BackupKind::PreMigration, key.as_deref())?;
+                backup::prune_backups(&backup_dir, BackupKind::PreMigration, keep)?;
+            }
+            migrate(conn)
+        })
+        .await?;
+
+    log::info!("Database initialized at {:?}", db_path);
+    Ok(())
+}
+
+/// Check that the file at `path` is a healthy library database
+pub fn check_database_file(path: &Path, key: Option<&str>) -> Result<(), DbError> {
+    if !path.is_file() {
+        return Err(DbError::Path(format!("{} is not a file", path.display())));
+    }
+
+    let conn = Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    if vault::is_encrypted(path)? {
+        let key = key.ok_or(VaultError::PasswordRequired)?;
+        vault::apply_key(&conn, key)?;
+    }
+
+    let result: String = conn
+        .query_row("PRAGMA quick_check", [], |row| row.get(0))
+        .map_err(|e| match e.sqlite_error_code() {
+            Some(rusqlite::ErrorCode::NotADatabase) => DbError::Corrupt(e.to_string()),
+            _ => DbError::Sqlite(e),
+        })?;
+    if result != "ok" {
+        return Err(DbError::Corrupt(result));
+    }
+    Ok(())
+}
+
+/// Delete the database file and its WAL side files
+pub fn remove_database_files(db_path: &Path) -> Result<(), DbError> {
+    for suffix in ["", "-wal", "-shm"] {
+        let mut path = db_path.as_os_str().to_owned();
+        path.push(suffix);
+        let path = PathBuf::from(path);
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Move the database file and its WAL side files out of the way, keeping
+/// them next to the original for later inspection
+pub fn set_aside(db_path: &Path, reason: &str) -> Result<PathBuf, DbError> {
+    let stamp = chrono::Utc::now().format("%Y%m%d-%H%M%S");
+    let mut aside = db_path.as_os_str().to_owned();
+    aside.push(format!(".{}-{}", reason, stamp));
+    let aside = PathBuf::from(aside);
+
+    if db_path.exists() {
+        std::fs::rename(db_path, &aside)?;
+    }
+    for suffix in ["-wal", "-shm"] {
+        let mut side = db_path.as_os_str().to_owned();
+        side.push(suffix);
+        let side = PathBuf::from(side);
+        if side.exists() {
+            let mut side_aside = aside.as_os_str().to_owned();
+            side_aside.push(suffix);
+            std::fs::rename(side, PathBuf::from(side_aside))?;
+        }
+    }
+
+    Ok(aside)
+}
+
+/// Schema migrations, applied in order. `PRAGMA user_version` records how many
+/// have run, so never edit a shipped entry - append a new one instead.
+const MIGRATIONS: &[&str] = &[
+    // 1: initial schema
+    r#"
         -- Prompts table
         CREATE TABLE IF NOT EXISTS prompts (
             id TEXT PRIMARY KEY,
@@ -120,15 +523,131 @@ pub async fn init_database(app_handle: &AppHandle) -> Result<(), DbError> {
             INSERT INTO prompts_fts(prompts_fts, rowid, text, header) VALUES('delete', OLD.rowid, OLD.text, OLD.header);
             INSERT INTO prompts_fts(rowid, text, header) VALUES (NEW.rowid, NEW.text, NEW.header);
         END;
-        "#,
+
+        -- Clear references left dangling while foreign keys were off
+        UPDATE prompts SET folder_id = NULL
+         WHERE folder_id IS NOT NULL AND folder_id NOT IN (SELECT id FROM folders);
+        UPDATE folders SET parent_id = NULL
+         WHERE parent_id IS NOT NULL AND parent_id NOT IN (SELECT id FROM folders);
+        DELETE FROM prompt_tags
+         WHERE prompt_id NOT IN (SELECT id FROM prompts)
+            OR tag_id NOT IN (SELECT id FROM tags);
+    "#,
+    // 2: soft delete
+    r#"
+        ALTER TABLE prompts ADD COLUMN deleted_at INTEGER;
+        ALTER TABLE folders ADD COLUMN deleted_at INTEGER;
+        CREATE INDEX IF NOT EXISTS idx_prompts_deleted ON prompts(deleted_at);
+        CREATE INDEX IF NOT EXISTS idx_folders_deleted ON folders(deleted_at);
+
+        -- Purged prompts the cloud copy still has to hear about
+        CREATE TABLE IF NOT EXISTS sync_tombstones (
+            prompt_id TEXT PRIMARY KEY,
+            cloud_id TEXT,
+            deleted_at INTEGER NOT NULL
+        );
+    "#,
+    // 3: prompt version history
+    r#"
+        CREATE TABLE IF NOT EXISTS prompt_revisions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            prompt_id TEXT NOT NULL,
+            text TEXT NOT NULL,
+            header TEXT,
+            created_at INTEGER NOT NULL,
+            reverted_from INTEGER,
+            FOREIGN KEY (prompt_id) REFERENCES prompts(id) ON DELETE CASCADE
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_revisions_prompt ON prompt_revisions(prompt_id, id DESC);
+
+        -- Every prompt starts with its current wording as the first revision
+        INSERT INTO prompt_revisions (prompt_id, text, header, created_at)
+        SELECT id, text, header, updated_at FROM prompts;
+
+        CREATE TRIGGER IF NOT EXISTS prompts_revision_ai AFTER INSERT ON prompts BEGIN
+            INSERT INTO prompt_revisions (prompt_id, text, header, created_at)
+            VALUES (NEW.id, NEW.text, NEW.header, NEW.updated_at);
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS prompts_revision_au AFTER UPDATE OF text, header ON prompts
+        WHEN OLD.text IS NOT NEW.text OR OLD.header IS NOT NEW.header BEGIN
+            INSERT INTO prompt_revisions (prompt_id, text, header, created_at)
+            VALUES (NEW.id, NEW.text, NEW.header, NEW.updated_at);
+        END;
+    "#,
+    // 4: smart folders and usage tracking
+    r#"
+        ALTER TABLE prompts ADD COLUMN last_used_at INTEGER;
+        CREATE INDEX IF NOT EXISTS idx_prompts_last_used ON prompts(last_used_at);
+
+        -- Saved searches; `query` is a serialized PromptFilter
+        CREATE TABLE IF NOT EXISTS smart_folders (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            icon TEXT,
+            color TEXT,
+            query TEXT NOT NULL,
+            sort_order INTEGER DEFAULT 0,
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL
+        );
+    "#,
+    // 5: operation journal for undo
+    r#"
+        -- `changes` is a JSON list of before/after entity snapshots
+        CREATE TABLE IF NOT EXISTS operation_journal (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            label TEXT NOT NULL,
+            changes TEXT NOT NULL,
+            created_at INTEGER NOT NULL,
+            undone INTEGER NOT NULL DEFAULT 0
+        );
+    "#,
+];
+
+fn schema_version(conn: &Connection) -> Result<usize, DbError> {
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    Ok(version as usize)
+}
+
+/// Whether an existing library is about to be migrated. A brand new file has
+/// nothing worth backing up first.
+pub fn needs_upgrade(conn: &Connection) -> Result<bool, DbError> {
+    let has_tables: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'prompts')",
+        [],
+        |row| row.get(0),
     )?;
+    Ok(has_tables && schema_version(conn)? < MIGRATIONS.len())
+}
+
+/// Whether the schema matches this build. Clients that don't own the library
+/// check this instead of migrating it.
+pub fn is_current(conn: &Connection) -> Result<bool, DbError> {
+    Ok(schema_version(conn)? == MIGRATIONS.len())
+}
+
+/// Apply pending migrations, each in its own transaction
+pub fn migrate(conn: &mut Connection) -> Result<(), DbError> {
+    let version = schema_version(conn)?;
+
+    for (index, sql) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(sql)?;
+        tx.pragma_update(None, "user_version", (index + 1) as i64)?;
+        tx.commit()?;
+        log::info!("Migrated database to schema version {}", index + 1);
+    }
 
-    log::info!("Database initialized at {:?}", db_path);
     Ok(())
 }
 
-pub fn get_connection(app_handle: &AppHandle) -> Result<Connection, DbError> {
-    let db_path = get_db_path(app_handle)?;
-    let conn = Connection::open(&db_path)?;
-    Ok(conn)
+/// A migrated in-memory library for unit tests
+#[cfg(test)]
+pub fn open_in_memory() -> Connection {
+    let mut conn = Connection::open_in_memory().unwrap();
+    conn.pragma_update(None, "foreign_keys", true).unwrap();
+    migrate(&mut conn).unwrap();
+    conn
 }
@@ -0,0 +1,454 @@
+//! `promptpack://` links: sign-in callbacks, pack imports, opening a prompt
+//! and adding one.
+//!
+//! Links can come from any web page, so nothing is written to the library
+//! until the user confirms: imports go through the import preview, and
+//! added prompts through a native dialog.
+
+use crate::db::Database;
+use crate::error::CommandError;
+use crate::repository::{CreatePromptInput, Prompt, PromptRepository};
+use crate::tray;
+use crate::validation;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, Manager, Url};
+use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
+use thiserror::Error;
+
+const SCHEME: &str = "promptpack";
+
+/// Hosts packs may be downloaded from, including their subdomains
+const ALLOWED_IMPORT_HOSTS: &[&str] = &["pmtpk.com"];
+
+/// Largest pack a link may download
+const MAX_PACK_BYTES: usize = 10 * 1024 * 1024;
+
+/// Redirects followed while downloading a pack
+const MAX_REDIRECTS: usize = 10;
+
+/// Longest prompt id a link may carry
+const MAX_ID_CHARS: usize = 64;
+
+/// Characters of an added prompt shown in the confirmation dialog
+const CONFIRM_PREVIEW_CHARS: usize = 300;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum DeepLinkError {
+    #[error("Not a PromptPack link")]
+    Scheme,
+    #[error("Unknown link \"{0}\"")]
+    UnknownRoute(String),
+    #[error("Missing parameter \"{0}\"")]
+    Missing(&'static str),
+    #[error("Invalid parameter \"{0}\": {1}")]
+    Invalid(&'static str, String),
+    #[error("Packs can only be imported over https from {}", ALLOWED_IMPORT_HOSTS.join(", "))]
+    HostNotAllowed,
+}
+
+/// A validated link
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeepLink {
+    /// `promptpack://auth?token=...`
+    Auth { token: String },
+    /// `promptpack://import?url=...`
+    Import { url: Url },
+    /// `promptpack://prompt/<id>`
+    Prompt { id: String },
+    /// `promptpack://add?text=...&header=...`
+    Add {
+        text: String,
+        header: Option<String>,
+    },
+}
+
+/// Something the frontend should show in response to a link
+#[derive(Debug, Serialize, Clone)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum DeepLinkAction {
+    /// Open the import preview for a downloaded pack
+    ImportPreview {
+        url: String,
+        data: Vec<u8>,
+    },
+    OpenPrompt {
+        id: String,
+    },
+}
+
+/// Actions waiting for the frontend. Links can arrive before it is loaded,
+/// so it is told to come and take them rather than sent them directly.
+#[derive(Default)]
+pub struct DeepLinkState {
+    pending: Mutex<Vec<DeepLinkAction>>,
+}
+
+impl DeepLinkState {
+    pub fn take(&self) -> Vec<DeepLinkAction> {
+        self.pending
+            .lock()
+            .map(|mut p| std::mem::take(&mut *p))
+            .unwrap_or_default()
+    }
+
+    fn push(&self, action: DeepLinkAction) {
+        if let Ok(mut p) = self.pending.lock() {
+            p.push(action);
+        }
+    }
+}
+
+pub fn parse(url: &Url) -> Result<DeepLink, DeepLinkError> {
+    if url.scheme() != SCHEME {
+        return Err(DeepLinkError::Scheme);
+    }
+
+    let route = url.host_str().unwrap_or_default();
+    let segments: Vec<&str> = url
+        .path_segments()
+        .map(|s| s.filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default();
+    let query: HashMap<String, String> = url.query_pairs().into_owned().collect();
+    let param = |name| query.get(name).map(|v| v.trim()).filter(|v| !v.is_empty());
+
+    match (route, segments.as_slice()) {
+        ("auth", []) => Ok(DeepLink::Auth {
+            token: param("token")
+                .ok_or(DeepLinkError::Missing("token"))?
+                .to_string(),
+        }),
+        ("import", []) => Ok(DeepLink::Import {
+            url: import_url(param("url").ok_or(DeepLinkError::Missing("url"))?)?,
+        }),
+        ("prompt", [id]) => Ok(DeepLink::Prompt { id: prompt_id(id)? }),
+        ("add", []) => {
+            let text = param("text").ok_or(DeepLinkError::Missing("text"))?;
+            let header = param("header");
+            within("text", text, validation::MAX_TEXT_CHARS)?;
+            if let Some(header) = header {
+                within("header", header, validation::MAX_HEADER_CHARS)?;
+            }
+            Ok(DeepLink::Add {
+                text: text.to_string(),
+                header: header.map(str::to_string),
+            })
+        }
+        _ => Err(DeepLinkError::UnknownRoute(
+            format!("{route}/{}", segments.join("/"))
+                .trim_end_matches('/')
+                .to_string(),
+        )),
+    }
+}
+
+fn import_url(value: &str) -> Result<Url, DeepLinkError> {
+    let url = Url::parse(value).map_err(|e| DeepLinkError::Invalid("url", e.to_string()))?;
+    if is_allowed_import_url(&url) {
+        Ok(url)
+    } else {
+        Err(DeepLinkError::HostNotAllowed)
+    }
+}
+
+fn is_allowed_import_url(url: &Url) -> bool {
+    let host = url.host_str().unwrap_or_default();
+    url.scheme() == "https"
+        && ALLOWED_IMPORT_HOSTS
+            .iter()
+            .any(|allowed| host == *allowed || host.ends_with(&format!(".{allowed}")))
+}
+
+fn prompt_id(value: &str) -> Result<String, DeepLinkError> {
+    let valid = value.len() <= MAX_ID_CHARS
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(value.to_string())
+    } else {
+        Err(DeepLinkError::Invalid("id", "Not a prompt id".to_string()))
+    }
+}
+
+fn within(name: &'static str, value: &str, max_chars: usize) -> Result<(), DeepLinkError> {
+    if value.chars().count() > max_chars {
+        return Err(DeepLinkError::Invalid(
+            name,
+            format!("Must be at most {max_chars} characters"),
+        ));
+    }
+    Ok(())
+}
+
+/// Act on a link the OS handed to the app
+pub fn handle(app_handle: &AppHandle, url: &Url) {
+    let link = match parse(url) {
+        Ok(link) => link,
+        Err(e) => return report(app_handle, e.into()),
+    };
+
+    match link {
+        DeepLink::Auth { token } => {
+            let _ = app_handle.emit("auth-callback", token);
+        }
+        DeepLink::Prompt { id } => show(app_handle, DeepLinkAction::OpenPrompt { id }),
+        DeepLink::Import { url } => {
+            let handle = app_handle.clone();
+            tauri::async_runtime::spawn(async move {
+                match download_pack(&url).await {
+                    Ok(data) => show(
+                        &handle,
+                        DeepLinkAction::ImportPreview {
+                            url: url.to_string(),
+                            data,
+                        },
+                    ),
+                    Err(e) => report(&handle, e),
+                }
+            });
+        }
+        DeepLink::Add { text, header } => confirm_add(app_handle, text, header),
+    }
+}
+
+/// Queue an action for the frontend and bring the library to the front
+fn show(app_handle: &AppHandle, action: DeepLinkAction) {
+    app_handle.state::<DeepLinkState>().push(action);
+    tray::show_main_window(app_handle);
+    let _ = app_handle.emit("deep-link-received", ());
+}
+
+fn report(app_handle: &AppHandle, error: CommandError) {
+    log::warn!("Ignored deep link: {}", error);
+    let _ = app_handle.emit("deep-link-failed", error);
+}
+
+/// Download a pack. Redirects are only followed to allowed hosts, and the
+/// body is read in chunks so an oversized pack is dropped before it is held
+/// in full.
+async fn download_pack(url: &Url) -> Result<Vec<u8>, CommandError> {
+    let too_large = || {
+        CommandError::validation(format!(
+            "Packs can be at most {} MB",
+            MAX_PACK_BYTES / 1024 / 1024
+        ))
+    };
+
+    let client = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(30))
+        .redirect(reqwest::redirect::Policy::custom(|attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                attempt.error("Too many redirects")
+            } else if is_allowed_import_url(attempt.url()) {
+                attempt.follow()
+            } else {
+                attempt.error(DeepLinkError::HostNotAllowed)
+            }
+        }))
+        .build()?;
+    let mut response = client
+        .get(url.clone())
+        .send()
+        .await
+        .map_err(|e| {
+            if e.is_redirect() {
+                CommandError::from(DeepLinkError::HostNotAllowed)
+            } else {
+                e.into()
+            }
+        })?
+        .error_for_status()?;
+    if response
+        .content_length()
+        .is_some_and(|len| len > MAX_PACK_BYTES as u64)
+    {
+        return Err(too_large());
+    }
+
+    let mut data = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if data.len() + chunk.len() > MAX_PACK_BYTES {
+            return Err(too_large());
+        }
+        data.extend_from_slice(&chunk);
+    }
+    Ok(data)
+}
+
+/// Ask before adding a prompt from a link, then open it
+fn confirm_add(app_handle: &AppHandle, text: String, header: Option<String>) {
+    let mut preview: String = text.chars().take(CONFIRM_PREVIEW_CHARS).collect();
+    if preview.len() < text.len() {
+        preview.push('…');
+    }
+    let message = match &header {
+        Some(header) => format!("{header}\n\n{preview}"),
+        None => preview,
+    };
+
+    let handle = app_handle.clone();
+    app_handle
+        .dialog()
+        .message(format!("A link wants to add this prompt:\n\n{message}"))
+        .title("Add Prompt")
+        .kind(MessageDialogKind::Info)
+        .buttons(MessageDialogButtons::OkCancelCustom(
+            "Add Prompt".to_string(),
+            "Cancel".to_string(),
+        ))
+        .show(move |confirmed| {
+            if !confirmed {
+                return;
+            }
+            tauri::async_runtime::spawn(async move {
+                match add_prompt(&handle, text, header).await {
+                    Ok(prompt) => show(&handle, DeepLinkAction::OpenPrompt { id: prompt.id }),
+                    Err(e) => report(&handle, e),
+                }
+            });
+        });
+}
+
+async fn add_prompt(
+    app_handle: &AppHandle,
+    text: String,
+    header: Option<String>,
+) -> Result<Prompt, CommandError> {
+    let input = CreatePromptInput {
+        text,
+        header,
+        ..Default::default()
+    };
+    app_handle
+        .state::<Database>()
+        .run(move |conn| {
+            let now = chrono::Utc::now().timestamp_millis();
+            let tx = conn.transaction()?;
+            let prompt = PromptRepository::new(&tx).create(input, now)?;
+            tx.commit()?;
+            Ok(prompt)
+        })
+        .await
+        .map_err(CommandError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(link: &str) -> Result<DeepLink, DeepLinkError> {
+        parse(&Url::parse(link).unwrap())
+    }
+
+    #[test]
+    fn routes_each_link() {
+        assert_eq!(
+            parse_str("promptpack://auth?token=abc%2E123"),
+            Ok(DeepLink::Auth {
+                token: "abc.123".to_string()
+            })
+        );
+        assert_eq!(
+            parse_str("promptpack://prompt/4f1c-9a_b/"),
+            Ok(DeepLink::Prompt {
+                id: "4f1c-9a_b".to_string()
+            })
+        );
+        assert_eq!(
+            parse_str("promptpack://add?text=Summarize+%7BTopic%7D&header=%20Summary%20"),
+            Ok(DeepLink::Add {
+                text: "Summarize {Topic}".to_string(),
+                header: Some("Summary".to_string()),
+            })
+        );
+        assert_eq!(
+            parse_str("promptpack://add?text=Hi&header="),
+            Ok(DeepLink::Add {
+                text: "Hi".to_string(),
+                header: None,
+            })
+        );
+    }
+
+    #[test]
+    fn import_only_from_allowed_hosts() {
+        let import = |url: &str| {
+            parse_str(&format!(
+                "promptpack://import?url={}",
+                urlencoding::encode(url)
+            ))
+        };
+
+        for url in [
+            "https://pmtpk.com/packs/writing.pmtpk",
+            "https://cdn.pmtpk.com/p/1?v=2",
+        ] {
+            assert_eq!(
+                import(url),
+                Ok(DeepLink::Import {
+                    url: Url::parse(url).unwrap()
+                })
+            );
+        }
+        for url in [
+            "http://pmtpk.com/pack.pmtpk",
+            "https://evilpmtpk.com/pack.pmtpk",
+            "https://pmtpk.com.evil.io/pack.pmtpk",
+            "https://pmtpk.com@evil.io/pack.pmtpk",
+            "file:///etc/passwd",
+        ] {
+            assert_eq!(import(url), Err(DeepLinkError::HostNotAllowed), "{url}");
+        }
+        assert!(matches!(
+            import("not a url"),
+            Err(DeepLinkError::Invalid("url", _))
+        ));
+    }
+
+    #[test]
+    fn rejects_missing_and_invalid_parameters() {
+        assert_eq!(
+            parse_str("promptpack://auth"),
+            Err(DeepLinkError::Missing("token"))
+        );
+        assert_eq!(
+            parse_str("promptpack://import"),
+            Err(DeepLinkError::Missing("url"))
+        );
+        assert_eq!(
+            parse_str("promptpack://add?text=%20%20&header=Empty"),
+            Err(DeepLinkError::Missing("text"))
+        );
+        assert!(matches!(
+            parse_str("promptpack://prompt/..%2F..%2Fsecrets"),
+            Err(DeepLinkError::Invalid("id", _))
+        ));
+        let long_header = "h".repeat(validation::MAX_HEADER_CHARS + 1);
+        assert!(matches!(
+            parse_str(&format!("promptpack://add?text=Hi&header={long_header}")),
+            Err(DeepLinkError::Invalid("header", _))
+        ));
+    }
+
+    #[test]
+    fn rejects_unknown_links() {
+        assert_eq!(
+            parse_str("https://pmtpk.com/add?text=Hi"),
+            Err(DeepLinkError::Scheme)
+        );
+        assert_eq!(
+            parse_str("promptpack://delete/all"),
+            Err(DeepLinkError::UnknownRoute("delete/all".to_string()))
+        );
+        assert_eq!(
+            parse_str("promptpack://prompt"),
+            Err(DeepLinkError::UnknownRoute("prompt".to_string()))
+        );
+        assert_eq!(
+            parse_str("promptpack://prompt/a/b"),
+            Err(DeepLinkError::UnknownRoute("prompt/a/b".to_string()))
+        );
+    }
+}
@@ -0,0 +1,236 @@
+use crate::auth::AuthError;
+use crate::crypto::CryptoError;
+use crate::db::DbError;
+use crate::deep_link::DeepLinkError;
+use crate::interchange::InterchangeError;
+use crate::markdown::MarkdownError;
+use crate::settings::SettingsError;
+use crate::vault::VaultError;
+use rusqlite::ErrorCode as SqliteCode;
+use serde::Serialize;
+use serde_json::{json, Value};
+use thiserror::Error;
+
+/// Stable error codes the frontend can branch on
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    NotFound,
+    /// Bad input from the caller
+    Validation,
+    PasswordRequired,
+    InvalidPassword,
+    VaultLocked,
+    VaultNotEnabled,
+    VaultAlreadyEnabled,
+    /// Another connection holds the lock; worth retrying
+    DatabaseBusy,
+    /// Still opening, or closed for a swap; worth retrying
+    DatabaseNotReady,
+    DatabaseInitFailed,
+    DatabaseCorrupt,
+    Database,
+    /// A pack or backup file that can't be read
+    InvalidFile,
+    Crypto,
+    InvalidToken,
+    TokenExpired,
+    Network,
+    Io,
+    Settings,
+    /// Undo or redo found a row changed since the operation
+    Conflict,
+    /// A global hotkey is invalid, duplicated or owned by another app
+    ShortcutConflict,
+    Clipboard,
+    Internal,
+}
+
+/// Error returned by every command, serialized as
+/// `{ code, message, details }`
+#[derive(Error, Debug, Serialize, Clone)]
+#[error("{message}")]
+pub struct CommandError {
+    pub code: ErrorCode,
+    pub message: String,
+    pub details: Option<Value>,
+}
+
+impl CommandError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    pub fn validation(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Validation, message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Internal, message)
+    }
+
+    pub fn with_details(mut self, details: Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    /// Tell the frontend a retry may succeed
+    fn retryable(self) -> Self {
+        self.with_details(json!({ "retryable": true }))
+    }
+}
+
+impl From<DbError> for CommandError {
+    fn from(e: DbError) -> Self {
+        let message = e.to_string();
+        match e {
+            DbError::Sqlite(e) => e.into(),
+            DbError::Vault(e) => e.into(),
+            DbError::NotFound(what) => {
+                Self::new(ErrorCode::NotFound, message).with_details(json!({ "entity": what }))
+            }
+            DbError::Invalid(_) => Self::new(ErrorCode::Validation, message),
+            DbError::Validation(e) => Self::new(ErrorCode::Validation, message)
+                .with_details(json!({ "fields": e.fields })),
+            DbError::Io(_) | DbError::Path(_) => Self::new(ErrorCode::Io, message),
+            DbError::Pool(_) => Self::new(ErrorCode::DatabaseBusy, message).retryable(),
+            DbError::Closed | DbError::NotReady => {
+                Self::new(ErrorCode::DatabaseNotReady, message).retryable()
+            }
+            DbError::InitFailed(_) => Self::new(ErrorCode::DatabaseInitFailed, message),
+            DbError::Corrupt(_) => Self::new(ErrorCode::DatabaseCorrupt, message),
+            DbError::Conflict(_) => Self::new(ErrorCode::Conflict, message),
+            DbError::Task(_) => Self::new(ErrorCode::Internal, message),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for CommandError {
+    fn from(e: rusqlite::Error) -> Self {
+        let message = e.to_string();
+        match e.sqlite_error_code() {
+            Some(SqliteCode::DatabaseBusy | SqliteCode::DatabaseLocked) => {
+                Self::new(ErrorCode::DatabaseBusy, message).retryable()
+            }
+            Some(SqliteCode::DatabaseCorrupt | SqliteCode::NotADatabase) => {
+                Self::new(ErrorCode::DatabaseCorrupt, message)
+            }
+            Some(SqliteCode::ConstraintViolation) => Self::new(ErrorCode::Validation, message),
+            _ => Self::new(ErrorCode::Database, message),
+        }
+    }
+}
+
+impl From<VaultError> for CommandError {
+    fn from(e: VaultError) -> Self {
+        let message = e.to_string();
+        match e {
+            VaultError::Locked => Self::new(ErrorCode::VaultLocked, message),
+            VaultError::NotEnabled => Self::new(ErrorCode::VaultNotEnabled, message),
+            VaultError::AlreadyEnabled => Self::new(ErrorCode::VaultAlreadyEnabled, message),
+            VaultError::PasswordRequired => Self::new(ErrorCode::PasswordRequired, message),
+            VaultError::InvalidPassword => Self::new(ErrorCode::InvalidPassword, message),
+            VaultError::InUse => Self::new(ErrorCode::DatabaseBusy, message).retryable(),
+            VaultError::Sqlite(e) => e.into(),
+            VaultError::Io(_) => Self::new(ErrorCode::Io, message),
+        }
+    }
+}
+
+impl From<CryptoError> for CommandError {
+    fn from(e: CryptoError) -> Self {
+        let code = match e {
+            CryptoError::PasswordRequired => ErrorCode::PasswordRequired,
+            CryptoError::InvalidPassword => ErrorCode::InvalidPassword,
+            CryptoError::InvalidFormat
+            | CryptoError::InvalidVersion(_)
+            | CryptoError::HashMismatch => ErrorCode::InvalidFile,
+            _ => ErrorCode::Crypto,
+        };
+        Self::new(code, e.to_string())
+    }
+}
+
+impl From<AuthError> for CommandError {
+    fn from(e: AuthError) -> Self {
+        let code = match e {
+            AuthError::InvalidToken => ErrorCode::InvalidToken,
+            AuthError::TokenExpired => ErrorCode::TokenExpired,
+        };
+        Self::new(code, e.to_string())
+    }
+}
+
+impl From<SettingsError> for CommandError {
+    fn from(e: SettingsError) -> Self {
+        Self::new(ErrorCode::Settings, e.to_string())
+    }
+}
+
+impl From<DeepLinkError> for CommandError {
+    fn from(e: DeepLinkError) -> Self {
+        Self::validation(e.to_string())
+    }
+}
+
+impl From<InterchangeError> for CommandError {
+    fn from(e: InterchangeError) -> Self {
+        let code = match e {
+            InterchangeError::MissingColumn(_) | InterchangeError::Delimiter => {
+                ErrorCode::Validation
+            }
+            _ => ErrorCode::InvalidFile,
+        };
+        Self::new(code, e.to_string())
+    }
+}
+
+impl From<MarkdownError> for CommandError {
+    fn from(e: MarkdownError) -> Self {
+        let code = match e {
+            MarkdownError::Io { .. } => ErrorCode::Io,
+            MarkdownError::NotADirectory(_) => ErrorCode::Validation,
+            _ => ErrorCode::InvalidFile,
+        };
+        Self::new(code, e.to_string())
+    }
+}
+
+impl From<tauri_plugin_clipboard_manager::Error> for CommandError {
+    fn from(e: tauri_plugin_clipboard_manager::Error) -> Self {
+        Self::new(ErrorCode::Clipboard, e.to_string())
+    }
+}
+
+impl From<std::io::Error> for CommandError {
+    fn from(e: std::io::Error) -> Self {
+        Self::new(ErrorCode::Io, e.to_string())
+    }
+}
+
+impl From<reqwest::Error> for CommandError {
+    fn from(e: reqwest::Error) -> Self {
+        let error = Self::new(ErrorCode::Network, e.to_string());
+        if e.is_timeout() || e.is_connect() {
+            error.retryable()
+        } else {
+            error
+        }
+    }
+}
+
+impl From<serde_json::Error> for CommandError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::validation(e.to_string())
+    }
+}
+
+impl From<tauri::Error> for CommandError {
+    fn from(e: tauri::Error) -> Self {
+        Self::internal(e.to_string())
+    }
+}
@@ -0,0 +1,286 @@
+use crate::db::DbError;
+use crate::folders;
+use base64::Engine;
+use rusqlite::types::{ToSqlOutput, Value, ValueRef};
+use rusqlite::ToSql;
+use serde::{Deserialize, Serialize};
+
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 500;
+
+/// Positional parameters collected while building a query
+pub type SqlParams = Vec<Box<dyn ToSql>>;
+
+/// Criteria for selecting prompts. Every field is optional and set fields
+/// must all match. Smart folders store one of these as JSON.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct PromptFilter {
+    /// Full-text search over text and header
+    pub text: Option<String>,
+    /// Tag names, all of which a prompt must carry
+    pub tags: Vec<String>,
+    pub source: Option<String>,
+    /// Host of the prompt's URL, subdomains included
+    pub url_domain: Option<String>,
+    pub folder_id: Option<String>,
+    /// Also match prompts in subfolders of `folder_id`
+    pub include_subfolders: bool,
+    pub favorite: Option<bool>,
+    pub created_after: Option<i64>,
+    pub created_before: Option<i64>,
+    pub updated_after: Option<i64>,
+    pub updated_before: Option<i64>,
+    /// Used within this many days of when the filter is evaluated
+    pub used_within_days: Option<u32>,
+    pub min_use_count: Option<i32>,
+    pub max_use_count: Option<i32>,
+}
+
+impl PromptFilter {
+    /// SQL conditions on `prompts` with their positional (`?`) parameters.
+    /// Trashed prompts never match.
+    pub fn to_sql(&self, now: i64) -> (String, SqlParams) {
+        let mut conditions = vec!["prompts.deleted_at IS NULL".to_string()];
+        let mut params: SqlParams = Vec::new();
+
+        if let Some(fts) = self
+            .text
+            .as_deref()
+            .map(fts_query)
+            .filter(|q| !q.is_empty())
+        {
+            conditions.push(
+                "prompts.rowid IN (SELECT rowid FROM prompts_fts WHERE prompts_fts MATCH ?)"
+                    .to_string(),
+            );
+            params.push(Box::new(fts));
+        }
+        for tag in &self.tags {
+            conditions.push(
+                "EXISTS (SELECT 1 FROM prompt_tags pt JOIN tags t ON t.id = pt.tag_id
+                         WHERE pt.prompt_id = prompts.id AND t.name = ? COLLATE NOCASE)"
+                    .to_string(),
+            );
+            params.push(Box::new(tag.clone()));
+        }
+        if let Some(source) = &self.source {
+            conditions.push("prompts.source = ?".to_string());
+            params.push(Box::new(source.clone()));
+        }
+        if let Some(domain) = &self.url_domain {
+            let domain = domain.trim().trim_start_matches("www.").to_lowercase();
+            conditions.push(format!(
+                "(instr(prompts.url, '://') > 0 AND ({host} = ? OR {host} LIKE ? ESCAPE '\\'))",
+                host = url_host_sql()
+            ));
+            params.push(Box::new(domain.clone()));
+            params.push(Box::new(format!("%.{}", escape_like(&domain))));
+        }
+        if let Some(folder_id) = &self.folder_id {
+            if self.include_subfolders {
+                // The subtree query takes its root as ?1; here it is positional
+                conditions.push(format!(
+                    "prompts.folder_id IN ({})",
+                    folders::SUBTREE.replace("?1", "?")
+                ));
+            } else {
+                conditions.push("prompts.folder_id = ?".to_string());
+            }
+            params.push(Box::new(folder_id.clone()));
+        }
+        if let Some(favorite) = self.favorite {
+            conditions.push("prompts.is_favorite = ?".to_string());
+            params.push(Box::new(i32::from(favorite)));
+        }
+
+        let ranges = [
+            ("prompts.created_at >= ?", self.created_after),
+            ("prompts.created_at < ?", self.created_before),
+            ("prompts.updated_at >= ?", self.updated_after),
+            ("prompts.updated_at < ?", self.updated_before),
+            (
+                "prompts.last_used_at >= ?",
+                self.used_within_days
+                    .map(|days| now - i64::from(days) * DAY_MS),
+            ),
+        ];
+        for (condition, value) in ranges {
+            if let Some(value) = value {
+                conditions.push(condition.to_string());
+                params.push(Box::new(value));
+            }
+        }
+
+        if let Some(min) = self.min_use_count {
+            conditions.push("prompts.use_count >= ?".to_string());
+            params.push(Box::new(min));
+        }
+        if let Some(max) = self.max_use_count {
+            conditions.push("prompts.use_count <= ?".to_string());
+            params.push(Box::new(max));
+        }
+
+        (conditions.join(" AND "), params)
+    }
+}
+
+/// Turn free text into an FTS5 query where every word must match as a prefix
+pub fn fts_query(input: &str) -> String {
+    input
+        .split_whitespace()
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Lowercased host of `prompts.url`, without scheme, path or port
+fn url_host_sql() -> String {
+    let rest = "substr(prompts.url, instr(prompts.url, '://') + 3)";
+    let authority = format!("substr({rest}, 1, instr({rest} || '/', '/') - 1)");
+    format!("lower(substr({authority}, 1, instr({authority} || ':', ':') - 1))")
+}
+
+/// Escape `%`, `_` and the escape character itself for a LIKE pattern
+fn escape_like(input: &str) -> String {
+    input
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SortField {
+    #[default]
+    CreatedAt,
+    UpdatedAt,
+    UseCount,
+    Header,
+    LastUsedAt,
+}
+
+impl SortField {
+    /// Never NULL, so keyset comparisons behave
+    fn expr(self) -> &'static str {
+        match self {
+            SortField::CreatedAt => "prompts.created_at",
+            SortField::UpdatedAt => "prompts.updated_at",
+            SortField::UseCount => "COALESCE(prompts.use_count, 0)",
+            SortField::Header => "COALESCE(prompts.header, '') COLLATE NOCASE",
+            SortField::LastUsedAt => "COALESCE(prompts.last_used_at, 0)",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortDirection {
+    Asc,
+    #[default]
+    Desc,
+}
+
+/// One page of a prompt listing
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct PromptPageQuery {
+    #[serde(flatten)]
+    pub filter: PromptFilter,
+    pub sort: SortField,
+    pub direction: SortDirection,
+    /// `next_cursor` of the previous page
+    pub cursor: Option<String>,
+    pub limit: Option<u32>,
+    /// Return a short preview instead of the full text
+    pub preview: bool,
+}
+
+/// Sort value of a row, as carried in a cursor
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum SortKey {
+    Integer(i64),
+    Text(String),
+}
+
+impl SortKey {
+    pub fn from_sql(value: ValueRef<'_>) -> Option<Self> {
+        match value {
+            ValueRef::Integer(i) => Some(SortKey::Integer(i)),
+            ValueRef::Text(t) => Some(SortKey::Text(String::from_utf8_lossy(t).into_owned())),
+            _ => None,
+        }
+    }
+}
+
+impl ToSql for SortKey {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(match self {
+            SortKey::Integer(i) => ToSqlOutput::Owned(Value::Integer(*i)),
+            SortKey::Text(t) => ToSqlOutput::Borrowed(ValueRef::Text(t.as_bytes())),
+        })
+    }
+}
+
+/// Position after the last row of a page. Opaque to the frontend.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Cursor {
+    pub sort: SortField,
+    pub key: SortKey,
+    pub id: String,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        let json = serde_json::to_vec(self).unwrap_or_default();
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(json)
+    }
+
+    pub fn decode(cursor: &str) -> Result<Self, DbError> {
+        base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(cursor)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .ok_or_else(|| DbError::Invalid("Invalid page cursor".to_string()))
+    }
+}
+
+impl PromptPageQuery {
+    pub fn page_size(&self) -> u32 {
+        self.limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE)
+    }
+
+    pub fn sort_expr(&self) -> &'static str {
+        self.sort.expr()
+    }
+
+    /// WHERE conditions including the cursor position, the ORDER BY clause,
+    /// and the parameters for the conditions
+    pub fn to_sql(&self, now: i64) -> Result<(String, String, SqlParams), DbError> {
+        let (mut conditions, mut params) = self.filter.to_sql(now);
+        let expr = self.sort.expr();
+        let (op, dir) = match self.direction {
+            SortDirection::Asc => (">", "ASC"),
+            SortDirection::Desc => ("<", "DESC"),
+        };
+
+        if let Some(cursor) = &self.cursor {
+            let cursor = Cursor::decode(cursor)?;
+            if cursor.sort != self.sort {
+                return Err(DbError::Invalid(
+                    "Page cursor belongs to a different sort order".to_string(),
+                ));
+            }
+            conditions.push_str(&format!(" AND ({expr}, prompts.id) {op} (?, ?)"));
+            params.push(Box::new(cursor.key));
+            params.push(Box::new(cursor.id));
+        }
+
+        let order = format!("{expr} {dir}, prompts.id {dir}");
+        Ok((conditions, order, params))
+    }
+}
@@ -0,0 +1,469 @@
+use crate::db::DbError;
+use crate::journal::EntityKind;
+use crate::trash;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Ids of the folder `?1` and every folder nested below it
+pub const SUBTREE: &str = "WITH RECURSIVE subtree(id) AS (
+                               SELECT ?1
+                               UNION
+                               SELECT f.id FROM folders f JOIN subtree s ON f.parent_id = s.id
+                           )
+                           SELECT id FROM subtree";
+
+/// What happens to a folder's prompts and subfolders when it is deleted
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum DeleteFolderMode {
+    /// Trash the folder together with its subfolders and their prompts
+    #[default]
+    Trash,
+    /// Hand the contents up to the folder's own parent
+    MoveToParent,
+    /// Hand the contents to another folder
+    MoveTo { folder_id: String },
+    /// Only delete the folder if it holds no prompts or subfolders
+    RefuseIfNotEmpty,
+}
+
+#[derive(Debug, Serialize, Clone, Default, PartialEq, Eq)]
+pub struct DeleteFolderResult {
+    pub folders_trashed: usize,
+    pub prompts_trashed: usize,
+    pub folders_moved: usize,
+    pub prompts_moved: usize,
+}
+
+/// Fail unless `id` is a folder that is not in the trash
+pub fn ensure_live(conn: &Connection, id: &str, what: &'static str) -> Result<(), DbError> {
+    let live: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM folders WHERE id = ?1 AND deleted_at IS NULL)",
+        [id],
+        |row| row.get(0),
+    )?;
+    if !live {
+        return Err(DbError::NotFound(what));
+    }
+    Ok(())
+}
+
+/// Whether `candidate` is `root` itself or nested anywhere below it
+pub fn is_in_subtree(conn: &Connection, root: &str, candidate: &str) -> Result<bool, DbError> {
+    Ok(conn.query_row(
+        &format!("SELECT EXISTS (SELECT 1 FROM ({SUBTREE}) WHERE id = ?2)"),
+        [root, candidate],
+        |row| row.get(0),
+    )?)
+}
+
+/// Folder names from the top level down to each live folder, keyed by id
+pub fn paths(conn: &Connection) -> Result<HashMap<String, Vec<String>>, DbError> {
+    let mut stmt =
+        conn.prepare("SELECT id, name, parent_id FROM folders WHERE deleted_at IS NULL")?;
+    let folders: HashMap<String, (String, Option<String>)> = stmt
+        .query_map([], |row| Ok((row.get(0)?, (row.get(1)?, row.get(2)?))))?
+        .collect::<Result<_, _>>()?;
+
+    let mut paths = HashMap::new();
+    for id in folders.keys() {
+        let mut path = Vec::new();
+        let mut next = Some(id);
+        // Stop at a trashed parent, or a cycle in a damaged library
+        while let Some((name, parent_id)) = next.and_then(|id| folders.get(id)) {
+            if path.len() == folders.len() {
+                break;
+            }
+            path.push(name.clone());
+            next = parent_id.as_ref();
+        }
+        path.reverse();
+        paths.insert(id.clone(), path);
+    }
+    Ok(paths)
+}
+
+/// Sort order that places a new folder last among its siblings
+pub fn next_sort_order(conn: &Connection, parent_id: Option<&str>) -> Result<i32, DbError> {
+    let max_order: i32 = conn.query_row(
+        "SELECT COALESCE(MAX(sort_order), 0) FROM folders
+         WHERE parent_id IS ?1 AND deleted_at IS NULL",
+        [parent_id],
+        |row| row.get(0),
+    )?;
+    Ok(max_order + 1)
+}
+
+/// Move a folder under `parent_id` (None = top level) at `position` among its
+/// new siblings, or at the end
+pub fn move_folder(
+    conn: &Connection,
+    id: &str,
+    parent_id: Option<&str>,
+    position: Option<usize>,
+) -> Result<(), DbError> {
+    ensure_live(conn, id, "Folder")?;
+    if let Some(parent_id) = parent_id {
+        ensure_live(conn, parent_id, "Parent folder")?;
+        if is_in_subtree(conn, id, parent_id)? {
+            return Err(DbError::Invalid(
+                "A folder cannot be moved into itself or one of its subfolders".to_string(),
+            ));
+        }
+    }
+
+    conn.execute(
+        "UPDATE folders SET parent_id = ?2 WHERE id = ?1",
+        rusqlite::params![id, parent_id],
+    )?;
+
+    let mut siblings = sibling_ids(conn, parent_id)?;
+    siblings.retain(|s| s != id);
+    let position = position.unwrap_or(siblings.len()).min(siblings.len());
+    siblings.insert(position, id.to_string());
+    write_order(conn, &siblings)
+}
+
+/// Put the children of `parent_id` in the given order. Siblings missing from
+/// `ordered_ids` keep their relative order after the listed ones.
+pub fn reorder_folders(
+    conn: &Connection,
+    parent_id: Option<&str>,
+    ordered_ids: &[String],
+) -> Result<(), DbError> {
+    let siblings = sibling_ids(conn, parent_id)?;
+    if let Some(stranger) = ordered_ids.iter().find(|id| !siblings.contains(id)) {
+        return Err(DbError::Invalid(format!(
+            "Folder {} is not a child of the given parent",
+            stranger
+        )));
+    }
+
+    let mut order: Vec<String> = Vec::with_capacity(siblings.len());
+    for id in ordered_ids.iter().chain(siblings.iter()) {
+        if !order.contains(id) {
+            order.push(id.clone());
+        }
+    }
+    write_order(conn, &order)
+}
+
+/// Delete a folder, dealing with its contents according to `mode`. Callers
+/// should run this inside a transaction.
+pub fn delete_folder(
+    conn: &Connection,
+    id: &str,
+    mode: &DeleteFolderMode,
+    now: i64,
+) -> Result<DeleteFolderResult, DbError> {
+    ensure_live(conn, id, "Folder")?;
+
+    let target = match mode {
+        DeleteFolderMode::Trash => {
+            let (folders_trashed, prompts_trashed) = trash::trash_folder(conn, id, now)?;
+            return Ok(DeleteFolderResult {
+                folders_trashed,
+                prompts_trashed,
+                ..Default::default()
+            });
+        }
+        DeleteFolderMode::RefuseIfNotEmpty => {
+            let (folders, prompts) = content_counts(conn, id)?;
+            if folders + prompts > 0 {
+                return Err(DbError::Invalid(format!(
+                    "Folder is not empty ({} subfolders, {} prompts)",
+                    folders, prompts
+                )));
+            }
+            None
+        }
+        DeleteFolderMode::MoveToParent => conn.query_row(
+            "SELECT parent_id FROM folders WHERE id = ?1",
+            [id],
+            |row| row.get::<_, Option<String>>(0),
+        )?,
+        DeleteFolderMode::MoveTo { folder_id } => {
+            ensure_live(conn, folder_id, "Target folder")?;
+            if is_in_subtree(conn, id, folder_id)? {
+                return Err(DbError::Invalid(
+                    "Contents cannot be moved into the folder being deleted".to_string(),
+                ));
+            }
+            Some(folder_id.clone())
+        }
+    };
+
+    // Children keep their relative order, after the target's own children
+    let children = sibling_ids(conn, Some(id))?;
+    let first_order = next_sort_order(conn, target.as_deref())?;
+    for (index, child) in children.iter().enumerate() {
+        conn.execute(
+            "UPDATE folders SET parent_id = ?2, sort_order = ?3 WHERE id = ?1",
+            rusqlite::params![child, target, first_order + index as i32],
+        )?;
+    }
+    let prompts_moved = conn.execute(
+        "UPDATE prompts SET folder_id = ?2 WHERE folder_id = ?1 AND deleted_at IS NULL",
+        rusqlite::params![id, target],
+    )?;
+
+    let (folders_trashed, prompts_trashed) = trash::trash_folder(conn, id, now)?;
+    Ok(DeleteFolderResult {
+        folders_trashed,
+        prompts_trashed,
+        folders_moved: children.len(),
+        prompts_moved,
+    })
+}
+
+/// Entities a folder operation can touch, for the undo journal: the folder
+/// `root` with its subfolders and their prompts, plus the children of each
+/// of `parents`, which may get renumbered
+pub fn journal_scope(
+    conn: &Connection,
+    root: Option<&str>,
+    parents: &[Option<&str>],
+) -> Result<Vec<(EntityKind, String)>, DbError> {
+    let mut folders = Vec::new();
+    if let Some(root) = root {
+        let mut stmt = conn.prepare(SUBTREE)?;
+        let ids = stmt.query_map([root], |row| row.get::<_, String>(0))?;
+        for id in ids {
+            folders.push(id?);
+        }
+    }
+    let mut stmt = conn.prepare("SELECT id FROM folders WHERE parent_id IS ?1")?;
+    for parent in parents {
+        for id in stmt.query_map([parent], |row| row.get::<_, String>(0))? {
+            folders.push(id?);
+        }
+    }
+    let mut seen = HashSet::new();
+    folders.retain(|id| seen.insert(id.clone()));
+
+    let mut scope: Vec<_> = folders
+        .into_iter()
+        .map(|id| (EntityKind::Folder, id))
+        .collect();
+    if let Some(root) = root {
+        let mut stmt =
+            conn.prepare(&format!("SELECT id FROM prompts WHERE folder_id IN ({SUBTREE})"))?;
+        let prompts = stmt.query_map([root], |row| Ok((EntityKind::Prompt, row.get(0)?)))?;
+        for prompt in prompts {
+            scope.push(prompt?);
+        }
+    }
+    Ok(scope)
+}
+
+/// Live subfolders and prompts directly inside a folder
+fn content_counts(conn: &Connection, id: &str) -> Result<(usize, usize), DbError> {
+    Ok(conn.query_row(
+        "SELECT
+             (SELECT COUNT(*) FROM folders WHERE parent_id = ?1 AND deleted_at IS NULL),
+             (SELECT COUNT(*) FROM prompts WHERE folder_id = ?1 AND deleted_at IS NULL)",
+        [id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?)
+}
+
+/// Live children of `parent_id`, in display order
+fn sibling_ids(conn: &Connection, parent_id: Option<&str>) -> Result<Vec<String>, DbError> {
+    let mut stmt = conn.prepare(
+        "SELECT id FROM folders
+         WHERE parent_id IS ?1 AND deleted_at IS NULL
+         ORDER BY sort_order, created_at",
+    )?;
+    let ids = stmt
+        .query_map([parent_id], |row| row.get(0))?
+        .collect::<Result<Vec<String>, _>>()?;
+    Ok(ids)
+}
+
+fn write_order(conn: &Connection, ids: &[String]) -> Result<(), DbError> {
+    let mut stmt = conn.prepare("UPDATE folders SET sort_order = ?2 WHERE id = ?1")?;
+    for (index, id) in ids.iter().enumerate() {
+        stmt.execute(rusqlite::params![id, index as i32 + 1])?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    /// root > a > b > c > d > e, one prompt in each, plus a separate `other`
+    fn deep_library() -> Connection {
+        let conn = db::open_in_memory();
+
+        let chain = ["root", "a", "b", "c", "d", "e"];
+        for (depth, id) in chain.iter().enumerate() {
+            let parent = depth.checked_sub(1).map(|p| chain[p]);
+            conn.execute(
+                "INSERT INTO folders (id, name, parent_id, sort_order, created_at)
+                 VALUES (?1, ?1, ?2, 1, 0)",
+                rusqlite::params![id, parent],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO prompts (id, text, folder_id, created_at, updated_at)
+                 VALUES (?1, 'text', ?2, 0, 0)",
+                rusqlite::params![format!("p-{}", id), id],
+            )
+            .unwrap();
+        }
+        conn.execute(
+            "INSERT INTO folders (id, name, sort_order, created_at) VALUES ('other', 'other', 2, 0)",
+            [],
+        )
+        .unwrap();
+        conn
+    }
+
+    fn parent_of(conn: &Connection, id: &str) -> Option<String> {
+        conn.query_row("SELECT parent_id FROM folders WHERE id = ?1", [id], |row| row.get(0))
+            .unwrap()
+    }
+
+    fn folder_of(conn: &Connection, prompt_id: &str) -> Option<String> {
+        conn.query_row("SELECT folder_id FROM prompts WHERE id = ?1", [prompt_id], |row| {
+            row.get(0)
+        })
+        .unwrap()
+    }
+
+    fn live_count(conn: &Connection, table: &str) -> i64 {
+        conn.query_row(
+            &format!("SELECT COUNT(*) FROM {table} WHERE deleted_at IS NULL"),
+            [],
+            |row| row.get(0),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn trash_mode_takes_the_whole_subtree() {
+        let conn = deep_library();
+        let result = delete_folder(&conn, "a", &DeleteFolderMode::Trash, 100).unwrap();
+
+        assert_eq!(result.folders_trashed, 5);
+        assert_eq!(result.prompts_trashed, 5);
+        assert_eq!(live_count(&conn, "folders"), 2);
+        assert_eq!(live_count(&conn, "prompts"), 1);
+    }
+
+    #[test]
+    fn move_to_parent_keeps_the_rest_of_the_chain() {
+        let conn = deep_library();
+        let result = delete_folder(&conn, "c", &DeleteFolderMode::MoveToParent, 100).unwrap();
+
+        assert_eq!(
+            result,
+            DeleteFolderResult {
+                folders_trashed: 1,
+                prompts_trashed: 0,
+                folders_moved: 1,
+                prompts_moved: 1,
+            }
+        );
+        assert_eq!(parent_of(&conn, "d").as_deref(), Some("b"));
+        assert_eq!(parent_of(&conn, "e").as_deref(), Some("d"));
+        assert_eq!(folder_of(&conn, "p-c").as_deref(), Some("b"));
+        assert_eq!(live_count(&conn, "prompts"), 6);
+    }
+
+    #[test]
+    fn move_to_parent_of_top_level_folder_unfiles_contents() {
+        let conn = deep_library();
+        delete_folder(&conn, "root", &DeleteFolderMode::MoveToParent, 100).unwrap();
+
+        assert_eq!(parent_of(&conn, "a"), None);
+        assert_eq!(folder_of(&conn, "p-root"), None);
+    }
+
+    #[test]
+    fn move_to_target_appends_after_its_children() {
+        let conn = deep_library();
+        conn.execute(
+            "INSERT INTO folders (id, name, parent_id, sort_order, created_at)
+             VALUES ('existing', 'existing', 'other', 1, 0)",
+            [],
+        )
+        .unwrap();
+
+        let mode = DeleteFolderMode::MoveTo {
+            folder_id: "other".to_string(),
+        };
+        delete_folder(&conn, "b", &mode, 100).unwrap();
+
+        assert_eq!(parent_of(&conn, "c").as_deref(), Some("other"));
+        assert_eq!(folder_of(&conn, "p-b").as_deref(), Some("other"));
+        let order: i32 = conn
+            .query_row("SELECT sort_order FROM folders WHERE id = 'c'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(order, 2);
+    }
+
+    #[test]
+    fn move_to_rejects_targets_inside_the_deleted_folder() {
+        let conn = deep_library();
+        let mode = DeleteFolderMode::MoveTo {
+            folder_id: "e".to_string(),
+        };
+
+        assert!(matches!(
+            delete_folder(&conn, "b", &mode, 100),
+            Err(DbError::Invalid(_))
+        ));
+        assert_eq!(live_count(&conn, "folders"), 7);
+    }
+
+    #[test]
+    fn refuse_if_not_empty() {
+        let conn = deep_library();
+        let mode = DeleteFolderMode::RefuseIfNotEmpty;
+
+        assert!(matches!(
+            delete_folder(&conn, "d", &mode, 100),
+            Err(DbError::Invalid(_))
+        ));
+
+        let result = delete_folder(&conn, "other", &mode, 100).unwrap();
+        assert_eq!(result.folders_trashed, 1);
+    }
+
+    #[test]
+    fn trashed_folders_cannot_be_deleted_again() {
+        let conn = deep_library();
+        delete_folder(&conn, "b", &DeleteFolderMode::Trash, 100).unwrap();
+
+        assert!(matches!(
+            delete_folder(&conn, "d", &DeleteFolderMode::MoveToParent, 200),
+            Err(DbError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn paths_run_from_the_top_level_folder() {
+        let conn = deep_library();
+        delete_folder(&conn, "d", &DeleteFolderMode::MoveToParent, 100).unwrap();
+
+        let paths = paths(&conn).unwrap();
+        assert_eq!(paths["e"], ["root", "a", "b", "c", "e"]);
+        assert_eq!(paths["other"], ["other"]);
+        assert!(!paths.contains_key("d"));
+    }
+
+    #[test]
+    fn journal_scope_stays_near_the_moved_folder() {
+        let conn = deep_library();
+        let scope = journal_scope(&conn, Some("c"), &[Some("b"), Some("other")]).unwrap();
+        let ids: Vec<&str> = scope.iter().map(|(_, id)| id.as_str()).collect();
+
+        assert_eq!(ids, ["c", "d", "e", "p-c", "p-d", "p-e"]);
+        assert_eq!(scope[2].0, EntityKind::Folder);
+        assert_eq!(scope[3].0, EntityKind::Prompt);
+    }
+}
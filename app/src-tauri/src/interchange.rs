@@ -0,0 +1,385 @@
+//! Plain JSON and CSV prompt files for spreadsheets and scripts.
+//!
+//! JSON files carry a format name and schema version around a list of
+//! [`PromptRecord`]s. CSV files have one row per prompt with these columns,
+//! which imports can map from other headers:
+//!
+//! | Column       | Value                                   |
+//! |--------------|-----------------------------------------|
+//! | `id`         | Prompt id; empty for a new prompt       |
+//! | `header`     | Title                                   |
+//! | `text`       | Prompt text, required                   |
+//! | `folder`     | Folder names joined by ` / `            |
+//! | `tags`       | Tag names joined by `, `                |
+//! | `source`     | Where the prompt came from, see below   |
+//! | `url`        | Source page                             |
+//! | `favorite`   | `true` or `false`                       |
+//! | `created_at` | RFC 3339 time or Unix milliseconds      |
+//! | `updated_at` | RFC 3339 time or Unix milliseconds      |
+//!
+//! `source` is one of `manual`, `clipboard`, `custom`, `chatgpt`, `claude`,
+//! `gemini`, `perplexity`, `grok`, `deepseek` or `kimi`.
+
+use crate::repository::{PromptRecord, RecordError, RecordSet};
+use chrono::{DateTime, SecondsFormat};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// `format` of a JSON prompts file
+pub const JSON_FORMAT: &str = "promptpack.prompts";
+
+/// Schema version of JSON prompts files written by this build
+pub const JSON_VERSION: u32 = 1;
+
+const FOLDER_SEPARATOR: &str = " / ";
+const TAG_SEPARATOR: &str = ", ";
+
+#[derive(Error, Debug)]
+pub enum InterchangeError {
+    #[error("Invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Invalid CSV: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("Not a PromptPack prompts file")]
+    Format,
+    #[error("Prompts file version {0} is newer than this app supports")]
+    Version(u64),
+    #[error("No \"{0}\" column")]
+    MissingColumn(String),
+    #[error("Delimiter must be a single ASCII character")]
+    Delimiter,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonFile<'a> {
+    format: &'static str,
+    version: u32,
+    exported_at: i64,
+    prompts: &'a [PromptRecord],
+}
+
+/// Records as a JSON prompts file
+pub fn to_json(records: &[PromptRecord], now: i64) -> Result<String, InterchangeError> {
+    Ok(serde_json::to_string_pretty(&JsonFile {
+        format: JSON_FORMAT,
+        version: JSON_VERSION,
+        exported_at: now,
+        prompts: records,
+    })?)
+}
+
+/// Records from a JSON prompts file, located by their index. A prompt that
+/// doesn't match the schema is reported without failing the rest.
+pub fn from_json(data: &str) -> Result<RecordSet, InterchangeError> {
+    let file: Value = serde_json::from_str(data.trim_start_matches('\u{feff}'))?;
+    if file["format"] != JSON_FORMAT {
+        return Err(InterchangeError::Format);
+    }
+    match file["version"].as_u64() {
+        Some(version) if version > u64::from(JSON_VERSION) => {
+            return Err(InterchangeError::Version(version))
+        }
+        Some(_) => {}
+        None => return Err(InterchangeError::Format),
+    }
+    let Some(prompts) = file["prompts"].as_array() else {
+        return Err(InterchangeError::Format);
+    };
+
+    let mut set = RecordSet::default();
+    for (i, prompt) in prompts.iter().enumerate() {
+        let location = format!("prompts[{i}]");
+        match PromptRecord::deserialize(prompt) {
+            Ok(record) => set.records.push((location, record)),
+            Err(e) => set.errors.push(RecordError {
+                location,
+                message: e.to_string(),
+            }),
+        }
+    }
+    Ok(set)
+}
+
+/// A CSV column
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Field {
+    Id,
+    Header,
+    Text,
+    Folder,
+    Tags,
+    Source,
+    Url,
+    Favorite,
+    CreatedAt,
+    UpdatedAt,
+}
+
+impl Field {
+    const ALL: [Field; 10] = [
+        Field::Id,
+        Field::Header,
+        Field::Text,
+        Field::Folder,
+        Field::Tags,
+        Field::Source,
+        Field::Url,
+        Field::Favorite,
+        Field::CreatedAt,
+        Field::UpdatedAt,
+    ];
+
+    /// Header written on export and expected on import by default
+    fn name(self) -> &'static str {
+        match self {
+            Field::Id => "id",
+            Field::Header => "header",
+            Field::Text => "text",
+            Field::Folder => "folder",
+            Field::Tags => "tags",
+            Field::Source => "source",
+            Field::Url => "url",
+            Field::Favorite => "favorite",
+            Field::CreatedAt => "created_at",
+            Field::UpdatedAt => "updated_at",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct CsvOptions {
+    /// Header of the column to read each field from, for files not written
+    /// by an export. Unmapped fields use their own name, and fields without
+    /// a column keep their current value.
+    pub columns: HashMap<Field, String>,
+    /// Defaults to a comma
+    pub delimiter: Option<char>,
+}
+
+/// Records as CSV with a header row
+pub fn to_csv(records: &[PromptRecord]) -> Result<String, InterchangeError> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(Field::ALL.map(Field::name))?;
+    for record in records {
+        let time = |millis: Option<i64>| millis.and_then(format_time).unwrap_or_default();
+        writer.write_record([
+            record.id.clone().unwrap_or_default(),
+            record.header.clone().unwrap_or_default(),
+            record.text.clone(),
+            record
+                .folder
+                .as_deref()
+                .unwrap_or_default()
+                .join(FOLDER_SEPARATOR),
+            record
+                .tags
+                .as_deref()
+                .unwrap_or_default()
+                .join(TAG_SEPARATOR),
+            record.source.clone().unwrap_or_default(),
+            record.url.clone().unwrap_or_default(),
+            record.favorite.unwrap_or(false).to_string(),
+            time(record.created_at),
+            time(record.updated_at),
+        ])?;
+    }
+    let bytes = writer
+        .into_inner()
+        .map_err(|e| InterchangeError::Csv(e.into_error().into()))?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Records from CSV with a header row, located by spreadsheet row number.
+/// Rows with unreadable values are reported without failing the rest.
+pub fn from_csv(data: &str, options: &CsvOptions) -> Result<RecordSet, InterchangeError> {
+    let delimiter = match options.delimiter {
+        None => b',',
+        Some(c) if c.is_ascii() => c as u8,
+        Some(_) => return Err(InterchangeError::Delimiter),
+    };
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_reader(data.trim_start_matches('\u{feff}').as_bytes());
+
+    let headers = reader.headers()?.clone();
+    let mut columns = HashMap::new();
+    for field in Field::ALL {
+        let mapped = options.columns.get(&field);
+        let name = mapped.map_or(field.name(), String::as_str);
+        match headers
+            .iter()
+            .position(|h| h.trim().eq_ignore_ascii_case(name.trim()))
+        {
+            Some(index) => {
+                columns.insert(field, index);
+            }
+            None if mapped.is_some() || field == Field::Text => {
+                return Err(InterchangeError::MissingColumn(name.to_string()))
+            }
+            None => {}
+        }
+    }
+
+    let mut set = RecordSet::default();
+    for (i, row) in reader.records().enumerate() {
+        // Row 1 holds the headers
+        let location = format!("Row {}", i + 2);
+        let record = row
+            .map_err(|e| e.to_string())
+            .and_then(|row| parse_row(&row, &columns));
+        match record {
+            Ok(record) => set.records.push((location, record)),
+            Err(message) => set.errors.push(RecordError { location, message }),
+        }
+    }
+    Ok(set)
+}
+
+fn parse_row(
+    row: &csv::StringRecord,
+    columns: &HashMap<Field, usize>,
+) -> Result<PromptRecord, String> {
+    let cell = |field| {
+        columns
+            .get(&field)
+            .map(|&i| row.get(i).unwrap_or_default().trim())
+    };
+    // Split on the exact separators `to_csv` joins with, so names that
+    // contain a bare `/` or `,` survive a round trip
+    let list = |field, separator: &str| {
+        cell(field).map(|value| {
+            value
+                .split(separator)
+                .map(|part| part.trim().to_string())
+                .filter(|part| !part.is_empty())
+                .collect()
+        })
+    };
+    let time = |field| match cell(field) {
+        Some("") | None => Ok(None),
+        Some(value) => parse_time(value)
+            .map(Some)
+            .ok_or_else(|| format!("Invalid {} \"{value}\"", field.name())),
+    };
+
+    Ok(PromptRecord {
+        id: cell(Field::Id)
+            .filter(|id| !id.is_empty())
+            .map(str::to_string),
+        text: columns
+            .get(&Field::Text)
+            .and_then(|&i| row.get(i))
+            .unwrap_or_default()
+            .to_string(),
+        header: cell(Field::Header).map(str::to_string),
+        source: cell(Field::Source)
+            .filter(|source| !source.is_empty())
+            .map(str::to_string),
+        url: cell(Field::Url).map(str::to_string),
+        folder: list(Field::Folder, FOLDER_SEPARATOR),
+        tags: list(Field::Tags, TAG_SEPARATOR),
+        favorite: match cell(Field::Favorite).map(str::to_lowercase).as_deref() {
+            None | Some("") => None,
+            Some("true" | "yes" | "1") => Some(true),
+            Some("false" | "no" | "0") => Some(false),
+            Some(value) => return Err(format!("Invalid favorite \"{value}\"")),
+        },
+        created_at: time(Field::CreatedAt)?,
+        updated_at: time(Field::UpdatedAt)?,
+    })
+}
+
+/// Unix milliseconds as an RFC 3339 UTC time
+pub(crate) fn format_time(millis: i64) -> Option<String> {
+    DateTime::from_timestamp_millis(millis).map(|d| d.to_rfc3339_opts(SecondsFormat::Millis, true))
+}
+
+/// An RFC 3339 time or Unix milliseconds
+pub(crate) fn parse_time(value: &str) -> Option<i64> {
+    value.parse().ok().or_else(|| {
+        DateTime::parse_from_rfc3339(value)
+            .ok()
+            .map(|d| d.timestamp_millis())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_round_trips() {
+        let record = PromptRecord {
+            id: Some("p1".to_string()),
+            text: "Review this, \"carefully\"\nline two".to_string(),
+            header: Some("Code review".to_string()),
+            source: Some("manual".to_string()),
+            url: Some(String::new()),
+            folder: Some(vec!["Team".to_string(), "Reviews".to_string()]),
+            tags: Some(vec!["backend".to_string(), "rust".to_string()]),
+            favorite: Some(true),
+            created_at: Some(1_714_555_800_000),
+            updated_at: Some(1_714_555_800_123),
+        };
+        let data = to_csv(std::slice::from_ref(&record)).unwrap();
+        assert!(data.starts_with("id,header,text,folder,tags,"));
+        assert!(data.contains(",Team / Reviews,\"backend, rust\","));
+
+        let set = from_csv(&data, &CsvOptions::default()).unwrap();
+        assert_eq!(set.records, [("Row 2".to_string(), record)]);
+    }
+
+    #[test]
+    fn csv_values_are_checked_per_row() {
+        let data = "Prompt;Star;Created\nA;yes;1714555800000\nB;maybe;\nC;;soon\n";
+        let options = CsvOptions {
+            columns: HashMap::from([
+                (Field::Text, "prompt".to_string()),
+                (Field::Favorite, "Star".to_string()),
+                (Field::CreatedAt, "Created".to_string()),
+            ]),
+            delimiter: Some(';'),
+        };
+        let set = from_csv(data, &options).unwrap();
+        assert_eq!(set.records.len(), 1);
+        assert_eq!(set.records[0].1.favorite, Some(true));
+        assert_eq!(set.records[0].1.created_at, Some(1_714_555_800_000));
+        assert_eq!(set.records[0].1.header, None);
+        let locations: Vec<_> = set.errors.iter().map(|e| e.location.as_str()).collect();
+        assert_eq!(locations, ["Row 3", "Row 4"]);
+
+        assert!(matches!(
+            from_csv("title\nx\n", &CsvOptions::default()),
+            Err(InterchangeError::MissingColumn(column)) if column == "text"
+        ));
+    }
+
+    #[test]
+    fn json_files_are_checked() {
+        let data = to_json(&[PromptRecord::default()], 5).unwrap();
+        assert_eq!(from_json(&data).unwrap().records.len(), 1);
+
+        let newer = data.replace("\"version\": 1", "\"version\": 2");
+        assert!(matches!(
+            from_json(&newer),
+            Err(InterchangeError::Version(2))
+        ));
+        assert!(matches!(
+            from_json(r#"{"prompts": []}"#),
+            Err(InterchangeError::Format)
+        ));
+
+        let set = from_json(
+            r#"{"format": "promptpack.prompts", "version": 1,
+                "prompts": [{"text": "ok"}, {"text": 5}]}"#,
+        )
+        .unwrap();
+        assert_eq!(set.records.len(), 1);
+        assert_eq!(set.errors[0].location, "prompts[1]");
+    }
+}
@@ -0,0 +1,444 @@
+use crate::db::DbError;
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Operations kept for undo; older ones are dropped
+pub const MAX_OPERATIONS: i64 = 100;
+
+/// Usage counters aren't edits, so undoing an edit leaves them alone
+const USAGE_COLUMNS: [&str; 2] = ["use_count", "last_used_at"];
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum EntityKind {
+    Prompt,
+    Folder,
+    SmartFolder,
+}
+
+impl EntityKind {
+    fn table(self) -> &'static str {
+        match self {
+            EntityKind::Prompt => "prompts",
+            EntityKind::Folder => "folders",
+            EntityKind::SmartFolder => "smart_folders",
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            EntityKind::Prompt => "Prompt",
+            EntityKind::Folder => "Folder",
+            EntityKind::SmartFolder => "Smart folder",
+        }
+    }
+}
+
+/// Full state of one row, plus the tags of a prompt
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Snapshot {
+    pub row: Map<String, Value>,
+    #[serde(default)]
+    pub tag_ids: Vec<String>,
+}
+
+/// One entity before and after an operation. `None` means the row did not
+/// exist at that point.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct EntityChange {
+    pub entity: EntityKind,
+    pub id: String,
+    pub before: Option<Snapshot>,
+    pub after: Option<Snapshot>,
+}
+
+/// Read the current state of an entity
+pub fn snapshot(
+    conn: &Connection,
+    entity: EntityKind,
+    id: &str,
+) -> Result<Option<Snapshot>, DbError> {
+    let mut stmt = conn.prepare(&format!("SELECT * FROM {} WHERE id = ?1", entity.table()))?;
+    let names: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+
+    let row = stmt
+        .query_row([id], |row| {
+            let mut map = Map::new();
+            for (index, name) in names.iter().enumerate() {
+                let value = match row.get_ref(index)? {
+                    ValueRef::Null | ValueRef::Blob(_) => Value::Null,
+                    ValueRef::Integer(i) => Value::from(i),
+                    ValueRef::Real(f) => Value::from(f),
+                    ValueRef::Text(t) => Value::from(String::from_utf8_lossy(t).into_owned()),
+                };
+                map.insert(name.clone(), value);
+            }
+            Ok(map)
+        })
+        .optional()?;
+
+    let Some(row) = row else {
+        return Ok(None);
+    };
+
+    let tag_ids = match entity {
+        EntityKind::Prompt => {
+            let mut stmt = conn
+                .prepare("SELECT tag_id FROM prompt_tags WHERE prompt_id = ?1 ORDER BY tag_id")?;
+            let ids = stmt.query_map([id], |row| row.get(0))?;
+            ids.collect::<Result<Vec<String>, _>>()?
+        }
+        EntityKind::Folder | EntityKind::SmartFolder => Vec::new(),
+    };
+
+    Ok(Some(Snapshot { row, tag_ids }))
+}
+
+/// Snapshot `entities`, run `f`, and return what changed
+pub fn capture<T, F>(
+    conn: &Connection,
+    entities: &[(EntityKind, String)],
+    f: F,
+) -> Result<(T, Vec<EntityChange>), DbError>
+where
+    F: FnOnce(&Connection) -> Result<T, DbError>,
+{
+    let before = entities
+        .iter()
+        .map(|(entity, id)| snapshot(conn, *entity, id))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let result = f(conn)?;
+
+    let mut changes = Vec::new();
+    for ((entity, id), before) in entities.iter().zip(before) {
+        let after = snapshot(conn, *entity, id)?;
+        if before != after {
+            changes.push(EntityChange {
+                entity: *entity,
+                id: id.clone(),
+                before,
+                after,
+            });
+        }
+    }
+    Ok((result, changes))
+}
+
+/// Store an operation so it can be undone. Returns None when nothing changed.
+/// A new operation discards anything that was waiting to be redone.
+pub fn record(
+    conn: &Connection,
+    label: &str,
+    changes: &[EntityChange],
+    now: i64,
+) -> Result<Option<i64>, DbError> {
+    if changes.is_empty() {
+        return Ok(None);
+    }
+
+    let json = serde_json::to_string(changes).map_err(|e| DbError::Invalid(e.to_string()))?;
+    conn.execute("DELETE FROM operation_journal WHERE undone = 1", [])?;
+    conn.execute(
+        "INSERT INTO operation_journal (label, changes, created_at) VALUES (?1, ?2, ?3)",
+        rusqlite::params![label, json, now],
+    )?;
+    let id = conn.last_insert_rowid();
+    conn.execute(
+        "DELETE FROM operation_journal WHERE id <= ?1",
+        [id - MAX_OPERATIONS],
+    )?;
+    Ok(Some(id))
+}
+
+/// Capture `entities` around `f` and record the result as one operation.
+/// Callers should run this inside a transaction.
+pub fn track<T, F>(
+    conn: &Connection,
+    label: &str,
+    entities: &[(EntityKind, String)],
+    now: i64,
+    f: F,
+) -> Result<T, DbError>
+where
+    F: FnOnce(&Connection) -> Result<T, DbError>,
+{
+    let (result, changes) = capture(conn, entities, f)?;
+    record(conn, label, &changes, now)?;
+    Ok(result)
+}
+
+/// Forget the operations that touch or refer to `ids`, for when those rows
+/// are gone for good and undoing them could no longer be replayed. The rest
+/// of the history stays.
+pub fn forget(conn: &Connection, ids: &[String]) -> Result<usize, DbError> {
+    if ids.is_empty() {
+        return Ok(0);
+    }
+    let mut stmt = conn.prepare("SELECT id, changes FROM operation_journal")?;
+    let operations = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut forgotten = 0;
+    for (id, json) in operations {
+        // Unreadable history can't be undone either
+        let mentions = match serde_json::from_str::<Vec<EntityChange>>(&json) {
+            Ok(changes) => changes.iter().any(|change| mentions(change, ids)),
+            Err(_) => true,
+        };
+        if mentions {
+            forgotten += conn.execute("DELETE FROM operation_journal WHERE id = ?1", [id])?;
+        }
+    }
+    Ok(forgotten)
+}
+
+/// Whether `change` is to one of `ids` or has a snapshot pointing at one,
+/// such as a prompt's `folder_id` or a folder's `parent_id`
+fn mentions(change: &EntityChange, ids: &[String]) -> bool {
+    ids.contains(&change.id)
+        || [&change.before, &change.after]
+            .into_iter()
+            .flatten()
+            .flat_map(|snapshot| snapshot.row.values())
+            .any(|value| value.as_str().is_some_and(|v| ids.iter().any(|id| id == v)))
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Undo,
+    Redo,
+}
+
+/// An entity touched by an undo or redo
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct EntityRef {
+    pub entity: EntityKind,
+    pub id: String,
+}
+
+/// What the next undo and redo would do, for menu labels
+#[derive(Debug, Serialize, Clone, Default, PartialEq)]
+pub struct UndoState {
+    pub undo: Option<String>,
+    pub redo: Option<String>,
+}
+
+/// Outcome of an undo or redo
+#[derive(Debug, Serialize, Clone)]
+pub struct AppliedOperation {
+    pub direction: Direction,
+    pub operation_id: i64,
+    pub label: String,
+    pub entities: Vec<EntityRef>,
+    pub state: UndoState,
+}
+
+pub fn undo_state(conn: &Connection) -> Result<UndoState, DbError> {
+    Ok(UndoState {
+        undo: next_operation(conn, Direction::Undo)?.map(|(_, label, _)| label),
+        redo: next_operation(conn, Direction::Redo)?.map(|(_, label, _)| label),
+    })
+}
+
+/// The operation an undo or redo would apply: the newest one still in
+/// effect, or the oldest one that has been undone
+fn next_operation(
+    conn: &Connection,
+    direction: Direction,
+) -> Result<Option<(i64, String, String)>, DbError> {
+    let sql = match direction {
+        Direction::Undo => {
+            "SELECT id, label, changes FROM operation_journal
+             WHERE undone = 0 ORDER BY id DESC LIMIT 1"
+        }
+        Direction::Redo => {
+            "SELECT id, label, changes FROM operation_journal
+             WHERE undone = 1 ORDER BY id ASC LIMIT 1"
+        }
+    };
+    Ok(conn
+        .query_row(sql, [], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .optional()?)
+}
+
+/// Undo the newest operation, or redo the last undone one. Returns None when
+/// there is nothing to apply. Fails with a conflict instead of overwriting a
+/// row that something outside the journal has changed since. Callers should
+/// run this inside a transaction.
+pub fn apply(
+    conn: &Connection,
+    direction: Direction,
+    now: i64,
+) -> Result<Option<AppliedOperation>, DbError> {
+    let Some((id, label, json)) = next_operation(conn, direction)? else {
+        return Ok(None);
+    };
+    let changes: Vec<EntityChange> =
+        serde_json::from_str(&json).map_err(|e| DbError::Corrupt(e.to_string()))?;
+
+    for change in &changes {
+        let expected = match direction {
+            Direction::Undo => change.after.as_ref(),
+            Direction::Redo => change.before.as_ref(),
+        };
+        let current = snapshot(conn, change.entity, &change.id)?;
+        if !same_state(current.as_ref(), expected) {
+            let verb = match direction {
+                Direction::Undo => "undone",
+                Direction::Redo => "redone",
+            };
+            return Err(DbError::Conflict(format!(
+                "\"{label}\" can't be {verb}: {} {} has changed since",
+                change.entity.name(),
+                change.id
+            )));
+        }
+    }
+
+    // Rows come back in whatever order; check references once at commit
+    conn.pragma_update(None, "defer_foreign_keys", true)?;
+    match direction {
+        Direction::Undo => {
+            for change in changes.iter().rev() {
+                write_state(conn, change.entity, &change.id, change.before.as_ref(), now)?;
+            }
+        }
+        Direction::Redo => {
+            for change in &changes {
+                write_state(conn, change.entity, &change.id, change.after.as_ref(), now)?;
+            }
+        }
+    }
+
+    conn.execute(
+        "UPDATE operation_journal SET undone = ?2 WHERE id = ?1",
+        rusqlite::params![id, direction == Direction::Undo],
+    )?;
+
+    Ok(Some(AppliedOperation {
+        direction,
+        operation_id: id,
+        label,
+        entities: changes
+            .into_iter()
+            .map(|c| EntityRef {
+                entity: c.entity,
+                id: c.id,
+            })
+            .collect(),
+        state: undo_state(conn)?,
+    }))
+}
+
+/// Whether a row is still as the journal left it. Usage counters and columns
+/// added by a later migration don't count.
+fn same_state(current: Option<&Snapshot>, expected: Option<&Snapshot>) -> bool {
+    match (current, expected) {
+        (None, None) => true,
+        (Some(current), Some(expected)) => {
+            current.tag_ids == expected.tag_ids
+                && expected
+                    .row
+                    .iter()
+                    .filter(|(column, _)| !USAGE_COLUMNS.contains(&column.as_str()))
+                    .all(|(column, value)| current.row.get(column) == Some(value))
+        }
+        _ => false,
+    }
+}
+
+/// Make an entity match `state`, deleting it when `state` is None. A deleted
+/// prompt leaves a sync tombstone so it doesn't come back from the cloud.
+fn write_state(
+    conn: &Connection,
+    entity: EntityKind,
+    id: &str,
+    state: Option<&Snapshot>,
+    now: i64,
+) -> Result<(), DbError> {
+    let table = entity.table();
+    let Some(state) = state else {
+        if entity == EntityKind::Prompt {
+            conn.execute(
+                "INSERT OR REPLACE INTO sync_tombstones (prompt_id, cloud_id, deleted_at)
+                 SELECT id, cloud_id, ?2 FROM prompts WHERE id = ?1",
+                rusqlite::params![id, now],
+            )?;
+        }
+        conn.execute(&format!("DELETE FROM {table} WHERE id = ?1"), [id])?;
+        return Ok(());
+    };
+
+    // Only columns that still exist; the journal may predate a migration
+    let mut stmt = conn.prepare(&format!("SELECT name FROM pragma_table_info('{table}')"))?;
+    let existing = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<Vec<String>, _>>()?;
+    let columns: Vec<&String> = state.row.keys().filter(|c| existing.contains(c)).collect();
+    if columns.is_empty() {
+        return Err(DbError::Corrupt(format!("Empty snapshot for {id}")));
+    }
+
+    let names = columns
+        .iter()
+        .map(|c| c.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let placeholders = (1..=columns.len())
+        .map(|i| format!("?{i}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let updates = columns
+        .iter()
+        .filter(|c| c.as_str() != "id" && !USAGE_COLUMNS.contains(&c.as_str()))
+        .map(|c| format!("{c} = excluded.{c}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let values: Vec<rusqlite::types::Value> = columns
+        .iter()
+        .map(|c| json_to_sql(&state.row[c.as_str()]))
+        .collect();
+
+    // Upsert rather than replace, so cascades on the old row don't fire
+    conn.execute(
+        &format!(
+            "INSERT INTO {table} ({names}) VALUES ({placeholders})
+             ON CONFLICT(id) DO UPDATE SET {updates}"
+        ),
+        rusqlite::params_from_iter(values),
+    )?;
+
+    if entity == EntityKind::Prompt {
+        conn.execute("DELETE FROM sync_tombstones WHERE prompt_id = ?1", [id])?;
+        conn.execute("DELETE FROM prompt_tags WHERE prompt_id = ?1", [id])?;
+        let mut insert = conn.prepare(
+            "INSERT OR IGNORE INTO prompt_tags (prompt_id, tag_id)
+             SELECT ?1, id FROM tags WHERE id = ?2",
+        )?;
+        for tag_id in &state.tag_ids {
+            insert.execute([id, tag_id.as_str()])?;
+        }
+    }
+    Ok(())
+}
+
+fn json_to_sql(value: &Value) -> rusqlite::types::Value {
+    use rusqlite::types::Value as Sql;
+    match value {
+        Value::Null => Sql::Null,
+        Value::Bool(b) => Sql::Integer(i64::from(*b)),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => Sql::Integer(i),
+            None => Sql::Real(n.as_f64().unwrap_or_default()),
+        },
+        Value::String(s) => Sql::Text(s.clone()),
+        other => Sql::Text(other.to_string()),
+    }
+}
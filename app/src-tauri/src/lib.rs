@@ -1,9 +1,34 @@
 mod auth;
+mod backup;
+pub mod bridge;
+mod bulk;
+mod capture;
+mod changes;
+pub mod cli;
 mod commands;
 mod crypto;
-mod db;
+pub mod db;
+mod deep_link;
+mod error;
+mod filter;
+mod folders;
+pub mod interchange;
+pub mod journal;
+pub mod markdown;
+pub mod mcp;
+pub mod repository;
+mod revisions;
+mod settings;
+mod shortcuts;
+mod smart_folders;
+mod template;
+mod trash;
+mod tray;
+mod validation;
+mod vault;
 
-use tauri::Emitter;
+use std::time::Duration;
+use tauri::{Emitter, Manager};
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -16,6 +41,11 @@ pub fn run() {
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_deep_link::init())
         .manage(commands::AuthState::default())
+        .manage(vault::VaultState::default())
+        .manage(db::Database::default())
+        .manage(shortcuts::ShortcutManager::default())
+        .manage(deep_link::DeepLinkState::default())
+        .manage(bridge::BridgeState::default())
         .manage(commands::HttpClient(
             reqwest::Client::builder()
                 .timeout(std::time::Duration::from_secs(30))
@@ -23,36 +53,110 @@ pub fn run() {
                 .expect("failed to create HTTP client"),
         ))
         .setup(|app| {
-            // Initialize database
+            let settings_path = settings::get_settings_path(app.handle())?;
+            app.manage(settings::SettingsState::load(settings_path));
+
+            // Bind global hotkeys. Conflicts are kept for get_shortcut_status.
+            shortcuts::register(app.handle());
+            tray::init(app.handle());
+
+            // Initialize database. Commands wait for it to finish, and failures
+            // are reported through the database status and an event.
             let app_handle = app.handle().clone();
             tauri::async_runtime::spawn(async move {
-                if let Err(e) = db::init_database(&app_handle).await {
-                    log::error!("Failed to initialize database: {}", e);
+                let _ = db::init_database(&app_handle).await;
+            });
+
+            // Serve the browser extension if the user turned the bridge on
+            let bridge_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                bridge::start_saved(&bridge_handle).await;
+            });
+
+            // Forward library changes to every window, whichever path made them
+            let mut library_changes = app.state::<db::Database>().subscribe_changes();
+            let changes_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                use tokio::sync::broadcast::error::RecvError;
+                loop {
+                    match library_changes.recv().await {
+                        Ok(batch) => {
+                            let _ = changes_handle.emit("library-changed", batch);
+                        }
+                        Err(RecvError::Lagged(missed)) => {
+                            log::warn!("Dropped {} library change batches", missed)
+                        }
+                        Err(RecvError::Closed) => break,
+                    }
                 }
             });
 
-            // Register deep link handler for auth callback
+            // Hourly housekeeping once the database is up: scheduled backups,
+            // trash expiry and revision compaction
+            let housekeeping_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+                loop {
+                    interval.tick().await;
+                    if housekeeping_handle
+                        .state::<db::Database>()
+                        .wait_ready()
+                        .await
+                        .is_err()
+                    {
+                        continue;
+                    }
+                    if let Err(e) = backup::run_scheduled(&housekeeping_handle).await {
+                        log::warn!("Scheduled backup failed: {}", e);
+                    }
+                    match trash::run_expiry(&housekeeping_handle).await {
+                        Ok(purged) if purged.prompts + purged.folders > 0 => log::info!(
+                            "Purged {} prompts and {} folders from the trash",
+                            purged.prompts,
+                            purged.folders
+                        ),
+                        Ok(_) => {}
+                        Err(e) => log::warn!("Trash expiry failed: {}", e),
+                    }
+                    if let Err(e) = revisions::run_compaction(&housekeeping_handle).await {
+                        log::warn!("Revision compaction failed: {}", e);
+                    }
+                }
+            });
+
+            // Auto-lock the vault once the library has been idle long enough
+            let lock_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let mut interval = tokio::time::interval(Duration::from_secs(30));
+                loop {
+                    interval.tick().await;
+                    let minutes = lock_handle
+                        .state::<settings::SettingsState>()
+                        .get()
+                        .vault
+                        .auto_lock_minutes;
+                    let vault_state = lock_handle.state::<vault::VaultState>();
+                    let database = lock_handle.state::<db::Database>();
+                    if minutes > 0
+                        && vault_state.is_unlocked()
+                        && database.idle_for() >= Duration::from_secs(u64::from(minutes) * 60)
+                    {
+                        vault_state.lock();
+                        database.lock();
+                        log::info!("Vault auto-locked after {} idle minutes", minutes);
+                        let _ = lock_handle.emit("vault-locked", ());
+                    }
+                }
+            });
+
+            // Route promptpack:// links: sign-in callbacks, imports and prompts
             #[cfg(desktop)]
             {
                 use tauri_plugin_deep_link::DeepLinkExt;
                 let handle = app.handle().clone();
                 app.deep_link().on_open_url(move |event| {
-                    let urls = event.urls();
-                    for url in urls {
-                        if url.scheme() == "promptpack" && url.host_str() == Some("auth") {
-                            // Extract token from URL query params
-                            if let Some(query) = url.query() {
-                                for pair in query.split('&') {
-                                    if let Some(token) = pair.strip_prefix("token=") {
-                                        let token = urlencoding::decode(token)
-                                            .unwrap_or_else(|_| token.into())
-                                            .to_string();
-                                        // Emit event to frontend
-                                        let _ = handle.emit("auth-callback", token);
-                                    }
-                                }
-                            }
-                        }
+                    for url in event.urls() {
+                        deep_link::handle(&handle, &url);
                     }
                 });
             }
@@ -72,14 +176,79 @@ pub fn run() {
             commands::create_prompt,
             commands::update_prompt,
             commands::delete_prompt,
+            commands::search_prompts,
+            commands::bulk_update_prompts,
+            commands::undo,
+            commands::redo,
+            commands::get_undo_state,
+            commands::record_prompt_use,
+            commands::get_prompt_revisions,
+            commands::diff_prompt_revisions,
+            commands::revert_prompt,
+            commands::get_history_settings,
+            commands::update_history_settings,
             commands::get_folders,
             commands::create_folder,
             commands::update_folder,
+            commands::move_folder,
+            commands::reorder_folders,
+            commands::get_folder_tree,
+            commands::get_folder_prompts,
             commands::delete_folder,
+            commands::create_smart_folder,
+            commands::update_smart_folder,
+            commands::delete_smart_folder,
+            commands::get_smart_folder_prompts,
+            commands::preview_smart_folder,
+            commands::get_trash,
+            commands::restore_prompt,
+            commands::restore_folder,
+            commands::purge_prompt,
+            commands::purge_folder,
+            commands::empty_trash,
+            commands::get_sync_tombstones,
+            commands::get_trash_settings,
+            commands::update_trash_settings,
             commands::import_pack,
             commands::export_pack,
+            commands::export_markdown,
+            commands::import_markdown,
+            commands::export_json,
+            commands::import_json,
+            commands::export_csv,
+            commands::import_csv,
+            commands::get_shortcut_settings,
+            commands::get_shortcut_status,
+            commands::update_shortcut_settings,
+            commands::toggle_quick_picker,
+            commands::hide_quick_picker,
+            commands::pick_prompt,
+            commands::capture_clipboard,
+            commands::get_capture_settings,
+            commands::update_capture_settings,
+            commands::take_deep_links,
+            commands::get_bridge_status,
+            commands::enable_bridge,
+            commands::disable_bridge,
+            commands::regenerate_bridge_token,
             commands::encrypt_data,
             commands::decrypt_data,
+            commands::get_database_status,
+            commands::retry_database_init,
+            commands::reset_database,
+            commands::restore_database,
+            commands::list_backups,
+            commands::create_backup,
+            commands::diff_backup,
+            commands::restore_backup,
+            commands::get_backup_settings,
+            commands::update_backup_settings,
+            commands::get_vault_status,
+            commands::enable_vault,
+            commands::unlock_vault,
+            commands::lock_vault,
+            commands::disable_vault,
+            commands::set_vault_auto_lock,
             commands::verify_auth_token,
             commands::get_auth_session,
             commands::logout,
@@ -0,0 +1,385 @@
+//! Prompts as a directory of Markdown files, for git repositories and
+//! Obsidian vaults.
+//!
+//! Each prompt is one file with YAML front matter above its text, and
+//! subdirectories mirror the folder tree:
+//!
+//! ```text
+//! ---
+//! id: 6f1c0b7e-...
+//! header: Code review
+//! tags:
+//! - backend
+//! source: manual
+//! favorite: true
+//! created: 2024-05-01T09:30:00.000Z
+//! updated: 2024-05-02T10:00:00.000Z
+//! ---
+//!
+//! Review this {Language} diff for bugs
+//! ```
+//!
+//! Importing a file with a known id updates that prompt. Files without
+//! front matter become new prompts headed by their file name.
+
+use crate::interchange::{format_time, parse_time};
+use crate::repository::{self, PromptRecord, RecordError, RecordSet};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Longest file or directory name written, before the extension
+const MAX_NAME_CHARS: usize = 80;
+
+/// Largest file read on import
+const MAX_FILE_BYTES: u64 = 1024 * 1024;
+
+const EXTENSION: &str = "md";
+
+#[derive(Error, Debug)]
+pub enum MarkdownError {
+    #[error("{} is not a directory", .0.display())]
+    NotADirectory(PathBuf),
+    #[error("{}: {source}", path.display())]
+    Io { path: PathBuf, source: io::Error },
+    #[error("Invalid front matter: {0}")]
+    FrontMatter(String),
+    #[error("Invalid {0} date \"{1}\"")]
+    Date(&'static str, String),
+    #[error("File is larger than {} KiB", MAX_FILE_BYTES / 1024)]
+    TooLarge,
+}
+
+fn io_error(path: &Path) -> impl FnOnce(io::Error) -> MarkdownError + '_ {
+    move |source| MarkdownError::Io {
+        path: path.to_path_buf(),
+        source,
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
+struct FrontMatter {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    header: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    favorite: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    created: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    updated: Option<String>,
+}
+
+/// One prompt as a Markdown document
+pub fn format(record: &PromptRecord) -> String {
+    let front = FrontMatter {
+        id: record.id.clone(),
+        header: record.header.clone().filter(|h| !h.is_empty()),
+        tags: record.tags.clone().unwrap_or_default(),
+        source: record.source.clone(),
+        url: record.url.clone().filter(|u| !u.is_empty()),
+        favorite: record.favorite.unwrap_or(false),
+        created: record.created_at.and_then(format_time),
+        updated: record.updated_at.and_then(format_time),
+    };
+    let yaml = serde_yaml::to_string(&front).unwrap_or_default();
+    let yaml = if yaml.trim() == "{}" { "" } else { &yaml };
+    format!("---\n{yaml}---\n\n{}\n", record.text)
+}
+
+/// A Markdown document as a prompt. Without front matter the whole file is
+/// the text and `name` becomes the header.
+pub fn parse(content: &str, name: &str) -> Result<PromptRecord, MarkdownError> {
+    let content = content.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+    let Some((yaml, body)) = split_front_matter(&content) else {
+        return Ok(PromptRecord {
+            text: content.trim().to_string(),
+            header: Some(name.to_string()),
+            ..Default::default()
+        });
+    };
+
+    let front: FrontMatter = if yaml.trim().is_empty() {
+        FrontMatter::default()
+    } else {
+        serde_yaml::from_str(yaml).map_err(|e| MarkdownError::FrontMatter(e.to_string()))?
+    };
+    let body = body.strip_prefix('\n').unwrap_or(body);
+    let body = body.strip_suffix('\n').unwrap_or(body);
+
+    Ok(PromptRecord {
+        id: front.id,
+        text: body.to_string(),
+        header: Some(front.header.unwrap_or_default()),
+        source: front.source,
+        url: Some(front.url.unwrap_or_default()),
+        folder: None,
+        tags: Some(front.tags),
+        favorite: Some(front.favorite),
+        created_at: front
+            .created
+            .map(|d| parse_timestamp("created", d))
+            .transpose()?,
+        updated_at: front
+            .updated
+            .map(|d| parse_timestamp("updated", d))
+            .transpose()?,
+    })
+}
+
+/// The front matter and the rest, when the document opens with `---`
+fn split_front_matter(content: &str) -> Option<(&str, &str)> {
+    let rest = content.strip_prefix("---\n")?;
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == "---" {
+            return Some((&rest[..offset], &rest[offset + line.len()..]));
+        }
+        offset += line.len();
+    }
+    None
+}
+
+fn parse_timestamp(field: &'static str, value: String) -> Result<i64, MarkdownError> {
+    parse_time(&value).ok_or(MarkdownError::Date(field, value))
+}
+
+/// Write one file per record below `dir`, in subdirectories named after
+/// each record's folder path. Files with the same name are overwritten.
+/// Returns the number of files written.
+pub fn write(dir: &Path, records: &[PromptRecord]) -> Result<usize, MarkdownError> {
+    fs::create_dir_all(dir).map_err(io_error(dir))?;
+    let mut names = Names::default();
+    let mut dirs: HashMap<Vec<String>, PathBuf> = HashMap::new();
+    dirs.insert(Vec::new(), dir.to_path_buf());
+
+    for record in records {
+        let folder = record.folder.clone().unwrap_or_default();
+        for depth in 1..=folder.len() {
+            if dirs.contains_key(&folder[..depth]) {
+                continue;
+            }
+            let parent = dirs[&folder[..depth - 1]].clone();
+            let path = parent.join(names.claim(&parent, &folder[depth - 1], ""));
+            fs::create_dir_all(&path).map_err(io_error(&path))?;
+            dirs.insert(folder[..depth].to_vec(), path);
+        }
+
+        let parent = dirs[&folder].clone();
+        let title = repository::title(record.header.as_deref(), &record.text, MAX_NAME_CHARS);
+        let path = parent.join(names.claim(&parent, &title, EXTENSION));
+        fs::write(&path, format(record)).map_err(io_error(&path))?;
+    }
+    Ok(records.len())
+}
+
+/// File names handed out so far, per directory
+#[derive(Default)]
+struct Names(HashMap<PathBuf, HashSet<String>>);
+
+impl Names {
+    /// A safe name for `title` in `dir` that no earlier file or directory
+    /// there has, ignoring case
+    fn claim(&mut self, dir: &Path, title: &str, extension: &str) -> String {
+        let used = self.0.entry(dir.to_path_buf()).or_default();
+        let stem = file_stem(title);
+        let mut n = 1;
+        loop {
+            let mut name = match n {
+                1 => stem.clone(),
+                _ => format!("{stem} ({n})"),
+            };
+            if !extension.is_empty() {
+                name = format!("{name}.{extension}");
+            }
+            if used.insert(name.to_lowercase()) {
+                return name;
+            }
+            n += 1;
+        }
+    }
+}
+
+/// `title` without characters that are invalid in file names on any
+/// platform, shortened, and never empty or a reserved Windows name
+fn file_stem(title: &str) -> String {
+    let cleaned: String = title
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '-',
+            c if c.is_control() => ' ',
+            c => c,
+        })
+        .take(MAX_NAME_CHARS)
+        .collect();
+    let cleaned = cleaned.trim().trim_matches('.').trim();
+
+    const RESERVED: &[&str] = &["con", "prn", "aux", "nul"];
+    let lower = cleaned.to_lowercase();
+    let reserved = RESERVED.contains(&lower.as_str())
+        || (lower.len() == 4
+            && (lower.starts_with("com") || lower.starts_with("lpt"))
+            && lower.ends_with(|c: char| c.is_ascii_digit()));
+    match cleaned {
+        "" => "Untitled".to_string(),
+        name if reserved => format!("{name}_"),
+        name => name.to_string(),
+    }
+}
+
+/// Every Markdown file below `dir` as a record with its folder path set
+/// from its subdirectory. Hidden files and directories such as `.git` and
+/// `.obsidian` are skipped. Records are located by their path relative
+/// to `dir`.
+pub fn read(dir: &Path) -> Result<RecordSet, MarkdownError> {
+    if !dir.is_dir() {
+        return Err(MarkdownError::NotADirectory(dir.to_path_buf()));
+    }
+    let mut set = RecordSet::default();
+    read_dir(dir, &mut Vec::new(), &mut set)?;
+    Ok(set)
+}
+
+fn read_dir(
+    dir: &Path,
+    folder: &mut Vec<String>,
+    set: &mut RecordSet,
+) -> Result<(), MarkdownError> {
+    let mut entries = fs::read_dir(dir)
+        .and_then(|entries| entries.collect::<Result<Vec<_>, _>>())
+        .map_err(io_error(dir))?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') {
+            continue;
+        }
+        if path.is_dir() {
+            folder.push(name);
+            read_dir(&path, folder, set)?;
+            folder.pop();
+            continue;
+        }
+        let is_markdown = path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case(EXTENSION));
+        if !is_markdown {
+            continue;
+        }
+
+        let location = folder
+            .iter()
+            .chain(std::iter::once(&name))
+            .cloned()
+            .collect::<Vec<_>>()
+            .join("/");
+        let stem = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        match read_file(&path, &stem) {
+            Ok(mut record) => {
+                record.folder = Some(folder.clone());
+                set.records.push((location, record));
+            }
+            Err(e) => set.errors.push(RecordError {
+                location,
+                message: e.to_string(),
+            }),
+        }
+    }
+    Ok(())
+}
+
+fn read_file(path: &Path, name: &str) -> Result<PromptRecord, MarkdownError> {
+    let size = fs::metadata(path).map_err(io_error(path))?.len();
+    if size > MAX_FILE_BYTES {
+        return Err(MarkdownError::TooLarge);
+    }
+    let content = fs::read_to_string(path).map_err(io_error(path))?;
+    parse(&content, name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn documents_round_trip() {
+        let record = PromptRecord {
+            id: Some("p1".to_string()),
+            text: "---\nReview this {Language} diff\n".to_string(),
+            header: Some("Code: review".to_string()),
+            source: Some("manual".to_string()),
+            url: Some(String::new()),
+            folder: None,
+            tags: Some(vec!["backend".to_string(), "needs: quotes".to_string()]),
+            favorite: Some(true),
+            created_at: Some(1_714_555_800_000),
+            updated_at: Some(1_714_555_800_123),
+        };
+        let document = format(&record);
+        assert!(document.starts_with("---\nid: p1\nheader: 'Code: review'\n"));
+        assert!(document.contains("created: 2024-05-01T09:30:00.000Z\n"));
+        assert_eq!(parse(&document, "ignored").unwrap(), record);
+        assert_eq!(
+            parse(&document.replace('\n', "\r\n"), "ignored").unwrap(),
+            record
+        );
+    }
+
+    #[test]
+    fn plain_files_are_headed_by_their_name() {
+        let record = parse("Summarize this article\n", "Summaries").unwrap();
+        assert_eq!(record.text, "Summarize this article");
+        assert_eq!(record.header.as_deref(), Some("Summaries"));
+        assert_eq!(record.id, None);
+        assert_eq!(record.tags, None);
+
+        let empty = parse("---\n---\n\nJust text\n", "x").unwrap();
+        assert_eq!(empty.text, "Just text");
+        assert_eq!(empty.header.as_deref(), Some(""));
+        assert_eq!(empty.tags, Some(Vec::new()));
+
+        assert!(matches!(
+            parse("---\ntags: [\n---\ntext", "x"),
+            Err(MarkdownError::FrontMatter(_))
+        ));
+        assert!(matches!(
+            parse("---\ncreated: yesterday\n---\ntext", "x"),
+            Err(MarkdownError::Date("created", _))
+        ));
+    }
+
+    #[test]
+    fn names_are_safe_and_unique() {
+        assert_eq!(file_stem("a/b: c?"), "a-b- c-");
+        assert_eq!(file_stem("  ..  "), "Untitled");
+        assert_eq!(file_stem("CON"), "CON_");
+        assert_eq!(file_stem("com1"), "com1_");
+        assert_eq!(file_stem(&"x".repeat(200)).len(), MAX_NAME_CHARS);
+
+        let mut names = Names::default();
+        let dir = Path::new("out");
+        assert_eq!(names.claim(dir, "Review", EXTENSION), "Review.md");
+        assert_eq!(names.claim(dir, "review", EXTENSION), "review (2).md");
+        assert_eq!(names.claim(dir, "Review", ""), "Review");
+        assert_eq!(
+            names.claim(Path::new("other"), "Review", EXTENSION),
+            "Review.md"
+        );
+    }
+}
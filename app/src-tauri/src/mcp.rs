@@ -0,0 +1,584 @@
+//! Model Context Protocol server, so AI clients can use prompts from the
+//! library.
+//!
+//! Every prompt is an MCP prompt named by its id, with its template
+//! variables as arguments, and a `search_prompts` tool finds prompts by
+//! text. Messages are JSON-RPC 2.0, one per line on stdio or one per POST to
+//! `/mcp` over local HTTP. The server can be limited to a folder subtree and
+//! to tags. It opens the library on first use, so a locked vault is reported
+//! to the client rather than ending the session.
+
+use crate::bridge;
+use crate::cli;
+use crate::db::DbError;
+use crate::error::{CommandError, ErrorCode};
+use crate::folders;
+use crate::repository::{Prompt, PromptFilter, PromptPageQuery, PromptRepository};
+use crate::template;
+use http_body_util::Full;
+use hyper::body::{Bytes, Incoming};
+use hyper::header::{self, HeaderValue};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use rusqlite::Connection;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::io::{BufRead, Write};
+use std::net::Ipv4Addr;
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+
+/// Protocol revisions we speak, newest first
+const PROTOCOL_VERSIONS: &[&str] = &["2025-06-18", "2025-03-26", "2024-11-05"];
+
+const SEARCH_TOOL: &str = "search_prompts";
+
+/// Results returned by the search tool unless the client asks for more
+const SEARCH_LIMIT: u32 = 10;
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+
+type Opener = Box<dyn Fn() -> Result<Connection, CommandError> + Send + Sync>;
+
+/// Answers MCP requests from one library
+pub struct McpServer {
+    open: Opener,
+    /// Prompts outside this filter are hidden from clients
+    scope: PromptFilter,
+    conn: Mutex<Option<Connection>>,
+}
+
+#[derive(Debug)]
+struct RpcError {
+    code: i64,
+    message: String,
+    data: Option<Value>,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+}
+
+impl From<CommandError> for RpcError {
+    fn from(e: CommandError) -> Self {
+        let code = match e.code {
+            ErrorCode::NotFound | ErrorCode::Validation => INVALID_PARAMS,
+            _ => INTERNAL_ERROR,
+        };
+        Self {
+            code,
+            message: e.message.clone(),
+            data: serde_json::to_value(&e).ok(),
+        }
+    }
+}
+
+impl From<DbError> for RpcError {
+    fn from(e: DbError) -> Self {
+        CommandError::from(e).into()
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct ListParams {
+    cursor: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GetParams {
+    name: String,
+    #[serde(default)]
+    arguments: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct ToolCall {
+    name: String,
+    #[serde(default)]
+    arguments: Value,
+}
+
+#[derive(Deserialize)]
+struct SearchArgs {
+    query: String,
+    #[serde(default)]
+    folder_id: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    limit: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct McpPrompt {
+    name: String,
+    title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    arguments: Vec<McpArgument>,
+}
+
+#[derive(Serialize)]
+struct McpArgument {
+    name: String,
+    description: String,
+    required: bool,
+}
+
+/// A prompt as the search tool reports it
+#[derive(Serialize)]
+struct Found {
+    id: String,
+    title: String,
+    folder: Vec<String>,
+    tags: Vec<String>,
+    variables: Vec<String>,
+    text: String,
+}
+
+impl McpServer {
+    /// `open` connects to the library; it is called again after a failure
+    pub fn new(
+        open: impl Fn() -> Result<Connection, CommandError> + Send + Sync + 'static,
+        scope: PromptFilter,
+    ) -> Self {
+        Self {
+            open: Box::new(open),
+            scope,
+            conn: Mutex::new(None),
+        }
+    }
+
+    /// Reply to one JSON-RPC message, or None when it needs no reply
+    pub fn handle(&self, message: &str) -> Option<Value> {
+        let message: Value = match serde_json::from_str(message) {
+            Ok(message) => message,
+            Err(e) => {
+                return Some(reply(
+                    Value::Null,
+                    Err(RpcError::new(PARSE_ERROR, e.to_string())),
+                ))
+            }
+        };
+        let id = message.get("id").cloned();
+        let Some(method) = message.get("method").and_then(Value::as_str) else {
+            // We never send requests, so there are no responses to accept
+            if message.get("result").is_some() || message.get("error").is_some() {
+                return None;
+            }
+            return Some(reply(
+                id.unwrap_or(Value::Null),
+                Err(RpcError::new(
+                    INVALID_REQUEST,
+                    "Expected a JSON-RPC request",
+                )),
+            ));
+        };
+
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+        let result = match method {
+            "initialize" => Ok(initialize(&params)),
+            "ping" => Ok(json!({})),
+            "prompts/list" => parse(params).and_then(|p| self.list(p)),
+            "prompts/get" => parse(params).and_then(|p| self.get(p)),
+            "tools/list" => Ok(json!({ "tools": [search_tool()] })),
+            "tools/call" => parse(params).and_then(|p| self.call_tool(p)),
+            _ if method.starts_with("notifications/") => Ok(Value::Null),
+            _ => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("Unknown method {method}"),
+            )),
+        };
+        // Notifications carry no id and get no reply
+        id.map(|id| reply(id, result))
+    }
+
+    /// Run `f` on the library, opening it first if needed. The connection is
+    /// dropped after a failure other than bad input, so the next request
+    /// opens the library afresh.
+    fn with_library<T>(
+        &self,
+        f: impl FnOnce(&Connection) -> Result<T, DbError>,
+    ) -> Result<T, RpcError> {
+        let mut guard = self
+            .conn
+            .lock()
+            .map_err(|_| RpcError::new(INTERNAL_ERROR, "Library connection poisoned"))?;
+        // Failing to open is never the client's fault, even when the
+        // library file is missing
+        let conn = match guard.take() {
+            Some(conn) => conn,
+            None => (self.open)().map_err(|e| RpcError {
+                code: INTERNAL_ERROR,
+                ..e.into()
+            })?,
+        };
+        let result = f(&conn).map_err(CommandError::from);
+        let keep = match &result {
+            Ok(_) => true,
+            Err(e) => matches!(e.code, ErrorCode::NotFound | ErrorCode::Validation),
+        };
+        if keep {
+            *guard = Some(conn);
+        }
+        Ok(result?)
+    }
+
+    fn list(&self, params: ListParams) -> Result<Value, RpcError> {
+        let query = PromptPageQuery {
+            filter: self.scope.clone(),
+            cursor: params.cursor,
+            ..Default::default()
+        };
+        self.with_library(|conn| {
+            let page = PromptRepository::new(conn).page(&query, now())?;
+            let paths = folders::paths(conn)?;
+            let mut prompts = Vec::new();
+            for prompt in &page.prompts {
+                prompts.push(describe(conn, prompt, &paths)?);
+            }
+            let mut result = json!({ "prompts": prompts });
+            if let Some(cursor) = page.next_cursor {
+                result["nextCursor"] = cursor.into();
+            }
+            Ok(result)
+        })
+    }
+
+    /// The prompt rendered with the client's arguments as one user message.
+    /// Fetching a prompt counts as using it.
+    fn get(&self, params: GetParams) -> Result<Value, RpcError> {
+        let scope = &self.scope;
+        let prompt = self.with_library(|conn| {
+            let prompts = PromptRepository::new(conn);
+            let now = now();
+            if !prompts.matches(&params.name, scope, now)? {
+                return Err(DbError::NotFound("Prompt"));
+            }
+            prompts.record_use(&params.name, now)
+        })?;
+
+        Ok(json!({
+            "description": cli::title(prompt.header.as_deref(), &prompt.text),
+            "messages": [{
+                "role": "user",
+                "content": {
+                    "type": "text",
+                    "text": template::render(&prompt.text, &params.arguments),
+                },
+            }],
+        }))
+    }
+
+    /// Tool failures are reported in the result, so the model can see them
+    fn call_tool(&self, call: ToolCall) -> Result<Value, RpcError> {
+        if call.name != SEARCH_TOOL {
+            return Err(RpcError::new(
+                INVALID_PARAMS,
+                format!("Unknown tool {}", call.name),
+            ));
+        }
+        let args: SearchArgs = parse(call.arguments)?;
+        if args.query.trim().is_empty() {
+            return Ok(tool_error("query must not be empty"));
+        }
+
+        match self.search(&args) {
+            Ok(found) => {
+                let text = if found.is_empty() {
+                    format!("No prompts match \"{}\"", args.query)
+                } else {
+                    found.iter().map(found_text).collect::<Vec<_>>().join("\n")
+                };
+                Ok(json!({
+                    "content": [{ "type": "text", "text": text }],
+                    "structuredContent": { "prompts": found },
+                }))
+            }
+            Err(e) => Ok(tool_error(&e.message)),
+        }
+    }
+
+    /// Prompts in scope matching the query, narrowed to the requested
+    /// folder and tags
+    fn search(&self, args: &SearchArgs) -> Result<Vec<Found>, RpcError> {
+        let mut query = PromptPageQuery {
+            filter: self.scope.clone(),
+            limit: Some(args.limit.unwrap_or(SEARCH_LIMIT)),
+            ..Default::default()
+        };
+        query.filter.text = Some(args.query.clone());
+        query.filter.tags.extend(args.tags.iter().cloned());
+
+        self.with_library(|conn| {
+            if let Some(folder_id) = &args.folder_id {
+                if let Some(root) = &self.scope.folder_id {
+                    if !folders::is_in_subtree(conn, root, folder_id)? {
+                        return Ok(Vec::new());
+                    }
+                }
+                query.filter.folder_id = Some(folder_id.clone());
+                query.filter.include_subfolders = true;
+            }
+
+            let prompts = PromptRepository::new(conn);
+            let paths = folders::paths(conn)?;
+            let mut found = Vec::new();
+            for prompt in prompts.page(&query, now())?.prompts {
+                found.push(Found {
+                    title: cli::title(prompt.header.as_deref(), &prompt.text),
+                    folder: folder_path(&prompt, &paths),
+                    tags: prompts.tag_names(&prompt.id)?,
+                    variables: template::variables(&prompt.text),
+                    id: prompt.id,
+                    text: prompt.text,
+                });
+            }
+            Ok(found)
+        })
+    }
+}
+
+fn now() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+/// Request params as `T`; missing params count as an empty object
+fn parse<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    let params = if params.is_null() { json!({}) } else { params };
+    serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))
+}
+
+fn reply(id: Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(e) => {
+            let mut error = json!({ "code": e.code, "message": e.message });
+            if let Some(data) = e.data {
+                error["data"] = data;
+            }
+            json!({ "jsonrpc": "2.0", "id": id, "error": error })
+        }
+    }
+}
+
+fn initialize(params: &Value) -> Value {
+    let requested = params.get("protocolVersion").and_then(Value::as_str);
+    let version = PROTOCOL_VERSIONS
+        .iter()
+        .find(|v| Some(**v) == requested)
+        .unwrap_or(&PROTOCOL_VERSIONS[0]);
+    json!({
+        "protocolVersion": version,
+        "capabilities": {
+            "prompts": { "listChanged": false },
+            "tools": { "listChanged": false },
+        },
+        "serverInfo": {
+            "name": "promptpack",
+            "title": "PromptPack",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "instructions": "Prompts from the user's PromptPack library. \
+            Use search_prompts to find one, then get it by id with its {Variables} as arguments.",
+    })
+}
+
+fn search_tool() -> Value {
+    json!({
+        "name": SEARCH_TOOL,
+        "title": "Search prompts",
+        "description": "Full-text search over the user's prompt library. \
+            Returns matching prompts with their id, folder, tags, template variables and text.",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "query": { "type": "string", "description": "Words to look for in prompt text and headers" },
+                "folder_id": { "type": "string", "description": "Only prompts in this folder or its subfolders" },
+                "tags": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "Only prompts carrying all of these tags",
+                },
+                "limit": { "type": "integer", "minimum": 1, "maximum": 100 },
+            },
+            "required": ["query"],
+        },
+    })
+}
+
+fn tool_error(message: &str) -> Value {
+    json!({
+        "content": [{ "type": "text", "text": message }],
+        "isError": true,
+    })
+}
+
+fn folder_path(prompt: &Prompt, paths: &HashMap<String, Vec<String>>) -> Vec<String> {
+    prompt
+        .folder_id
+        .as_ref()
+        .and_then(|id| paths.get(id))
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// A prompt as an MCP prompt. Each template variable becomes an argument.
+fn describe(
+    conn: &Connection,
+    prompt: &Prompt,
+    paths: &HashMap<String, Vec<String>>,
+) -> Result<McpPrompt, DbError> {
+    let folder = folder_path(prompt, paths);
+    let tags = PromptRepository::new(conn).tag_names(&prompt.id)?;
+    let mut details = Vec::new();
+    if !folder.is_empty() {
+        details.push(format!("Folder: {}", folder.join(" / ")));
+    }
+    if !tags.is_empty() {
+        details.push(format!("Tags: {}", tags.join(", ")));
+    }
+
+    Ok(McpPrompt {
+        name: prompt.id.clone(),
+        title: cli::title(prompt.header.as_deref(), &prompt.text),
+        description: (!details.is_empty()).then(|| details.join(". ")),
+        arguments: template::variables(&prompt.text)
+            .into_iter()
+            .map(|name| McpArgument {
+                description: format!("Replaces {{{name}}} in the prompt"),
+                name,
+                required: true,
+            })
+            .collect(),
+    })
+}
+
+fn found_text(found: &Found) -> String {
+    let mut text = format!("## {}\nid: {}\n", found.title, found.id);
+    if !found.folder.is_empty() {
+        text.push_str(&format!("folder: {}\n", found.folder.join(" / ")));
+    }
+    if !found.tags.is_empty() {
+        text.push_str(&format!("tags: {}\n", found.tags.join(", ")));
+    }
+    text.push('\n');
+    text.push_str(&found.text);
+    text.push('\n');
+    text
+}
+
+// ============ Transports ============
+
+/// Answer messages from stdin on stdout until stdin closes
+pub fn serve_stdio(server: &McpServer) -> Result<(), CommandError> {
+    let mut stdout = std::io::stdout().lock();
+    for line in std::io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(reply) = server.handle(&line) {
+            serde_json::to_writer(&mut stdout, &reply)?;
+            writeln!(stdout)?;
+            stdout.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// Answer POSTs to `http://127.0.0.1:<port>/mcp` until the process ends.
+/// With a token, clients must send it as a bearer token.
+pub fn serve_http(server: McpServer, port: u16, token: Option<String>) -> Result<(), CommandError> {
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(async move {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port)).await?;
+        let port = listener.local_addr()?.port();
+        eprintln!("MCP server listening on http://127.0.0.1:{port}/mcp");
+
+        let server = Arc::new(server);
+        let token: Option<Arc<str>> = token.filter(|t| !t.is_empty()).map(Into::into);
+        loop {
+            let (stream, _) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    log::warn!("MCP server failed to accept a connection: {}", e);
+                    continue;
+                }
+            };
+            let server = server.clone();
+            let token = token.clone();
+            let service = service_fn(move |req| {
+                let server = server.clone();
+                let token = token.clone();
+                async move { Ok::<_, Infallible>(respond(server, token.as_deref(), port, req).await) }
+            });
+            tokio::spawn(async move {
+                if let Err(e) = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await
+                {
+                    log::debug!("MCP connection closed: {}", e);
+                }
+            });
+        }
+    })
+}
+
+async fn respond(
+    server: Arc<McpServer>,
+    token: Option<&str>,
+    port: u16,
+    req: Request<Incoming>,
+) -> Response<Full<Bytes>> {
+    // Browsers may only reach the server from a page it serves, which is none
+    if !bridge::is_loopback_host(req.headers(), port) || req.headers().contains_key(header::ORIGIN)
+    {
+        return bridge::empty_response(StatusCode::FORBIDDEN);
+    }
+    if token.is_some_and(|token| !bridge::is_authorized(req.headers(), token)) {
+        return bridge::error_response(CommandError::new(
+            ErrorCode::InvalidToken,
+            "Missing or wrong token",
+        ));
+    }
+    if req.uri().path() != "/mcp" {
+        return bridge::empty_response(StatusCode::NOT_FOUND);
+    }
+    if req.method() != Method::POST {
+        let mut response = bridge::empty_response(StatusCode::METHOD_NOT_ALLOWED);
+        response
+            .headers_mut()
+            .insert(header::ALLOW, HeaderValue::from_static("POST"));
+        return response;
+    }
+
+    let body = match bridge::read_body(req).await {
+        Ok(body) => String::from_utf8_lossy(&body).into_owned(),
+        Err(e) => return bridge::error_response(e),
+    };
+    match tokio::task::spawn_blocking(move || server.handle(&body)).await {
+        Ok(Some(reply)) => {
+            bridge::json_response(StatusCode::OK, &reply).unwrap_or_else(bridge::error_response)
+        }
+        Ok(None) => bridge::empty_response(StatusCode::ACCEPTED),
+        Err(e) => bridge::error_response(CommandError::internal(e.to_string())),
+    }
+}
@@ -0,0 +1,1319 @@
+//! Prompt and folder storage over a plain `rusqlite::Connection`.
+//!
+//! Nothing here knows about Tauri, so the same code backs the commands and
+//! the integration tests. Writes are validated and journaled for undo;
+//! callers should run them inside a transaction.
+
+use crate::db::DbError;
+use crate::filter::{self, Cursor, SortKey};
+use crate::folders;
+use crate::journal::{self, EntityKind};
+use crate::trash;
+use crate::validation::{self, ValidationError, Validator};
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+pub use crate::filter::{PromptFilter, PromptPageQuery};
+pub use crate::folders::{DeleteFolderMode, DeleteFolderResult};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Prompt {
+    pub id: String,
+    pub text: String,
+    pub header: Option<String>,
+    pub source: String,
+    pub url: Option<String>,
+    pub folder_id: Option<String>,
+    pub is_favorite: bool,
+    pub use_count: i32,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub sync_status: String,
+    pub cloud_id: Option<String>,
+    pub deleted_at: Option<i64>,
+    pub last_used_at: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Folder {
+    pub id: String,
+    pub name: String,
+    pub icon: Option<String>,
+    pub color: Option<String>,
+    pub parent_id: Option<String>,
+    pub sort_order: i32,
+    pub created_at: i64,
+    pub deleted_at: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct CreatePromptInput {
+    pub text: String,
+    pub header: Option<String>,
+    pub source: Option<String>,
+    pub url: Option<String>,
+    pub folder_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct UpdatePromptInput {
+    pub text: Option<String>,
+    pub header: Option<String>,
+    pub source: Option<String>,
+    pub url: Option<String>,
+    pub folder_id: Option<String>,
+    pub is_favorite: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct CreateFolderInput {
+    pub name: String,
+    pub icon: Option<String>,
+    pub color: Option<String>,
+    pub parent_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct UpdateFolderInput {
+    pub name: Option<String>,
+    pub icon: Option<String>,
+    pub color: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PromptPage {
+    pub prompts: Vec<Prompt>,
+    /// Pass back as `cursor` to fetch the next page; None on the last page
+    pub next_cursor: Option<String>,
+    /// Prompts matching the filter across all pages
+    pub total: i64,
+    /// Whether `text` holds a truncated preview
+    pub preview: bool,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct FolderNode {
+    #[serde(flatten)]
+    pub folder: Folder,
+    /// Prompts directly in this folder
+    pub prompt_count: i64,
+    /// Prompts in this folder and all of its subfolders
+    pub total_prompt_count: i64,
+    pub children: Vec<FolderNode>,
+}
+
+/// A prompt as it travels in an export file, with its folder as a path of
+/// names and its tags by name. Usage and sync state stay behind.
+///
+/// On import, a `None` field keeps the current value of an existing prompt,
+/// and an empty header or URL clears it.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct PromptRecord {
+    pub id: Option<String>,
+    pub text: String,
+    pub header: Option<String>,
+    pub source: Option<String>,
+    pub url: Option<String>,
+    /// Folder names below the folder being exported or imported into;
+    /// empty for a prompt directly in it
+    pub folder: Option<Vec<String>>,
+    pub tags: Option<Vec<String>>,
+    pub favorite: Option<bool>,
+    pub created_at: Option<i64>,
+    pub updated_at: Option<i64>,
+}
+
+/// What an import did, or would do in a dry run
+#[derive(Debug, Serialize, Clone, Default, PartialEq)]
+pub struct ImportReport {
+    pub created: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub folders_created: usize,
+    /// Records that were skipped
+    pub errors: Vec<RecordError>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RecordError {
+    /// File or row the record came from
+    pub location: String,
+    pub message: String,
+}
+
+/// Prompts inserted from a pack file
+#[derive(Debug, Serialize, Clone)]
+pub struct PackImport {
+    pub prompts: Vec<Prompt>,
+    /// Entries that were skipped
+    pub errors: Vec<RecordError>,
+}
+
+/// Records read from an export file or directory
+#[derive(Debug, Default)]
+pub struct RecordSet {
+    /// Each record with the file or row it came from
+    pub records: Vec<(String, PromptRecord)>,
+    /// Entries that couldn't be read
+    pub errors: Vec<RecordError>,
+}
+
+const PROMPT_COLUMNS: &str = "id, text, header, source, url, folder_id, is_favorite, use_count, \
+                              created_at, updated_at, sync_status, cloud_id, deleted_at, \
+                              last_used_at";
+
+const FOLDER_COLUMNS: &str = "id, name, icon, color, parent_id, sort_order, created_at, deleted_at";
+
+/// Characters of `text` returned in preview mode
+const PREVIEW_CHARS: u32 = 200;
+
+/// Most results a full-text search returns
+const SEARCH_LIMIT: u32 = 200;
+
+fn prompt_from_row(row: &rusqlite::Row) -> Result<Prompt, rusqlite::Error> {
+    Ok(Prompt {
+        id: row.get(0)?,
+        text: row.get(1)?,
+        header: row.get(2)?,
+        source: row.get(3)?,
+        url: row.get(4)?,
+        folder_id: row.get(5)?,
+        is_favorite: row.get::<_, i32>(6)? != 0,
+        use_count: row.get(7)?,
+        created_at: row.get(8)?,
+        updated_at: row.get(9)?,
+        sync_status: row.get(10)?,
+        cloud_id: row.get(11)?,
+        deleted_at: row.get(12)?,
+        last_used_at: row.get(13)?,
+    })
+}
+
+fn folder_from_row(row: &rusqlite::Row) -> Result<Folder, rusqlite::Error> {
+    Ok(Folder {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        icon: row.get(2)?,
+        color: row.get(3)?,
+        parent_id: row.get(4)?,
+        sort_order: row.get(5)?,
+        created_at: row.get(6)?,
+        deleted_at: row.get(7)?,
+    })
+}
+
+/// Build a `SET a = ?1, b = ?2` update from the fields that are present
+#[derive(Default)]
+struct Assignments {
+    columns: Vec<String>,
+    params: Vec<Box<dyn rusqlite::ToSql>>,
+}
+
+impl Assignments {
+    fn set(&mut self, column: &str, value: impl rusqlite::ToSql + 'static) {
+        self.params.push(Box::new(value));
+        self.columns
+            .push(format!("{column} = ?{}", self.params.len()));
+    }
+
+    fn is_empty(&self) -> bool {
+        self.columns.is_empty()
+    }
+
+    /// Run the update against the row with primary key `id`
+    fn execute(mut self, conn: &Connection, table: &str, id: &str) -> Result<usize, DbError> {
+        self.params.push(Box::new(id.to_string()));
+        let sql = format!(
+            "UPDATE {table} SET {} WHERE id = ?{}",
+            self.columns.join(", "),
+            self.params.len()
+        );
+        let param_refs: Vec<&dyn rusqlite::ToSql> =
+            self.params.iter().map(|p| p.as_ref()).collect();
+        Ok(conn.execute(&sql, param_refs.as_slice())?)
+    }
+}
+
+pub struct PromptRepository<'a> {
+    conn: &'a Connection,
+}
+
+impl<'a> PromptRepository<'a> {
+    pub fn new(conn: &'a Connection) -> Self {
+        Self { conn }
+    }
+
+    fn query(&self, sql: &str, params: impl rusqlite::Params) -> Result<Vec<Prompt>, DbError> {
+        let mut stmt = self.conn.prepare(sql)?;
+        let prompts = stmt
+            .query_map(params, prompt_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(prompts)
+    }
+
+    /// A prompt by id, whether or not it is in the trash
+    pub fn get(&self, id: &str) -> Result<Option<Prompt>, DbError> {
+        Ok(self
+            .conn
+            .query_row(
+                &format!("SELECT {PROMPT_COLUMNS} FROM prompts WHERE id = ?"),
+                [id],
+                prompt_from_row,
+            )
+            .optional()?)
+    }
+
+    /// A live prompt with the same text, ignoring surrounding whitespace
+    pub fn find_by_text(&self, text: &str) -> Result<Option<Prompt>, DbError> {
+        Ok(self
+            .conn
+            .query_row(
+                &format!(
+                    "SELECT {PROMPT_COLUMNS} FROM prompts
+                     WHERE deleted_at IS NULL AND trim(text, ?2) = trim(?1, ?2)
+                     ORDER BY created_at LIMIT 1"
+                ),
+                [text, " \t\r\n"],
+                prompt_from_row,
+            )
+            .optional()?)
+    }
+
+    fn require(&self, id: &str) -> Result<Prompt, DbError> {
+        self.get(id)?.ok_or(DbError::NotFound("Prompt"))
+    }
+
+    /// One page of prompts. Without a query this returns the newest prompts
+    /// first.
+    pub fn page(&self, query: &PromptPageQuery, now: i64) -> Result<PromptPage, DbError> {
+        let (filter_sql, filter_params) = query.filter.to_sql(now);
+        let filter_refs: Vec<&dyn rusqlite::ToSql> =
+            filter_params.iter().map(|p| p.as_ref()).collect();
+        let total: i64 = self.conn.query_row(
+            &format!("SELECT COUNT(*) FROM prompts WHERE {filter_sql}"),
+            filter_refs.as_slice(),
+            |row| row.get(0),
+        )?;
+
+        // `text` is the second column; swap it for a prefix in preview mode
+        let columns = if query.preview {
+            PROMPT_COLUMNS.replacen("text", &format!("substr(text, 1, {PREVIEW_CHARS})"), 1)
+        } else {
+            PROMPT_COLUMNS.to_string()
+        };
+        let (conditions, order, mut params) = query.to_sql(now)?;
+        let page_size = query.page_size();
+        // One extra row tells us whether another page follows
+        params.push(Box::new(page_size + 1));
+
+        let sql = format!(
+            "SELECT {columns}, {sort} FROM prompts
+             WHERE {conditions}
+             ORDER BY {order}
+             LIMIT ?",
+            sort = query.sort_expr()
+        );
+        let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+        let mut stmt = self.conn.prepare(&sql)?;
+        let mut rows = stmt
+            .query_map(param_refs.as_slice(), |row| {
+                Ok((prompt_from_row(row)?, SortKey::from_sql(row.get_ref(14)?)))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let next_cursor = if rows.len() > page_size as usize {
+            rows.truncate(page_size as usize);
+            rows.last().and_then(|(prompt, key)| {
+                key.clone().map(|key| {
+                    Cursor {
+                        sort: query.sort,
+                        key,
+                        id: prompt.id.clone(),
+                    }
+                    .encode()
+                })
+            })
+        } else {
+            None
+        };
+
+        Ok(PromptPage {
+            prompts: rows.into_iter().map(|(prompt, _)| prompt).collect(),
+            next_cursor,
+            total,
+            preview: query.preview,
+        })
+    }
+
+    /// Full-text search over live prompts, best match first
+    pub fn search(&self, query: &str) -> Result<Vec<Prompt>, DbError> {
+        let fts = filter::fts_query(query);
+        if fts.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.query(
+            &format!(
+                "SELECT {PROMPT_COLUMNS} FROM prompts
+                 JOIN (SELECT rowid AS hit, rank FROM prompts_fts WHERE prompts_fts MATCH ?1)
+                   ON hit = prompts.rowid
+                 WHERE deleted_at IS NULL
+                 ORDER BY rank
+                 LIMIT {SEARCH_LIMIT}"
+            ),
+            [fts],
+        )
+    }
+
+    /// Prompts matching `filter`, newest first
+    pub fn filtered(&self, filter: &PromptFilter, now: i64) -> Result<Vec<Prompt>, DbError> {
+        let (conditions, params) = filter.to_sql(now);
+        let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+        self.query(
+            &format!(
+                "SELECT {PROMPT_COLUMNS} FROM prompts
+                 WHERE {conditions} ORDER BY created_at DESC"
+            ),
+            param_refs.as_slice(),
+        )
+    }
+
+    /// Whether the prompt is live and matches `filter`
+    pub fn matches(&self, id: &str, filter: &PromptFilter, now: i64) -> Result<bool, DbError> {
+        let (conditions, mut params) = filter.to_sql(now);
+        params.push(Box::new(id.to_string()));
+        let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+        Ok(self.conn.query_row(
+            &format!("SELECT EXISTS (SELECT 1 FROM prompts WHERE {conditions} AND id = ?)"),
+            param_refs.as_slice(),
+            |row| row.get(0),
+        )?)
+    }
+
+    /// Names of the prompt's tags, alphabetically
+    pub fn tag_names(&self, id: &str) -> Result<Vec<String>, DbError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT t.name FROM prompt_tags pt JOIN tags t ON t.id = pt.tag_id
+             WHERE pt.prompt_id = ?1 ORDER BY t.name COLLATE NOCASE",
+        )?;
+        let names = stmt
+            .query_map([id], |row| row.get(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(names)
+    }
+
+    /// Prompts in a folder, optionally including everything in its subfolders
+    pub fn in_folder(
+        &self,
+        folder_id: &str,
+        include_subfolders: bool,
+    ) -> Result<Vec<Prompt>, DbError> {
+        let scope = if include_subfolders {
+            format!("folder_id IN ({})", folders::SUBTREE)
+        } else {
+            "folder_id = ?1".to_string()
+        };
+        self.query(
+            &format!(
+                "SELECT {PROMPT_COLUMNS} FROM prompts
+                 WHERE deleted_at IS NULL AND {scope}
+                 ORDER BY created_at DESC"
+            ),
+            [folder_id],
+        )
+    }
+
+    /// Live favorites, most used first
+    pub fn favorites(&self, limit: usize) -> Result<Vec<Prompt>, DbError> {
+        self.query(
+            &format!(
+                "SELECT {PROMPT_COLUMNS} FROM prompts
+                 WHERE deleted_at IS NULL AND is_favorite = 1
+                 ORDER BY use_count DESC, updated_at DESC LIMIT ?"
+            ),
+            [limit],
+        )
+    }
+
+    /// Live prompts that have been used, most uses first
+    pub fn most_used(&self, limit: usize) -> Result<Vec<Prompt>, DbError> {
+        self.query(
+            &format!(
+                "SELECT {PROMPT_COLUMNS} FROM prompts
+                 WHERE deleted_at IS NULL AND use_count > 0
+                 ORDER BY use_count DESC, last_used_at DESC LIMIT ?"
+            ),
+            [limit],
+        )
+    }
+
+    /// Live prompts that have been used, most recent first
+    pub fn recently_used(&self, limit: usize) -> Result<Vec<Prompt>, DbError> {
+        self.query(
+            &format!(
+                "SELECT {PROMPT_COLUMNS} FROM prompts
+                 WHERE deleted_at IS NULL AND last_used_at IS NOT NULL
+                 ORDER BY last_used_at DESC LIMIT ?"
+            ),
+            [limit],
+        )
+    }
+
+    /// Prompts in the trash, most recently deleted first
+    pub fn trashed(&self) -> Result<Vec<Prompt>, DbError> {
+        self.query(
+            &format!(
+                "SELECT {PROMPT_COLUMNS} FROM prompts
+                 WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC"
+            ),
+            [],
+        )
+    }
+
+    pub fn create(&self, input: CreatePromptInput, now: i64) -> Result<Prompt, DbError> {
+        Validator::new()
+            .required("text", &input.text, validation::MAX_TEXT_CHARS)
+            .optional(
+                "header",
+                input.header.as_deref(),
+                validation::MAX_HEADER_CHARS,
+            )
+            .source("source", input.source.as_deref())
+            .url("url", input.url.as_deref())
+            .folder(self.conn, "folder_id", input.folder_id.as_deref())?
+            .finish()?;
+
+        let id = uuid::Uuid::new_v4().to_string();
+        let source = input.source.unwrap_or_else(|| "manual".to_string());
+
+        let scope = [(EntityKind::Prompt, id.clone())];
+        journal::track(self.conn, "Create prompt", &scope, now, |conn| {
+            conn.execute(
+                "INSERT INTO prompts (id, text, header, source, url, folder_id, is_favorite, use_count, created_at, updated_at, sync_status)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, 0, 0, ?7, ?7, 'local-only')",
+                rusqlite::params![id, input.text, input.header, source, input.url, input.folder_id, now],
+            )?;
+            Ok(())
+        })?;
+
+        Ok(Prompt {
+            id,
+            text: input.text,
+            header: input.header,
+            source,
+            url: input.url,
+            folder_id: input.folder_id,
+            is_favorite: false,
+            use_count: 0,
+            created_at: now,
+            updated_at: now,
+            sync_status: "local-only".to_string(),
+            cloud_id: None,
+            deleted_at: None,
+            last_used_at: None,
+        })
+    }
+
+    /// Apply the fields present in `input`. Revision compaction is left to
+    /// the caller, since it depends on the history settings.
+    pub fn update(&self, id: &str, input: UpdatePromptInput, now: i64) -> Result<Prompt, DbError> {
+        let mut validator = Validator::new();
+        if let Some(text) = &input.text {
+            validator.required("text", text, validation::MAX_TEXT_CHARS);
+        }
+        validator
+            .optional(
+                "header",
+                input.header.as_deref(),
+                validation::MAX_HEADER_CHARS,
+            )
+            .source("source", input.source.as_deref())
+            .url("url", input.url.as_deref())
+            .folder(self.conn, "folder_id", input.folder_id.as_deref())?
+            .finish()?;
+
+        let mut assignments = Assignments::default();
+        assignments.set("updated_at", now);
+        if let Some(text) = input.text {
+            assignments.set("text", text);
+        }
+        if let Some(header) = input.header {
+            assignments.set("header", header);
+        }
+        if let Some(source) = input.source {
+            assignments.set("source", source);
+        }
+        if let Some(url) = input.url {
+            assignments.set("url", url);
+        }
+        if let Some(folder_id) = input.folder_id {
+            assignments.set("folder_id", folder_id);
+        }
+        if let Some(is_favorite) = input.is_favorite {
+            assignments.set("is_favorite", i32::from(is_favorite));
+        }
+
+        let scope = [(EntityKind::Prompt, id.to_string())];
+        journal::track(self.conn, "Edit prompt", &scope, now, |conn| {
+            if assignments.execute(conn, "prompts", id)? == 0 {
+                return Err(DbError::NotFound("Prompt"));
+            }
+            Ok(())
+        })?;
+        self.require(id)
+    }
+
+    /// Move a prompt to the trash
+    pub fn delete(&self, id: &str, now: i64) -> Result<(), DbError> {
+        let scope = [(EntityKind::Prompt, id.to_string())];
+        journal::track(self.conn, "Delete prompt", &scope, now, |conn| {
+            trash::trash_prompt(conn, id, now)
+        })
+    }
+
+    /// Take a prompt back out of the trash
+    pub fn restore(&self, id: &str, now: i64) -> Result<Prompt, DbError> {
+        let scope = [(EntityKind::Prompt, id.to_string())];
+        journal::track(self.conn, "Restore prompt", &scope, now, |conn| {
+            trash::restore_prompt(conn, id)
+        })?;
+        self.require(id)
+    }
+
+    /// Restore an earlier revision. Returns false when the text already
+    /// matched and nothing changed.
+    pub fn revert(&self, id: &str, revision_id: i64, now: i64) -> Result<bool, DbError> {
+        let scope = [(EntityKind::Prompt, id.to_string())];
+        journal::track(self.conn, "Revert prompt", &scope, now, |conn| {
+            crate::revisions::revert_to(conn, id, revision_id, now)
+        })
+    }
+
+    /// Bump a prompt's use count. Usage isn't journaled.
+    pub fn record_use(&self, id: &str, now: i64) -> Result<Prompt, DbError> {
+        self.conn.execute(
+            "UPDATE prompts SET use_count = use_count + 1, last_used_at = ?2 WHERE id = ?1",
+            rusqlite::params![id, now],
+        )?;
+        self.require(id)
+    }
+
+    /// Insert the prompts of a pack file as one undoable operation. Entries
+    /// that fail the same checks as `create` are skipped and reported.
+    pub fn import(&self, entries: &[serde_json::Value], now: i64) -> Result<PackImport, DbError> {
+        let mut errors = Vec::new();
+        let mut valid = Vec::new();
+        for (i, entry) in entries.iter().enumerate() {
+            let field = |name| entry.get(name).and_then(|v| v.as_str()).map(str::to_string);
+            let record = PromptRecord {
+                text: field("text").unwrap_or_default(),
+                header: field("header"),
+                source: field("source"),
+                ..Default::default()
+            };
+            match validate_record(&record) {
+                Ok(()) => valid.push((uuid::Uuid::new_v4().to_string(), record)),
+                Err(e) => errors.push(RecordError {
+                    location: format!("prompts[{i}]"),
+                    message: e.to_string(),
+                }),
+            }
+        }
+        let scope: Vec<(EntityKind, String)> = valid
+            .iter()
+            .map(|(id, _)| (EntityKind::Prompt, id.clone()))
+            .collect();
+
+        let mut imported = Vec::new();
+        journal::track(self.conn, "Import prompts", &scope, now, |conn| {
+            for (id, record) in valid {
+                let PromptRecord {
+                    text,
+                    header,
+                    source,
+                    ..
+                } = record;
+                let source = source.unwrap_or_else(|| "manual".to_string());
+
+                conn.execute(
+                    "INSERT INTO prompts (id, text, header, source, is_favorite, use_count, created_at, updated_at, sync_status)
+                     VALUES (?1, ?2, ?3, ?4, 0, 0, ?5, ?5, 'local-only')",
+                    rusqlite::params![id, text, header, source, now],
+                )?;
+
+                imported.push(Prompt {
+                    id,
+                    text,
+                    header,
+                    source,
+                    url: None,
+                    folder_id: None,
+                    is_favorite: false,
+                    use_count: 0,
+                    created_at: now,
+                    updated_at: now,
+                    sync_status: "local-only".to_string(),
+                    cloud_id: None,
+                    deleted_at: None,
+                    last_used_at: None,
+                });
+            }
+            Ok(())
+        })?;
+        Ok(PackImport {
+            prompts: imported,
+            errors,
+        })
+    }
+
+    /// Pack file JSON holding the given prompts, or every live prompt when
+    /// `ids` is None
+    pub fn export(&self, ids: Option<&[String]>, now: i64) -> Result<String, DbError> {
+        let (condition, params): (String, Vec<&dyn rusqlite::ToSql>) = match ids {
+            Some(ids) => (
+                format!("id IN ({})", vec!["?"; ids.len()].join(", ")),
+                ids.iter().map(|id| id as &dyn rusqlite::ToSql).collect(),
+            ),
+            None => ("deleted_at IS NULL".to_string(), Vec::new()),
+        };
+
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT text, header, source, created_at FROM prompts
+             WHERE {condition} ORDER BY created_at"
+        ))?;
+        let prompts = stmt
+            .query_map(params.as_slice(), |row| {
+                Ok(serde_json::json!({
+                    "text": row.get::<_, String>(0)?,
+                    "header": row.get::<_, Option<String>>(1)?,
+                    "source": row.get::<_, String>(2)?,
+                    "createdAt": row.get::<_, i64>(3)?,
+                }))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let pack = serde_json::json!({
+            "version": 1,
+            "exportedAt": now,
+            "prompts": prompts,
+        });
+        Ok(pack.to_string())
+    }
+
+    /// Live prompts as export records, oldest first. With `folder_id`, only
+    /// that folder's subtree, with paths starting at the folder itself.
+    pub fn records(&self, folder_id: Option<&str>) -> Result<Vec<PromptRecord>, DbError> {
+        let paths = folders::paths(self.conn)?;
+        let (scope, skip) = match folder_id {
+            Some(id) => {
+                folders::ensure_live(self.conn, id, "Folder")?;
+                let depth = paths.get(id).map_or(0, Vec::len);
+                (format!("AND folder_id IN ({})", folders::SUBTREE), depth)
+            }
+            None => (String::new(), 0),
+        };
+        let prompts = self.query(
+            &format!(
+                "SELECT {PROMPT_COLUMNS} FROM prompts
+                 WHERE deleted_at IS NULL {scope}
+                 ORDER BY created_at, id"
+            ),
+            rusqlite::params_from_iter(folder_id),
+        )?;
+
+        prompts
+            .into_iter()
+            .map(|prompt| {
+                let folder = prompt
+                    .folder_id
+                    .as_ref()
+                    .and_then(|id| paths.get(id))
+                    .map(|path| path[skip.min(path.len())..].to_vec())
+                    .unwrap_or_default();
+                Ok(PromptRecord {
+                    tags: Some(self.tag_names(&prompt.id)?),
+                    id: Some(prompt.id),
+                    text: prompt.text,
+                    header: prompt.header,
+                    source: Some(prompt.source),
+                    url: prompt.url,
+                    folder: Some(folder),
+                    favorite: Some(prompt.is_favorite),
+                    created_at: Some(prompt.created_at),
+                    updated_at: Some(prompt.updated_at),
+                })
+            })
+            .collect()
+    }
+
+    /// Create or update prompts from records as one undoable operation.
+    ///
+    /// A record whose id is in the library updates that prompt, bringing it
+    /// back from the trash if needed. Other records become new prompts,
+    /// keeping their id. Folders are matched by name below `folder_id` and
+    /// created when missing. Invalid records are skipped and reported along
+    /// with the entries the set couldn't read.
+    ///
+    /// Nothing is committed here, so rolling back the caller's transaction
+    /// gives a dry run.
+    pub fn import_records(
+        &self,
+        set: &RecordSet,
+        folder_id: Option<&str>,
+        now: i64,
+    ) -> Result<ImportReport, DbError> {
+        if let Some(id) = folder_id {
+            folders::ensure_live(self.conn, id, "Folder")?;
+        }
+        let mut report = ImportReport {
+            errors: set.errors.clone(),
+            ..Default::default()
+        };
+        let mut folder_plan = FolderPlan::new(folder_id);
+        let mut seen = HashSet::new();
+        let mut writes = Vec::new();
+
+        for (location, record) in &set.records {
+            let mut skip = |message: String| {
+                report.errors.push(RecordError {
+                    location: location.clone(),
+                    message,
+                })
+            };
+            if let Err(e) = validate_record(record) {
+                skip(e.to_string());
+                continue;
+            }
+            let id = match record.id.as_deref().map(str::trim) {
+                Some(id) if !id.is_empty() => id.to_string(),
+                _ => uuid::Uuid::new_v4().to_string(),
+            };
+            if !seen.insert(id.clone()) {
+                skip(format!("Another record also has id {id}"));
+                continue;
+            }
+
+            let folder_id = match &record.folder {
+                Some(path) => Some(folder_plan.resolve(self.conn, path)?),
+                None => None,
+            };
+            let existing = self.get(&id)?;
+            match &existing {
+                Some(prompt) if self.is_unchanged(prompt, record, &folder_id)? => {
+                    report.unchanged += 1;
+                    continue;
+                }
+                Some(_) => report.updated += 1,
+                None => report.created += 1,
+            }
+            writes.push((id, record, folder_id, existing));
+        }
+        report.folders_created = folder_plan.created.len();
+
+        let scope: Vec<(EntityKind, String)> = folder_plan
+            .created
+            .iter()
+            .map(|(id, _, _)| (EntityKind::Folder, id.clone()))
+            .chain(
+                writes
+                    .iter()
+                    .map(|(id, ..)| (EntityKind::Prompt, id.clone())),
+            )
+            .collect();
+        if scope.is_empty() {
+            return Ok(report);
+        }
+
+        journal::track(self.conn, "Import prompts", &scope, now, |conn| {
+            for (id, name, parent_id) in &folder_plan.created {
+                conn.execute(
+                    "INSERT INTO folders (id, name, parent_id, sort_order, created_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    rusqlite::params![
+                        id,
+                        name,
+                        parent_id,
+                        folders::next_sort_order(conn, parent_id.as_deref())?,
+                        now
+                    ],
+                )?;
+            }
+            for (id, record, folder_id, existing) in &writes {
+                write_record(conn, id, record, folder_id, existing.as_ref(), now)?;
+            }
+            Ok(())
+        })?;
+        Ok(report)
+    }
+
+    /// Whether importing `record` would leave `prompt` as it is
+    fn is_unchanged(
+        &self,
+        prompt: &Prompt,
+        record: &PromptRecord,
+        folder_id: &Option<Option<String>>,
+    ) -> Result<bool, DbError> {
+        let same = |current: &Option<String>, new: &Option<String>| {
+            new.iter().all(|new| current.as_deref() == non_empty(new))
+        };
+        let same_tags = match &record.tags {
+            Some(tags) => {
+                let mut current = self.tag_names(&prompt.id)?;
+                let mut new = unique_names(tags);
+                current.sort_by_key(|t| t.to_lowercase());
+                new.sort_by_key(|t| t.to_lowercase());
+                current.len() == new.len()
+                    && current
+                        .iter()
+                        .zip(&new)
+                        .all(|(a, b)| a.eq_ignore_ascii_case(b))
+            }
+            None => true,
+        };
+
+        Ok(prompt.deleted_at.is_none()
+            && prompt.text == record.text
+            && same(&prompt.header, &record.header)
+            && same(&prompt.url, &record.url)
+            && record.source.iter().all(|s| *s == prompt.source)
+            && record.favorite.iter().all(|f| *f == prompt.is_favorite)
+            && folder_id.iter().all(|f| *f == prompt.folder_id)
+            && same_tags)
+    }
+}
+
+/// Folders an import puts prompts in, found by name or planned for creation
+struct FolderPlan {
+    /// Folder for each path already seen; the root path maps to the folder
+    /// being imported into
+    resolved: HashMap<Vec<String>, Option<String>>,
+    /// (id, name, parent id) of folders to create, parents first
+    created: Vec<(String, String, Option<String>)>,
+}
+
+impl FolderPlan {
+    fn new(root: Option<&str>) -> Self {
+        Self {
+            resolved: HashMap::from([(Vec::new(), root.map(str::to_string))]),
+            created: Vec::new(),
+        }
+    }
+
+    fn resolve(&mut self, conn: &Connection, path: &[String]) -> Result<Option<String>, DbError> {
+        for depth in 1..=path.len() {
+            let prefix = &path[..depth];
+            if self.resolved.contains_key(prefix) {
+                continue;
+            }
+            let parent_id = self.resolved[&path[..depth - 1]].clone();
+            let name = path[depth - 1].trim();
+            let planned = self
+                .created
+                .iter()
+                .any(|(id, ..)| Some(id) == parent_id.as_ref());
+            let existing: Option<String> = if planned {
+                None
+            } else {
+                conn.query_row(
+                    "SELECT id FROM folders
+                     WHERE parent_id IS ?1 AND name = ?2 AND deleted_at IS NULL
+                     ORDER BY sort_order LIMIT 1",
+                    rusqlite::params![parent_id, name],
+                    |row| row.get(0),
+                )
+                .optional()?
+            };
+            let id = existing.unwrap_or_else(|| {
+                let id = uuid::Uuid::new_v4().to_string();
+                self.created.push((id.clone(), name.to_string(), parent_id));
+                id
+            });
+            self.resolved.insert(prefix.to_vec(), Some(id));
+        }
+        Ok(self.resolved[path].clone())
+    }
+}
+
+fn validate_record(record: &PromptRecord) -> Result<(), ValidationError> {
+    let mut validator = Validator::new();
+    validator
+        .required("text", &record.text, validation::MAX_TEXT_CHARS)
+        .optional(
+            "header",
+            record.header.as_deref(),
+            validation::MAX_HEADER_CHARS,
+        )
+        .source("source", record.source.as_deref())
+        .url("url", record.url.as_deref());
+    for name in record.folder.iter().flatten() {
+        validator.required("folder", name, validation::MAX_NAME_CHARS);
+    }
+    for tag in record.tags.iter().flatten() {
+        validator.required("tags", tag, validation::MAX_NAME_CHARS);
+    }
+    validator.finish()
+}
+
+fn non_empty(value: &str) -> Option<&str> {
+    Some(value.trim()).filter(|v| !v.is_empty())
+}
+
+/// Trimmed names without case-insensitive repeats, in their first order
+fn unique_names(names: &[String]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    names
+        .iter()
+        .map(|n| n.trim().to_string())
+        .filter(|n| seen.insert(n.to_lowercase()))
+        .collect()
+}
+
+/// Insert or update the prompt `id` from an import record
+fn write_record(
+    conn: &Connection,
+    id: &str,
+    record: &PromptRecord,
+    folder_id: &Option<Option<String>>,
+    existing: Option<&Prompt>,
+    now: i64,
+) -> Result<(), DbError> {
+    let optional =
+        |value: &Option<String>| value.as_deref().and_then(non_empty).map(str::to_string);
+    match existing {
+        Some(prompt) => {
+            if prompt.deleted_at.is_some() {
+                trash::restore_prompt(conn, id)?;
+            }
+            let mut assignments = Assignments::default();
+            assignments.set("updated_at", now);
+            assignments.set("text", record.text.clone());
+            if record.header.is_some() {
+                assignments.set("header", optional(&record.header));
+            }
+            if record.url.is_some() {
+                assignments.set("url", optional(&record.url));
+            }
+            if let Some(source) = &record.source {
+                assignments.set("source", source.clone());
+            }
+            if let Some(favorite) = record.favorite {
+                assignments.set("is_favorite", i32::from(favorite));
+            }
+            if let Some(folder_id) = folder_id {
+                assignments.set("folder_id", folder_id.clone());
+            }
+            assignments.execute(conn, "prompts", id)?;
+        }
+        None => {
+            conn.execute(
+                "INSERT INTO prompts (id, text, header, source, url, folder_id, is_favorite, use_count, created_at, updated_at, sync_status)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, 0, ?8, ?9, 'local-only')",
+                rusqlite::params![
+                    id,
+                    record.text,
+                    optional(&record.header),
+                    record.source.as_deref().unwrap_or("manual"),
+                    optional(&record.url),
+                    folder_id.clone().flatten(),
+                    i32::from(record.favorite.unwrap_or(false)),
+                    record.created_at.unwrap_or(now),
+                    now
+                ],
+            )?;
+        }
+    }
+
+    if let Some(tags) = &record.tags {
+        conn.execute("DELETE FROM prompt_tags WHERE prompt_id = ?1", [id])?;
+        for name in unique_names(tags) {
+            let tag_id: Option<String> = conn
+                .query_row(
+                    "SELECT id FROM tags WHERE name = ?1 COLLATE NOCASE",
+                    [&name],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            let tag_id = match tag_id {
+                Some(tag_id) => tag_id,
+                None => {
+                    let tag_id = uuid::Uuid::new_v4().to_string();
+                    conn.execute(
+                        "INSERT INTO tags (id, name) VALUES (?1, ?2)",
+                        [&tag_id, &name],
+                    )?;
+                    tag_id
+                }
+            };
+            conn.execute(
+                "INSERT OR IGNORE INTO prompt_tags (prompt_id, tag_id) VALUES (?1, ?2)",
+                [id, tag_id.as_str()],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Header, or the first line when there is none, cut to `max_chars`
+pub fn title(header: Option<&str>, text: &str, max_chars: usize) -> String {
+    let title = header
+        .filter(|h| !h.trim().is_empty())
+        .unwrap_or_else(|| text.lines().next().unwrap_or(""))
+        .trim();
+    match title.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}…", &title[..end]),
+        None => title.to_string(),
+    }
+}
+
+/// The prompt entries of a decoded pack file
+pub fn pack_entries(json: &str) -> Result<Vec<serde_json::Value>, DbError> {
+    let mut pack: serde_json::Value = serde_json::from_str(json)
+        .map_err(|e| DbError::Invalid(format!("Invalid pack format: {e}")))?;
+    match pack.get_mut("prompts").map(serde_json::Value::take) {
+        Some(serde_json::Value::Array(entries)) => Ok(entries),
+        _ => Err(DbError::Invalid(
+            "Invalid pack format: missing prompts array".to_string(),
+        )),
+    }
+}
+
+pub struct FolderRepository<'a> {
+    conn: &'a Connection,
+}
+
+impl<'a> FolderRepository<'a> {
+    pub fn new(conn: &'a Connection) -> Self {
+        Self { conn }
+    }
+
+    fn query(&self, sql: &str, params: impl rusqlite::Params) -> Result<Vec<Folder>, DbError> {
+        let mut stmt = self.conn.prepare(sql)?;
+        let folders = stmt
+            .query_map(params, folder_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(folders)
+    }
+
+    pub fn get(&self, id: &str) -> Result<Option<Folder>, DbError> {
+        Ok(self
+            .conn
+            .query_row(
+                &format!("SELECT {FOLDER_COLUMNS} FROM folders WHERE id = ?"),
+                [id],
+                folder_from_row,
+            )
+            .optional()?)
+    }
+
+    /// Folders outside the trash, in display order
+    pub fn list(&self) -> Result<Vec<Folder>, DbError> {
+        self.query(
+            &format!(
+                "SELECT {FOLDER_COLUMNS} FROM folders
+                 WHERE deleted_at IS NULL ORDER BY sort_order"
+            ),
+            [],
+        )
+    }
+
+    /// Folders in the trash, most recently deleted first
+    pub fn trashed(&self) -> Result<Vec<Folder>, DbError> {
+        self.query(
+            &format!(
+                "SELECT {FOLDER_COLUMNS} FROM folders
+                 WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC"
+            ),
+            [],
+        )
+    }
+
+    /// Live folders nested under their parents, with prompt counts
+    pub fn tree(&self) -> Result<Vec<FolderNode>, DbError> {
+        let folders = self.query(
+            &format!(
+                "SELECT {FOLDER_COLUMNS} FROM folders
+                 WHERE deleted_at IS NULL ORDER BY sort_order, created_at"
+            ),
+            [],
+        )?;
+
+        let mut stmt = self.conn.prepare(
+            "SELECT folder_id, COUNT(*) FROM prompts
+             WHERE folder_id IS NOT NULL AND deleted_at IS NULL
+             GROUP BY folder_id",
+        )?;
+        let counts = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<HashMap<String, i64>, _>>()?;
+
+        Ok(build_folder_tree(folders, &counts))
+    }
+
+    /// Create a folder after its existing siblings
+    pub fn create(&self, input: CreateFolderInput, now: i64) -> Result<Folder, DbError> {
+        Validator::new()
+            .required("name", &input.name, validation::MAX_NAME_CHARS)
+            .optional("icon", input.icon.as_deref(), validation::MAX_ICON_CHARS)
+            .color("color", input.color.as_deref())
+            .folder(self.conn, "parent_id", input.parent_id.as_deref())?
+            .finish()?;
+
+        let id = uuid::Uuid::new_v4().to_string();
+        let sort_order = folders::next_sort_order(self.conn, input.parent_id.as_deref())?;
+
+        let scope = [(EntityKind::Folder, id.clone())];
+        journal::track(self.conn, "Create folder", &scope, now, |conn| {
+            conn.execute(
+                "INSERT INTO folders (id, name, icon, color, parent_id, sort_order, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                rusqlite::params![
+                    id,
+                    input.name,
+                    input.icon,
+                    input.color,
+                    input.parent_id,
+                    sort_order,
+                    now
+                ],
+            )?;
+            Ok(())
+        })?;
+
+        Ok(Folder {
+            id,
+            name: input.name,
+            icon: input.icon,
+            color: input.color,
+            parent_id: input.parent_id,
+            sort_order,
+            created_at: now,
+            deleted_at: None,
+        })
+    }
+
+    pub fn update(&self, id: &str, input: UpdateFolderInput, now: i64) -> Result<Folder, DbError> {
+        let mut validator = Validator::new();
+        if let Some(name) = &input.name {
+            validator.required("name", name, validation::MAX_NAME_CHARS);
+        }
+        validator
+            .optional("icon", input.icon.as_deref(), validation::MAX_ICON_CHARS)
+            .color("color", input.color.as_deref())
+            .finish()?;
+
+        let mut assignments = Assignments::default();
+        if let Some(name) = input.name {
+            assignments.set("name", name);
+        }
+        if let Some(icon) = input.icon {
+            assignments.set("icon", icon);
+        }
+        if let Some(color) = input.color {
+            assignments.set("color", color);
+        }
+
+        if !assignments.is_empty() {
+            let scope = [(EntityKind::Folder, id.to_string())];
+            journal::track(self.conn, "Edit folder", &scope, now, |conn| {
+                assignments.execute(conn, "folders", id)?;
+                Ok(())
+            })?;
+        }
+
+        self.get(id)?.ok_or(DbError::NotFound("Folder"))
+    }
+
+    /// Move a folder under another one (or to the top level with no parent),
+    /// optionally at a given position among its new siblings
+    pub fn move_to(
+        &self,
+        id: &str,
+        parent_id: Option<&str>,
+        position: Option<usize>,
+        now: i64,
+    ) -> Result<(), DbError> {
+        let old_parent: Option<String> = self
+            .conn
+            .query_row("SELECT parent_id FROM folders WHERE id = ?1", [id], |row| {
+                row.get(0)
+            })
+            .optional()?
+            .flatten();
+        let scope =
+            folders::journal_scope(self.conn, Some(id), &[old_parent.as_deref(), parent_id])?;
+        journal::track(self.conn, "Move folder", &scope, now, |conn| {
+            folders::move_folder(conn, id, parent_id, position)
+        })
+    }
+
+    /// Reorder the children of one parent
+    pub fn reorder(
+        &self,
+        parent_id: Option<&str>,
+        ordered_ids: &[String],
+        now: i64,
+    ) -> Result<(), DbError> {
+        let scope = folders::journal_scope(self.conn, None, &[parent_id])?;
+        journal::track(self.conn, "Reorder folders", &scope, now, |conn| {
+            folders::reorder_folders(conn, parent_id, ordered_ids)
+        })
+    }
+
+    /// Move a folder to the trash, dealing with its contents according to
+    /// `mode`
+    pub fn delete(
+        &self,
+        id: &str,
+        mode: &DeleteFolderMode,
+        now: i64,
+    ) -> Result<DeleteFolderResult, DbError> {
+        let scope = folders::journal_scope(self.conn, Some(id), &[])?;
+        journal::track(self.conn, "Delete folder", &scope, now, |conn| {
+            folders::delete_folder(conn, id, mode, now)
+        })
+    }
+
+    /// Restore a folder together with everything that was trashed with it
+    pub fn restore(&self, id: &str, now: i64) -> Result<(), DbError> {
+        let scope = folders::journal_scope(self.conn, Some(id), &[])?;
+        journal::track(self.conn, "Restore folder", &scope, now, |conn| {
+            trash::restore_folder(conn, id)
+        })
+    }
+}
+
+/// Nest `folders` under their parents. Folders whose parent is missing end up
+/// at the top level.
+fn build_folder_tree(folders: Vec<Folder>, counts: &HashMap<String, i64>) -> Vec<FolderNode> {
+    let ids: HashSet<String> = folders.iter().map(|f| f.id.clone()).collect();
+    let mut children: HashMap<Option<String>, Vec<Folder>> = HashMap::new();
+    for folder in folders {
+        let parent = folder.parent_id.clone().filter(|p| ids.contains(p));
+        children.entry(parent).or_default().push(folder);
+    }
+    folder_nodes(None, &mut children, counts)
+}
+
+fn folder_nodes(
+    parent: Option<String>,
+    children: &mut HashMap<Option<String>, Vec<Folder>>,
+    counts: &HashMap<String, i64>,
+) -> Vec<FolderNode> {
+    children
+        .remove(&parent)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|folder| {
+            let nested = folder_nodes(Some(folder.id.clone()), children, counts);
+            let prompt_count = counts.get(&folder.id).copied().unwrap_or(0);
+            FolderNode {
+                total_prompt_count: prompt_count
+                    + nested.iter().map(|n| n.total_prompt_count).sum::<i64>(),
+                prompt_count,
+                children: nested,
+                folder,
+            }
+        })
+        .collect()
+}
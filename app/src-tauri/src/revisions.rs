@@ -0,0 +1,353 @@
+use crate::db::{Database, DbError};
+use crate::settings::{HistorySettings, SettingsState};
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use similar::{ChangeTag, TextDiff};
+use tauri::{AppHandle, Manager};
+
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+/// A saved state of a prompt's text and header.
+///
+/// Revisions are written by triggers whenever a prompt is created or its
+/// text or header changes, so the newest one always matches the prompt.
+#[derive(Debug, Serialize, Clone)]
+pub struct PromptRevision {
+    pub id: i64,
+    pub prompt_id: String,
+    pub text: String,
+    pub header: Option<String>,
+    pub created_at: i64,
+    /// Set when this revision was created by reverting to an older one
+    pub reverted_from: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffMode {
+    #[default]
+    Line,
+    Word,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct DiffChunk {
+    /// "equal", "insert" or "delete"
+    pub tag: &'static str,
+    pub value: String,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct RevisionDiff {
+    pub from: PromptRevision,
+    pub to: PromptRevision,
+    pub header_changed: bool,
+    pub chunks: Vec<DiffChunk>,
+}
+
+fn revision_from_row(row: &rusqlite::Row) -> Result<PromptRevision, rusqlite::Error> {
+    Ok(PromptRevision {
+        id: row.get(0)?,
+        prompt_id: row.get(1)?,
+        text: row.get(2)?,
+        header: row.get(3)?,
+        created_at: row.get(4)?,
+        reverted_from: row.get(5)?,
+    })
+}
+
+/// All revisions of a prompt, newest first
+pub fn list_revisions(conn: &Connection, prompt_id: &str) -> Result<Vec<PromptRevision>, DbError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, prompt_id, text, header, created_at, reverted_from
+         FROM prompt_revisions WHERE prompt_id = ?1 ORDER BY id DESC",
+    )?;
+    let revisions = stmt
+        .query_map([prompt_id], revision_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(revisions)
+}
+
+pub fn get_revision(conn: &Connection, id: i64) -> Result<PromptRevision, DbError> {
+    conn.query_row(
+        "SELECT id, prompt_id, text, header, created_at, reverted_from
+         FROM prompt_revisions WHERE id = ?1",
+        [id],
+        revision_from_row,
+    )
+    .optional()?
+    .ok_or(DbError::NotFound("Revision"))
+}
+
+/// Diff the text of two revisions of the same prompt
+pub fn diff_revisions(
+    conn: &Connection,
+    from_id: i64,
+    to_id: i64,
+    mode: DiffMode,
+) -> Result<RevisionDiff, DbError> {
+    let from = get_revision(conn, from_id)?;
+    let to = get_revision(conn, to_id)?;
+    if from.prompt_id != to.prompt_id {
+        return Err(DbError::NotFound("Revision"));
+    }
+
+    Ok(RevisionDiff {
+        header_changed: from.header != to.header,
+        chunks: diff_text(&from.text, &to.text, mode),
+        from,
+        to,
+    })
+}
+
+/// Line or word diff, with neighbouring changes of the same kind merged
+pub fn diff_text(old: &str, new: &str, mode: DiffMode) -> Vec<DiffChunk> {
+    let diff = match mode {
+        DiffMode::Line => TextDiff::from_lines(old, new),
+        DiffMode::Word => TextDiff::from_words(old, new),
+    };
+
+    let mut chunks: Vec<DiffChunk> = Vec::new();
+    for change in diff.iter_all_changes() {
+        let tag = match change.tag() {
+            ChangeTag::Equal => "equal",
+            ChangeTag::Insert => "insert",
+            ChangeTag::Delete => "delete",
+        };
+        match chunks.last_mut() {
+            Some(last) if last.tag == tag => last.value.push_str(change.value()),
+            _ => chunks.push(DiffChunk {
+                tag,
+                value: change.value().to_string(),
+            }),
+        }
+    }
+    chunks
+}
+
+/// Put a prompt's text and header back to an earlier revision.
+///
+/// The update goes through the revision trigger like any other edit, so the
+/// revert shows up as the newest revision. Returns false if the prompt
+/// already matches the revision.
+pub fn revert_to(
+    conn: &Connection,
+    prompt_id: &str,
+    revision_id: i64,
+    now: i64,
+) -> Result<bool, DbError> {
+    let revision = get_revision(conn, revision_id)?;
+    if revision.prompt_id != prompt_id {
+        return Err(DbError::NotFound("Revision"));
+    }
+
+    let changed = conn.execute(
+        "UPDATE prompts SET text = ?2, header = ?3, updated_at = ?4
+         WHERE id = ?1 AND (text IS NOT ?2 OR header IS NOT ?3)",
+        rusqlite::params![prompt_id, revision.text, revision.header, now],
+    )?;
+    if changed == 0 {
+        return Ok(false);
+    }
+
+    conn.execute(
+        "UPDATE prompt_revisions SET reverted_from = ?2
+         WHERE id = (SELECT MAX(id) FROM prompt_revisions WHERE prompt_id = ?1)",
+        rusqlite::params![prompt_id, revision_id],
+    )?;
+    Ok(true)
+}
+
+/// Drop revisions beyond the newest `max_revisions` of each prompt, and any
+/// older than `max_age_days` except the newest. Limits of 0 are ignored.
+pub fn compact(
+    conn: &Connection,
+    prompt_id: Option<&str>,
+    settings: &HistorySettings,
+    now: i64,
+) -> Result<usize, DbError> {
+    let max_revisions = match settings.max_revisions {
+        0 => i64::MAX,
+        n => i64::from(n),
+    };
+    let cutoff = match settings.max_age_days {
+        0 => i64::MIN,
+        days => now - i64::from(days) * DAY_MS,
+    };
+
+    let removed = conn.execute(
+        "DELETE FROM prompt_revisions WHERE id IN (
+             SELECT id FROM (
+                 SELECT id, created_at,
+                        ROW_NUMBER() OVER (PARTITION BY prompt_id ORDER BY id DESC) AS position
+                 FROM prompt_revisions
+                 WHERE ?1 IS NULL OR prompt_id = ?1
+             )
+             WHERE position > ?2 OR (position > 1 AND created_at < ?3)
+         )",
+        rusqlite::params![prompt_id, max_revisions, cutoff],
+    )?;
+    Ok(removed)
+}
+
+// ============ App glue ============
+
+/// Compact the revision history of every prompt
+pub async fn run_compaction(app_handle: &AppHandle) -> Result<usize, DbError> {
+    let settings = app_handle.state::<SettingsState>().get().history;
+    let now = chrono::Utc::now().timestamp_millis();
+
+    app_handle
+        .state::<Database>()
+        .run_background(move |conn| compact(conn, None, &settings, now))
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::repository::{CreatePromptInput, PromptRepository, UpdatePromptInput};
+
+    fn create(conn: &Connection, text: &str, now: i64) -> String {
+        let input = CreatePromptInput {
+            text: text.to_string(),
+            ..Default::default()
+        };
+        PromptRepository::new(conn).create(input, now).unwrap().id
+    }
+
+    fn edit(conn: &Connection, id: &str, text: &str, now: i64) {
+        let input = UpdatePromptInput {
+            text: Some(text.to_string()),
+            ..Default::default()
+        };
+        PromptRepository::new(conn).update(id, input, now).unwrap();
+    }
+
+    fn texts(conn: &Connection, id: &str) -> Vec<String> {
+        list_revisions(conn, id)
+            .unwrap()
+            .into_iter()
+            .map(|revision| revision.text)
+            .collect()
+    }
+
+    #[test]
+    fn triggers_record_text_and_header_changes_only() {
+        let conn = db::open_in_memory();
+        let prompts = PromptRepository::new(&conn);
+        let id = create(&conn, "First", 1);
+        edit(&conn, &id, "Second", 2);
+        edit(&conn, &id, "Second", 3);
+        let favorite = UpdatePromptInput {
+            is_favorite: Some(true),
+            ..Default::default()
+        };
+        prompts.update(&id, favorite, 4).unwrap();
+        prompts.record_use(&id, 5).unwrap();
+        assert_eq!(texts(&conn, &id), ["Second", "First"]);
+
+        let header = UpdatePromptInput {
+            header: Some("Title".to_string()),
+            ..Default::default()
+        };
+        prompts.update(&id, header, 6).unwrap();
+        let newest = &list_revisions(&conn, &id).unwrap()[0];
+        assert_eq!(newest.header.as_deref(), Some("Title"));
+        assert_eq!((newest.text.as_str(), newest.created_at), ("Second", 6));
+    }
+
+    #[test]
+    fn diff_text_merges_neighbouring_changes() {
+        let chunks = diff_text("a\nb\nc\n", "a\nx\ny\nc\n", DiffMode::Line);
+        let chunks: Vec<_> = chunks
+            .iter()
+            .map(|chunk| (chunk.tag, chunk.value.as_str()))
+            .collect();
+        assert_eq!(
+            chunks,
+            [
+                ("equal", "a\n"),
+                ("delete", "b\n"),
+                ("insert", "x\ny\n"),
+                ("equal", "c\n"),
+            ]
+        );
+
+        let chunks = diff_text("the quick fox", "the slow fox", DiffMode::Word);
+        let chunks: Vec<_> = chunks
+            .iter()
+            .map(|chunk| (chunk.tag, chunk.value.as_str()))
+            .collect();
+        assert_eq!(
+            chunks,
+            [
+                ("equal", "the "),
+                ("delete", "quick"),
+                ("insert", "slow"),
+                ("equal", " fox"),
+            ]
+        );
+        assert!(diff_text("same", "same", DiffMode::Word)
+            .iter()
+            .all(|chunk| chunk.tag == "equal"));
+    }
+
+    #[test]
+    fn revert_records_where_it_came_from() {
+        let conn = db::open_in_memory();
+        let id = create(&conn, "First", 1);
+        edit(&conn, &id, "Second", 2);
+        let first = list_revisions(&conn, &id).unwrap()[1].id;
+
+        assert!(revert_to(&conn, &id, first, 3).unwrap());
+        let revisions = list_revisions(&conn, &id).unwrap();
+        assert_eq!(revisions.len(), 3);
+        assert_eq!(revisions[0].text, "First");
+        assert_eq!(revisions[0].reverted_from, Some(first));
+        assert_eq!(revisions[1].reverted_from, None);
+
+        // Already matching: nothing to do
+        assert!(!revert_to(&conn, &id, first, 4).unwrap());
+        assert_eq!(list_revisions(&conn, &id).unwrap().len(), 3);
+
+        let other = create(&conn, "Other", 5);
+        assert!(matches!(
+            revert_to(&conn, &other, first, 6),
+            Err(DbError::NotFound("Revision"))
+        ));
+    }
+
+    #[test]
+    fn compact_keeps_the_newest_revisions() {
+        let conn = db::open_in_memory();
+        let id = create(&conn, "1", 1);
+        for i in 2..=5 {
+            edit(&conn, &id, &i.to_string(), i);
+        }
+        let other = create(&conn, "Other 1", 1);
+        edit(&conn, &other, "Other 2", 2);
+
+        let settings = HistorySettings {
+            max_revisions: 3,
+            max_age_days: 0,
+        };
+        assert_eq!(compact(&conn, Some(&id), &settings, 10).unwrap(), 2);
+        assert_eq!(texts(&conn, &id), ["5", "4", "3"]);
+        assert_eq!(texts(&conn, &other).len(), 2);
+
+        let settings = HistorySettings {
+            max_revisions: 0,
+            max_age_days: 1,
+        };
+        assert_eq!(compact(&conn, None, &settings, 4 + DAY_MS).unwrap(), 2);
+        assert_eq!(texts(&conn, &id), ["5", "4"]);
+        assert_eq!(texts(&conn, &other), ["Other 2"]);
+
+        // The newest revision stays however old it is
+        assert_eq!(compact(&conn, None, &settings, 10 * DAY_MS).unwrap(), 1);
+        assert_eq!(texts(&conn, &id), ["5"]);
+        assert_eq!(texts(&conn, &other), ["Other 2"]);
+    }
+}
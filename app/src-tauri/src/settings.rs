@@ -0,0 +1,242 @@
+use crate::backup::BackupKind;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum SettingsError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid settings file: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Path error: {0}")]
+    Path(String),
+    #[error("Failed to acquire lock")]
+    Lock,
+}
+
+/// Backend settings persisted as `settings.json` in the app data dir.
+///
+/// These live outside the database so they can be read while the vault is
+/// still locked.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct AppSettings {
+    pub vault: VaultSettings,
+    pub backup: BackupSettings,
+    pub trash: TrashSettings,
+    pub history: HistorySettings,
+    pub shortcuts: ShortcutSettings,
+    pub capture: CaptureSettings,
+    pub bridge: BridgeSettings,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct VaultSettings {
+    /// Lock the vault after this many idle minutes (0 = never)
+    pub auto_lock_minutes: u32,
+}
+
+impl Default for VaultSettings {
+    fn default() -> Self {
+        Self {
+            auto_lock_minutes: 15,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct BackupSettings {
+    /// Take daily and weekly snapshots in the background
+    pub enabled: bool,
+    pub daily_retention: usize,
+    pub weekly_retention: usize,
+    /// Retention for manual and pre-import/migration/restore snapshots
+    pub event_retention: usize,
+}
+
+impl Default for BackupSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            daily_retention: 7,
+            weekly_retention: 4,
+            event_retention: 10,
+        }
+    }
+}
+
+impl BackupSettings {
+    pub fn retention_for(&self, kind: BackupKind) -> usize {
+        match kind {
+            BackupKind::Daily => self.daily_retention,
+            BackupKind::Weekly => self.weekly_retention,
+            _ => self.event_retention,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct TrashSettings {
+    /// Purge trashed items after this many days (0 = keep forever)
+    pub retention_days: u32,
+}
+
+impl Default for TrashSettings {
+    fn default() -> Self {
+        Self { retention_days: 30 }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct HistorySettings {
+    /// Revisions kept per prompt (0 = unlimited)
+    pub max_revisions: u32,
+    /// Drop revisions older than this, always keeping the newest (0 = never)
+    pub max_age_days: u32,
+}
+
+impl Default for HistorySettings {
+    fn default() -> Self {
+        Self {
+            max_revisions: 50,
+            max_age_days: 0,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ShortcutSettings {
+    /// Global hotkey that shows or hides the quick picker ("" = none)
+    pub quick_picker: String,
+    /// Global hotkey that saves the clipboard as a prompt ("" = none)
+    pub capture_clipboard: String,
+    /// Paste into the focused app after a picked prompt is copied
+    pub auto_paste: bool,
+}
+
+impl Default for ShortcutSettings {
+    fn default() -> Self {
+        Self {
+            quick_picker: "CommandOrControl+Shift+Space".to_string(),
+            capture_clipboard: "CommandOrControl+Shift+Alt+C".to_string(),
+            auto_paste: false,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct CaptureSettings {
+    /// Checked in order; the first rule with a matching keyword picks the folder
+    pub rules: Vec<FolderRule>,
+    /// Without a matching rule, use a folder whose name appears in the text
+    pub match_folder_names: bool,
+}
+
+impl Default for CaptureSettings {
+    fn default() -> Self {
+        Self {
+            rules: Vec::new(),
+            match_folder_names: true,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FolderRule {
+    pub folder_id: String,
+    /// Whole words or phrases, matched case-insensitively
+    pub keywords: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct BridgeSettings {
+    /// Serve the library to the browser extension on 127.0.0.1
+    pub enabled: bool,
+    pub port: u16,
+    /// Pairing token the extension must present; generated when the bridge
+    /// is first enabled
+    pub token: String,
+}
+
+impl Default for BridgeSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 47215,
+            token: String::new(),
+        }
+    }
+}
+
+pub struct SettingsState {
+    path: PathBuf,
+    settings: Mutex<AppSettings>,
+}
+
+impl SettingsState {
+    /// Load settings from disk, falling back to defaults if the file is
+    /// missing or unreadable
+    pub fn load(path: PathBuf) -> Self {
+        let settings = match read_settings(&path) {
+            Ok(s) => s,
+            Err(e) => {
+                log::warn!("Using default settings: {}", e);
+                AppSettings::default()
+            }
+        };
+
+        Self {
+            path,
+            settings: Mutex::new(settings),
+        }
+    }
+
+    pub fn get(&self) -> AppSettings {
+        self.settings.lock().map(|s| s.clone()).unwrap_or_default()
+    }
+
+    /// Apply a change and write the result back to disk
+    pub fn update<F>(&self, f: F) -> Result<AppSettings, SettingsError>
+    where
+        F: FnOnce(&mut AppSettings),
+    {
+        let mut settings = self.settings.lock().map_err(|_| SettingsError::Lock)?;
+        f(&mut settings);
+        write_settings(&self.path, &settings)?;
+        Ok(settings.clone())
+    }
+}
+
+pub fn get_settings_path(app_handle: &AppHandle) -> Result<PathBuf, SettingsError> {
+    let app_data_dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| SettingsError::Path(e.to_string()))?;
+
+    std::fs::create_dir_all(&app_data_dir)?;
+    Ok(app_data_dir.join("settings.json"))
+}
+
+fn read_settings(path: &Path) -> Result<AppSettings, SettingsError> {
+    if !path.exists() {
+        return Ok(AppSettings::default());
+    }
+    let data = std::fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&data)?)
+}
+
+fn write_settings(path: &Path, settings: &AppSettings) -> Result<(), SettingsError> {
+    let data = serde_json::to_string_pretty(settings)?;
+    std::fs::write(path, data)?;
+    Ok(())
+}
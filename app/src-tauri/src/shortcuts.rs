@@ -0,0 +1,301 @@
+//! Global hotkeys and the quick picker window they open.
+
+use crate::capture;
+use crate::error::{CommandError, ErrorCode};
+use crate::settings::{SettingsState, ShortcutSettings};
+use serde::Serialize;
+use serde_json::json;
+use std::io::{self, ErrorKind};
+use std::process::Command;
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{
+    AppHandle, Emitter, Manager, WebviewUrl, WebviewWindow, WebviewWindowBuilder, WindowEvent,
+};
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutState};
+
+/// Window label of the quick picker
+pub const PICKER_LABEL: &str = "picker";
+
+/// Time for the previously focused app to get focus back before pasting
+const PASTE_DELAY: Duration = Duration::from_millis(150);
+
+/// Something a global hotkey can trigger
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ShortcutAction {
+    QuickPicker,
+    CaptureClipboard,
+}
+
+impl ShortcutAction {
+    const ALL: [ShortcutAction; 2] = [
+        ShortcutAction::QuickPicker,
+        ShortcutAction::CaptureClipboard,
+    ];
+
+    fn accelerator(self, settings: &ShortcutSettings) -> &str {
+        match self {
+            ShortcutAction::QuickPicker => &settings.quick_picker,
+            ShortcutAction::CaptureClipboard => &settings.capture_clipboard,
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            ShortcutAction::QuickPicker => "the quick picker",
+            ShortcutAction::CaptureClipboard => "clipboard capture",
+        }
+    }
+
+    fn run(self, app_handle: &AppHandle) {
+        let result = match self {
+            ShortcutAction::QuickPicker => toggle_picker(app_handle),
+            ShortcutAction::CaptureClipboard => {
+                spawn_capture(app_handle);
+                Ok(())
+            }
+        };
+        if let Err(e) = result {
+            log::warn!("Shortcut for {} failed: {}", self.describe(), e);
+        }
+    }
+}
+
+/// Whether one action's hotkey could be bound
+#[derive(Debug, Serialize, Clone)]
+pub struct ShortcutStatus {
+    pub action: ShortcutAction,
+    pub accelerator: String,
+    pub registered: bool,
+    /// Why it isn't bound, e.g. another app already owns the hotkey
+    pub error: Option<String>,
+}
+
+/// Keeps the outcome of the last binding, so conflicts found at startup
+/// (before the frontend listens) can still be shown
+#[derive(Default)]
+pub struct ShortcutManager {
+    statuses: Mutex<Vec<ShortcutStatus>>,
+}
+
+impl ShortcutManager {
+    pub fn statuses(&self) -> Vec<ShortcutStatus> {
+        self.statuses.lock().map(|s| s.clone()).unwrap_or_default()
+    }
+
+    fn set(&self, statuses: Vec<ShortcutStatus>) {
+        if let Ok(mut s) = self.statuses.lock() {
+            *s = statuses;
+        }
+    }
+}
+
+/// Bind the saved hotkeys at startup, logging any that conflict
+pub fn register(app_handle: &AppHandle) {
+    let settings = app_handle.state::<SettingsState>().get().shortcuts;
+    let statuses = bind(app_handle, &settings);
+    for status in statuses.iter().filter(|s| !s.registered) {
+        log::warn!(
+            "Shortcut {} for {} not registered: {}",
+            status.accelerator,
+            status.action.describe(),
+            status.error.as_deref().unwrap_or_default()
+        );
+    }
+    app_handle.state::<ShortcutManager>().set(statuses);
+}
+
+/// Bind new hotkeys and save them. If any of them can't be bound, the
+/// previous hotkeys are restored and the conflicts returned in the error.
+pub fn update(
+    app_handle: &AppHandle,
+    shortcuts: ShortcutSettings,
+) -> Result<Vec<ShortcutStatus>, CommandError> {
+    let settings = app_handle.state::<SettingsState>();
+    let manager = app_handle.state::<ShortcutManager>();
+
+    let statuses = bind(app_handle, &shortcuts);
+    if statuses.iter().any(|s| !s.registered) {
+        manager.set(bind(app_handle, &settings.get().shortcuts));
+        return Err(CommandError::new(
+            ErrorCode::ShortcutConflict,
+            "Some shortcuts could not be registered",
+        )
+        .with_details(json!({ "shortcuts": statuses })));
+    }
+
+    settings.update(|s| s.shortcuts = shortcuts)?;
+    manager.set(statuses.clone());
+    Ok(statuses)
+}
+
+/// Replace every bound hotkey with the ones in `settings`
+fn bind(app_handle: &AppHandle, settings: &ShortcutSettings) -> Vec<ShortcutStatus> {
+    if let Err(e) = app_handle.global_shortcut().unregister_all() {
+        log::warn!("Failed to unregister shortcuts: {}", e);
+    }
+
+    let mut bound: Vec<(Shortcut, ShortcutAction)> = Vec::new();
+    let mut statuses = Vec::new();
+    for action in ShortcutAction::ALL {
+        let accelerator = action.accelerator(settings).trim();
+        if accelerator.is_empty() {
+            continue;
+        }
+        let error = match bind_one(app_handle, action, accelerator, &bound) {
+            Ok(shortcut) => {
+                bound.push((shortcut, action));
+                None
+            }
+            Err(e) => Some(e),
+        };
+        statuses.push(ShortcutStatus {
+            action,
+            accelerator: accelerator.to_string(),
+            registered: error.is_none(),
+            error,
+        });
+    }
+    statuses
+}
+
+fn bind_one(
+    app_handle: &AppHandle,
+    action: ShortcutAction,
+    accelerator: &str,
+    bound: &[(Shortcut, ShortcutAction)],
+) -> Result<Shortcut, String> {
+    let shortcut: Shortcut = accelerator
+        .parse()
+        .map_err(|e| format!("Invalid shortcut: {}", e))?;
+    if let Some((_, other)) = bound.iter().find(|(s, _)| *s == shortcut) {
+        return Err(format!("Already used for {}", other.describe()));
+    }
+    app_handle
+        .global_shortcut()
+        .on_shortcut(shortcut, move |app, _, event| {
+            if event.state == ShortcutState::Pressed {
+                action.run(app);
+            }
+        })
+        .map_err(|e| format!("Unavailable, it may be in use by another app: {}", e))?;
+    Ok(shortcut)
+}
+
+/// Capture the clipboard in the background. Failures go out as a
+/// `clipboard-capture-failed` event since no window asked for it.
+pub fn spawn_capture(app_handle: &AppHandle) {
+    let handle = app_handle.clone();
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = capture::capture_clipboard(&handle).await {
+            log::warn!("Clipboard capture failed: {}", e);
+            let _ = handle.emit("clipboard-capture-failed", e);
+        }
+    });
+}
+
+// ============ Quick Picker ============
+
+/// Show the quick picker, or hide it if it is already showing
+pub fn toggle_picker(app_handle: &AppHandle) -> tauri::Result<()> {
+    match app_handle.get_webview_window(PICKER_LABEL) {
+        Some(window) if window.is_visible()? => window.hide(),
+        Some(window) => {
+            window.center()?;
+            window.show()?;
+            window.set_focus()
+        }
+        None => build_picker(app_handle).map(|_| ()),
+    }
+}
+
+pub fn hide_picker(app_handle: &AppHandle) -> tauri::Result<()> {
+    match app_handle.get_webview_window(PICKER_LABEL) {
+        Some(window) => window.hide(),
+        None => Ok(()),
+    }
+}
+
+/// Frameless, always-on-top window that hides again when it loses focus.
+/// It is kept around after the first use so it opens instantly.
+fn build_picker(app_handle: &AppHandle) -> tauri::Result<WebviewWindow> {
+    let window = WebviewWindowBuilder::new(
+        app_handle,
+        PICKER_LABEL,
+        WebviewUrl::App("index.html?window=picker".into()),
+    )
+    .title("Quick Picker - PromptPack")
+    .inner_size(640.0, 420.0)
+    .resizable(false)
+    .decorations(false)
+    .always_on_top(true)
+    .skip_taskbar(true)
+    .center()
+    .focused(true)
+    .build()?;
+
+    let picker = window.clone();
+    window.on_window_event(move |event| {
+        if let WindowEvent::Focused(false) = event {
+            let _ = picker.hide();
+        }
+    });
+    Ok(window)
+}
+
+/// Send the platform paste keystroke to whichever app has focus, once it
+/// has had time to get it back from the picker
+pub async fn paste() -> bool {
+    tokio::time::sleep(PASTE_DELAY).await;
+    match tauri::async_runtime::spawn_blocking(send_paste_keystroke).await {
+        Ok(Ok(())) => true,
+        Ok(Err(e)) => {
+            log::warn!("Auto-paste failed: {}", e);
+            false
+        }
+        Err(e) => {
+            log::warn!("Auto-paste failed: {}", e);
+            false
+        }
+    }
+}
+
+fn send_paste_keystroke() -> io::Result<()> {
+    let tools: &[(&str, &[&str])] = if cfg!(target_os = "macos") {
+        &[(
+            "osascript",
+            &[
+                "-e",
+                "tell application \"System Events\" to keystroke \"v\" using command down",
+            ],
+        )]
+    } else if cfg!(windows) {
+        &[(
+            "powershell",
+            &[
+                "-NoProfile",
+                "-Command",
+                "(New-Object -ComObject WScript.Shell).SendKeys('^v')",
+            ],
+        )]
+    } else {
+        &[
+            ("wtype", &["-M", "ctrl", "v", "-m", "ctrl"]),
+            ("xdotool", &["key", "--clearmodifiers", "ctrl+v"]),
+        ]
+    };
+
+    for (program, args) in tools {
+        match Command::new(program).args(*args).status() {
+            Ok(status) if status.success() => return Ok(()),
+            Ok(status) => return Err(io::Error::other(format!("{program} failed ({status})"))),
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Err(io::Error::new(
+        ErrorKind::NotFound,
+        "no tool available to send a paste keystroke",
+    ))
+}
@@ -0,0 +1,280 @@
+use crate::db::DbError;
+use crate::filter::PromptFilter;
+use crate::validation::{self, Validator};
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+/// A saved search, shown next to regular folders
+#[derive(Debug, Serialize, Clone)]
+pub struct SmartFolder {
+    pub id: String,
+    pub name: String,
+    pub icon: Option<String>,
+    pub color: Option<String>,
+    pub query: PromptFilter,
+    pub sort_order: i32,
+    pub created_at: i64,
+    pub updated_at: i64,
+    /// Prompts currently matching `query`
+    pub prompt_count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SmartFolderInput {
+    pub name: String,
+    pub icon: Option<String>,
+    pub color: Option<String>,
+    pub query: PromptFilter,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateSmartFolderInput {
+    pub name: Option<String>,
+    pub icon: Option<String>,
+    pub color: Option<String>,
+    pub query: Option<PromptFilter>,
+}
+
+/// How many prompts match `filter` right now
+pub fn count_matches(conn: &Connection, filter: &PromptFilter, now: i64) -> Result<i64, DbError> {
+    let (conditions, params) = filter.to_sql(now);
+    let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+    Ok(conn.query_row(
+        &format!("SELECT COUNT(*) FROM prompts WHERE {conditions}"),
+        param_refs.as_slice(),
+        |row| row.get(0),
+    )?)
+}
+
+fn parse_query(json: &str) -> Result<PromptFilter, rusqlite::Error> {
+    serde_json::from_str(json).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))
+    })
+}
+
+/// All smart folders in display order, with live counts
+pub fn list(conn: &Connection, now: i64) -> Result<Vec<SmartFolder>, DbError> {
+    let mut stmt = conn.prepare("SELECT id FROM smart_folders ORDER BY sort_order, created_at")?;
+    let ids = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<Vec<String>, _>>()?;
+
+    ids.iter()
+        .map(|id| get(conn, id, now)?.ok_or(DbError::NotFound("Smart folder")))
+        .collect()
+}
+
+pub fn get(conn: &Connection, id: &str, now: i64) -> Result<Option<SmartFolder>, DbError> {
+    let folder = conn
+        .query_row(
+            "SELECT id, name, icon, color, query, sort_order, created_at, updated_at
+             FROM smart_folders WHERE id = ?1",
+            [id],
+            |row| {
+                Ok(SmartFolder {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    icon: row.get(2)?,
+                    color: row.get(3)?,
+                    query: parse_query(&row.get::<_, String>(4)?)?,
+                    sort_order: row.get(5)?,
+                    created_at: row.get(6)?,
+                    updated_at: row.get(7)?,
+                    prompt_count: 0,
+                })
+            },
+        )
+        .optional()?;
+
+    match folder {
+        Some(mut folder) => {
+            folder.prompt_count = count_matches(conn, &folder.query, now)?;
+            Ok(Some(folder))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Check the fields a folder has, and that the folder and tags the query
+/// refers to exist
+fn validate(
+    conn: &Connection,
+    name: Option<&str>,
+    icon: Option<&str>,
+    color: Option<&str>,
+    query: Option<&PromptFilter>,
+) -> Result<(), DbError> {
+    let mut validator = Validator::new();
+    if let Some(name) = name {
+        validator.required("name", name, validation::MAX_NAME_CHARS);
+    }
+    validator
+        .optional("icon", icon, validation::MAX_ICON_CHARS)
+        .color("color", color);
+    if let Some(query) = query {
+        validator
+            .folder(conn, "query.folder_id", query.folder_id.as_deref())?
+            .tag_names(conn, "query.tags", &query.tags)?;
+    }
+    validator.finish()?;
+    Ok(())
+}
+
+pub fn create(
+    conn: &Connection,
+    id: &str,
+    input: SmartFolderInput,
+    now: i64,
+) -> Result<SmartFolder, DbError> {
+    validate(
+        conn,
+        Some(&input.name),
+        input.icon.as_deref(),
+        input.color.as_deref(),
+        Some(&input.query),
+    )?;
+    let query = serde_json::to_string(&input.query).map_err(|e| DbError::Invalid(e.to_string()))?;
+
+    conn.execute(
+        "INSERT INTO smart_folders (id, name, icon, color, query, sort_order, created_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5,
+                 (SELECT COALESCE(MAX(sort_order), 0) + 1 FROM smart_folders), ?6, ?6)",
+        rusqlite::params![id, input.name, input.icon, input.color, query, now],
+    )?;
+
+    get(conn, id, now)?.ok_or(DbError::NotFound("Smart folder"))
+}
+
+pub fn update(
+    conn: &Connection,
+    id: &str,
+    input: UpdateSmartFolderInput,
+    now: i64,
+) -> Result<SmartFolder, DbError> {
+    validate(
+        conn,
+        input.name.as_deref(),
+        input.icon.as_deref(),
+        input.color.as_deref(),
+        input.query.as_ref(),
+    )?;
+    let query = input
+        .query
+        .map(|q| serde_json::to_string(&q))
+        .transpose()
+        .map_err(|e| DbError::Invalid(e.to_string()))?;
+
+    let changed = conn.execute(
+        "UPDATE smart_folders SET
+             name = COALESCE(?2, name),
+             icon = COALESCE(?3, icon),
+             color = COALESCE(?4, color),
+             query = COALESCE(?5, query),
+             updated_at = ?6
+         WHERE id = ?1",
+        rusqlite::params![id, input.name, input.icon, input.color, query, now],
+    )?;
+    if changed == 0 {
+        return Err(DbError::NotFound("Smart folder"));
+    }
+
+    get(conn, id, now)?.ok_or(DbError::NotFound("Smart folder"))
+}
+
+pub fn delete(conn: &Connection, id: &str) -> Result<(), DbError> {
+    if conn.execute("DELETE FROM smart_folders WHERE id = ?1", [id])? == 0 {
+        return Err(DbError::NotFound("Smart folder"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::repository::{CreateFolderInput, FolderRepository};
+
+    fn fields(error: DbError) -> Vec<String> {
+        match error {
+            DbError::Validation(e) => e.fields.into_iter().map(|f| f.field).collect(),
+            other => panic!("expected a validation error, got {other:?}"),
+        }
+    }
+
+    fn input(name: &str, query: PromptFilter) -> SmartFolderInput {
+        SmartFolderInput {
+            name: name.to_string(),
+            icon: None,
+            color: None,
+            query,
+        }
+    }
+
+    #[test]
+    fn create_checks_fields_like_folders() {
+        let conn = db::open_in_memory();
+        let mut bad = input(" ", PromptFilter::default());
+        bad.icon = Some("x".repeat(validation::MAX_ICON_CHARS + 1));
+        bad.color = Some("blue".to_string());
+        let error = create(&conn, "s1", bad, 1).unwrap_err();
+        assert_eq!(fields(error), ["name", "icon", "color"]);
+        assert!(get(&conn, "s1", 1).unwrap().is_none());
+    }
+
+    #[test]
+    fn query_must_refer_to_existing_folders_and_tags() {
+        let conn = db::open_in_memory();
+        let folders = FolderRepository::new(&conn);
+        let live = CreateFolderInput {
+            name: "Work".to_string(),
+            ..Default::default()
+        };
+        let live = folders.create(live, 1).unwrap();
+        conn.execute("INSERT INTO tags (id, name) VALUES ('t1', 'draft')", [])
+            .unwrap();
+
+        let query = PromptFilter {
+            folder_id: Some("missing".to_string()),
+            tags: vec!["draft".to_string(), "nope".to_string()],
+            ..Default::default()
+        };
+        let error = create(&conn, "s1", input("Bad", query), 2).unwrap_err();
+        assert_eq!(fields(error), ["query.folder_id", "query.tags"]);
+
+        let query = PromptFilter {
+            folder_id: Some(live.id.clone()),
+            tags: vec!["draft".to_string()],
+            ..Default::default()
+        };
+        let folder = create(&conn, "s1", input("Good", query), 3).unwrap();
+        assert_eq!(folder.query.folder_id, Some(live.id));
+    }
+
+    #[test]
+    fn update_checks_only_given_fields() {
+        let conn = db::open_in_memory();
+        create(&conn, "s1", input("Saved", PromptFilter::default()), 1).unwrap();
+
+        let bad = UpdateSmartFolderInput {
+            name: Some(String::new()),
+            icon: None,
+            color: Some("#12".to_string()),
+            query: Some(PromptFilter {
+                tags: vec!["nope".to_string()],
+                ..Default::default()
+            }),
+        };
+        let error = update(&conn, "s1", bad, 2).unwrap_err();
+        assert_eq!(fields(error), ["name", "color", "query.tags"]);
+        assert_eq!(get(&conn, "s1", 2).unwrap().unwrap().name, "Saved");
+
+        let rename = UpdateSmartFolderInput {
+            name: Some("Renamed".to_string()),
+            icon: None,
+            color: None,
+            query: None,
+        };
+        let folder = update(&conn, "s1", rename, 3).unwrap();
+        assert_eq!((folder.name.as_str(), folder.updated_at), ("Renamed", 3));
+    }
+}
@@ -0,0 +1,137 @@
+//! Template variables: `{Name}` placeholders in prompt text.
+//!
+//! Mirrors `templateParser.ts` in the app and the extension, so a prompt
+//! renders the same wherever it is used.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Characters that mark braces as code or JSON rather than a variable
+const NON_VARIABLE_CHARS: &[char] = &['{', '}', '[', ']', ':', ';', '"', '\'', '`', ','];
+
+/// Most words a variable name may have
+const MAX_WORDS: usize = 3;
+
+/// Whether the text between a pair of braces names a variable: one to three
+/// words on one line, without code-like punctuation
+fn is_variable(content: &str) -> bool {
+    let trimmed = content.trim();
+    !trimmed.is_empty()
+        && !trimmed.contains('\n')
+        && !trimmed.contains(NON_VARIABLE_CHARS)
+        && trimmed.split_whitespace().count() <= MAX_WORDS
+}
+
+/// Every `{...}` in `text` with at least one character inside, whether or not
+/// it names a variable
+fn braces(text: &str) -> Vec<(Range<usize>, &str)> {
+    let mut found = Vec::new();
+    let mut from = 0;
+    while let Some(open) = text[from..].find('{').map(|i| from + i) {
+        match text[open + 1..].find('}').map(|i| open + 1 + i) {
+            Some(close) if close > open + 1 => {
+                found.push((open..close + 1, &text[open + 1..close]));
+                from = close + 1;
+            }
+            Some(_) => from = open + 1,
+            None => break,
+        }
+    }
+    found
+}
+
+/// Unique variable names in order of first appearance
+pub fn variables(text: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for (_, content) in braces(text) {
+        let name = content.trim();
+        if is_variable(content) && !names.iter().any(|n| n == name) {
+            names.push(name.to_string());
+        }
+    }
+    names
+}
+
+/// Replace each variable with its value. Placeholders without a non-blank
+/// value are left as they are.
+pub fn render(text: &str, values: &HashMap<String, String>) -> String {
+    let mut rendered = String::with_capacity(text.len());
+    let mut last = 0;
+    for (range, content) in braces(text) {
+        let value = values
+            .get(content.trim())
+            .filter(|v| is_variable(content) && !v.trim().is_empty());
+        if let Some(value) = value {
+            rendered.push_str(&text[last..range.start]);
+            rendered.push_str(value);
+            last = range.end;
+        }
+    }
+    rendered.push_str(&text[last..]);
+    rendered
+}
+
+/// Variables in `text` that `values` leaves unfilled
+pub fn missing(text: &str, values: &HashMap<String, String>) -> Vec<String> {
+    variables(text)
+        .into_iter()
+        .filter(|name| {
+            values
+                .get(name)
+                .map_or("", String::as_str)
+                .trim()
+                .is_empty()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn finds_unique_variables_in_order() {
+        assert_eq!(
+            variables("Find {Stock} for {Month} {Year}, then {Stock} again"),
+            ["Stock", "Month", "Year"]
+        );
+        assert_eq!(variables("{ Target Audience }"), ["Target Audience"]);
+    }
+
+    #[test]
+    fn ignores_code_and_json() {
+        assert!(variables(r#"{"key": "value"}"#).is_empty());
+        assert!(variables("fn main() {}").is_empty());
+        assert!(variables("if (x) { return a; }").is_empty());
+        assert!(variables("{one two three four}").is_empty());
+        assert!(variables("{line\nbreak}").is_empty());
+    }
+
+    #[test]
+    fn renders_known_values_and_keeps_the_rest() {
+        let text = "Compare {Stock} in {Year} with {Other}";
+        let rendered = render(
+            text,
+            &values(&[("Stock", "AAPL"), ("Year", "2024"), ("Other", " ")]),
+        );
+        assert_eq!(rendered, "Compare AAPL in 2024 with {Other}");
+        assert_eq!(
+            missing(text, &values(&[("Stock", "AAPL"), ("Other", " ")])),
+            ["Year", "Other"]
+        );
+    }
+
+    #[test]
+    fn leaves_non_variables_untouched() {
+        let text = r#"Return {"name": {Name}} as JSON"#;
+        assert_eq!(render(text, &values(&[("Name", "Ada")])), text);
+        assert_eq!(render("{{Name}}", &values(&[("Name", "Ada")])), "{{Name}}");
+    }
+}
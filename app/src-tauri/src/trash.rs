@@ -0,0 +1,308 @@
+use crate::db::{Database, DbError};
+use crate::folders::SUBTREE;
+use crate::journal;
+use crate::settings::SettingsState;
+use rusqlite::{Connection, OptionalExtension, ToSql};
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+/// A prompt deletion the cloud copy has to catch up with
+#[derive(Debug, Serialize, Clone)]
+pub struct Tombstone {
+    pub prompt_id: String,
+    pub cloud_id: Option<String>,
+    pub deleted_at: i64,
+    /// Gone for good, rather than still sitting in the trash
+    pub purged: bool,
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct PurgeResult {
+    pub prompts: usize,
+    pub folders: usize,
+}
+
+/// Move a prompt to the trash
+pub fn trash_prompt(conn: &Connection, id: &str, now: i64) -> Result<(), DbError> {
+    let changed = conn.execute(
+        "UPDATE prompts SET deleted_at = ?2 WHERE id = ?1 AND deleted_at IS NULL",
+        rusqlite::params![id, now],
+    )?;
+    if changed == 0 {
+        return Err(DbError::NotFound("Prompt"));
+    }
+    Ok(())
+}
+
+/// Move a folder, its subfolders and all prompts inside them to the trash.
+/// Returns how many folders and prompts were trashed.
+///
+/// Everything shares one `deleted_at`, which is how `restore_folder` finds
+/// the batch again.
+pub fn trash_folder(conn: &Connection, id: &str, now: i64) -> Result<(usize, usize), DbError> {
+    let folders = conn.execute(
+        &format!(
+            "UPDATE folders SET deleted_at = ?2
+             WHERE deleted_at IS NULL AND id IN ({SUBTREE})"
+        ),
+        rusqlite::params![id, now],
+    )?;
+    if folders == 0 {
+        return Err(DbError::NotFound("Folder"));
+    }
+
+    let prompts = conn.execute(
+        &format!(
+            "UPDATE prompts SET deleted_at = ?2
+             WHERE deleted_at IS NULL AND folder_id IN ({SUBTREE})"
+        ),
+        rusqlite::params![id, now],
+    )?;
+    Ok((folders, prompts))
+}
+
+/// Take a prompt back out of the trash. If its folder is still trashed it
+/// comes back unfiled.
+pub fn restore_prompt(conn: &Connection, id: &str) -> Result<(), DbError> {
+    let changed = conn.execute(
+        "UPDATE prompts SET
+             deleted_at = NULL,
+             folder_id = CASE
+                 WHEN folder_id IN (SELECT id FROM folders WHERE deleted_at IS NULL) THEN folder_id
+             END
+         WHERE id = ?1 AND deleted_at IS NOT NULL",
+        [id],
+    )?;
+    if changed == 0 {
+        return Err(DbError::NotFound("Trashed prompt"));
+    }
+    Ok(())
+}
+
+/// Restore a folder together with everything that was trashed with it
+pub fn restore_folder(conn: &Connection, id: &str) -> Result<(), DbError> {
+    let deleted_at: i64 = conn
+        .query_row(
+            "SELECT deleted_at FROM folders WHERE id = ?1 AND deleted_at IS NOT NULL",
+            [id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or(DbError::NotFound("Trashed folder"))?;
+
+    conn.execute(
+        &format!(
+            "UPDATE prompts SET deleted_at = NULL
+             WHERE deleted_at = ?2 AND folder_id IN ({SUBTREE})"
+        ),
+        rusqlite::params![id, deleted_at],
+    )?;
+    conn.execute(
+        &format!(
+            "UPDATE folders SET deleted_at = NULL
+             WHERE deleted_at = ?2 AND id IN ({SUBTREE})"
+        ),
+        rusqlite::params![id, deleted_at],
+    )?;
+
+    // A parent that is still in the trash can't hold it
+    conn.execute(
+        "UPDATE folders SET parent_id = NULL
+         WHERE id = ?1
+           AND parent_id IN (SELECT id FROM folders WHERE deleted_at IS NOT NULL)",
+        [id],
+    )?;
+    Ok(())
+}
+
+/// Permanently delete a trashed prompt
+pub fn purge_prompt(conn: &Connection, id: &str) -> Result<(), DbError> {
+    if purge_prompts(conn, "id = ?1", &[&id])? == 0 {
+        return Err(DbError::NotFound("Trashed prompt"));
+    }
+    Ok(())
+}
+
+/// Permanently delete a trashed folder, its subfolders and the trashed
+/// prompts inside them
+pub fn purge_folder(conn: &Connection, id: &str) -> Result<PurgeResult, DbError> {
+    let trashed: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM folders WHERE id = ?1 AND deleted_at IS NOT NULL)",
+        [id],
+        |row| row.get(0),
+    )?;
+    if !trashed {
+        return Err(DbError::NotFound("Trashed folder"));
+    }
+
+    let filter = format!("folder_id IN ({SUBTREE})");
+    let prompts = purge_prompts(conn, &filter, &[&id])?;
+    let folders = purge_folders(conn, &format!("id IN ({SUBTREE})"), &[&id])?;
+    Ok(PurgeResult { prompts, folders })
+}
+
+/// Permanently delete everything in the trash
+pub fn empty_trash(conn: &Connection) -> Result<PurgeResult, DbError> {
+    Ok(PurgeResult {
+        prompts: purge_prompts(conn, "1", &[])?,
+        folders: purge_folders(conn, "1", &[])?,
+    })
+}
+
+/// Permanently delete items that have been in the trash longer than
+/// `retention_days`
+pub fn purge_expired(
+    conn: &Connection,
+    retention_days: u32,
+    now: i64,
+) -> Result<PurgeResult, DbError> {
+    let cutoff = now - i64::from(retention_days) * DAY_MS;
+    Ok(PurgeResult {
+        prompts: purge_prompts(conn, "deleted_at < ?1", &[&cutoff])?,
+        folders: purge_folders(conn, "deleted_at < ?1", &[&cutoff])?,
+    })
+}
+
+/// Prompt deletions after `since`, both those still in the trash and those
+/// already purged
+pub fn tombstones(conn: &Connection, since: i64) -> Result<Vec<Tombstone>, DbError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, cloud_id, deleted_at, 0 FROM prompts
+         WHERE deleted_at IS NOT NULL AND deleted_at > ?1
+         UNION ALL
+         SELECT prompt_id, cloud_id, deleted_at, 1 FROM sync_tombstones
+         WHERE deleted_at > ?1
+         ORDER BY 3",
+    )?;
+
+    let tombstones = stmt
+        .query_map([since], |row| {
+            Ok(Tombstone {
+                prompt_id: row.get(0)?,
+                cloud_id: row.get(1)?,
+                deleted_at: row.get(2)?,
+                purged: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(tombstones)
+}
+
+/// Hard-delete trashed prompts matching `filter`, leaving a tombstone for each
+fn purge_prompts(conn: &Connection, filter: &str, params: &[&dyn ToSql]) -> Result<usize, DbError> {
+    let ids = trashed_ids(conn, "prompts", filter, params)?;
+    conn.execute(
+        &format!(
+            "INSERT OR REPLACE INTO sync_tombstones (prompt_id, cloud_id, deleted_at)
+             SELECT id, cloud_id, deleted_at FROM prompts
+             WHERE deleted_at IS NOT NULL AND {filter}"
+        ),
+        params,
+    )?;
+    let purged = conn.execute(
+        &format!("DELETE FROM prompts WHERE deleted_at IS NOT NULL AND {filter}"),
+        params,
+    )?;
+    // Undo can't bring purged rows back, so history that refers to them goes
+    journal::forget(conn, &ids)?;
+    Ok(purged)
+}
+
+/// Hard-delete trashed folders matching `filter`
+fn purge_folders(conn: &Connection, filter: &str, params: &[&dyn ToSql]) -> Result<usize, DbError> {
+    let ids = trashed_ids(conn, "folders", filter, params)?;
+    // Live folders nested under a purged one would otherwise cascade with it
+    conn.execute(
+        &format!(
+            "UPDATE folders SET parent_id = NULL
+             WHERE deleted_at IS NULL
+               AND parent_id IN (SELECT id FROM folders WHERE deleted_at IS NOT NULL AND {filter})"
+        ),
+        params,
+    )?;
+    let purged = conn.execute(
+        &format!("DELETE FROM folders WHERE deleted_at IS NOT NULL AND {filter}"),
+        params,
+    )?;
+    journal::forget(conn, &ids)?;
+    Ok(purged)
+}
+
+/// Ids of trashed rows in `table` matching `filter`
+fn trashed_ids(
+    conn: &Connection,
+    table: &str,
+    filter: &str,
+    params: &[&dyn ToSql],
+) -> Result<Vec<String>, DbError> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id FROM {table} WHERE deleted_at IS NOT NULL AND {filter}"
+    ))?;
+    let ids = stmt
+        .query_map(params, |row| row.get(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(ids)
+}
+
+// ============ App glue ============
+
+/// Purge whatever has outlived the configured trash retention
+pub async fn run_expiry(app_handle: &AppHandle) -> Result<PurgeResult, DbError> {
+    let retention_days = app_handle.state::<SettingsState>().get().trash.retention_days;
+    if retention_days == 0 {
+        return Ok(PurgeResult::default());
+    }
+
+    let now = chrono::Utc::now().timestamp_millis();
+    app_handle
+        .state::<Database>()
+        .run_background(move |conn| {
+            let tx = conn.transaction()?;
+            let purged = purge_expired(&tx, retention_days, now)?;
+            tx.commit()?;
+            Ok(purged)
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use crate::journal::Direction;
+    use crate::repository::{CreatePromptInput, PromptRepository, UpdatePromptInput};
+
+    fn create(conn: &Connection, text: &str) -> String {
+        let input = CreatePromptInput {
+            text: text.to_string(),
+            ..Default::default()
+        };
+        PromptRepository::new(conn).create(input, 1).unwrap().id
+    }
+
+    #[test]
+    fn purge_keeps_unrelated_history() {
+        let conn = db::open_in_memory();
+        let prompts = PromptRepository::new(&conn);
+        let kept = create(&conn, "Before");
+        let purged = create(&conn, "Gone soon");
+        let edit = UpdatePromptInput {
+            text: Some("After".to_string()),
+            ..Default::default()
+        };
+        prompts.update(&kept, edit, 2).unwrap();
+        prompts.delete(&purged, 3).unwrap();
+
+        purge_prompt(&conn, &purged).unwrap();
+
+        let undone = journal::apply(&conn, Direction::Undo, 5).unwrap().unwrap();
+        assert_eq!(undone.entities[0].id, kept);
+        assert_eq!(prompts.get(&kept).unwrap().unwrap().text, "Before");
+
+        // Creating the purged prompt was forgotten along with its deletion
+        journal::apply(&conn, Direction::Undo, 5).unwrap().unwrap();
+        assert!(journal::apply(&conn, Direction::Undo, 5).unwrap().is_none());
+    }
+}
@@ -0,0 +1,228 @@
+//! Tray menu listing favorite and frequently used prompts, plus quick
+//! actions. The menu is rebuilt whenever the library changes.
+
+use crate::db::{Database, DbError};
+use crate::error::CommandError;
+use crate::repository::{self, Prompt, PromptRepository};
+use crate::shortcuts;
+use crate::vault::VaultError;
+use rusqlite::Connection;
+use std::collections::HashSet;
+use tauri::menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem};
+use tauri::{AppHandle, Emitter, Listener, Manager, Wry};
+use tauri_plugin_clipboard_manager::ClipboardExt;
+
+/// Tray icon created from `trayIcon` in tauri.conf.json
+const TRAY_ID: &str = "main";
+
+/// Most prompts listed in each section
+const SECTION_SIZE: usize = 8;
+
+/// Longest prompt title shown in the menu
+const LABEL_CHARS: usize = 40;
+
+const PROMPT_PREFIX: &str = "prompt:";
+const CAPTURE_ID: &str = "capture";
+const OPEN_LIBRARY_ID: &str = "open-library";
+const SYNC_ID: &str = "sync-now";
+const QUIT_ID: &str = "quit";
+
+/// Prompts for each menu section, none listed twice
+struct TrayPrompts {
+    favorites: Vec<Prompt>,
+    most_used: Vec<Prompt>,
+    recent: Vec<Prompt>,
+}
+
+impl TrayPrompts {
+    fn load(conn: &Connection) -> Result<Self, DbError> {
+        let prompts = PromptRepository::new(conn);
+        let mut seen = HashSet::new();
+        let mut unseen = |list: Vec<Prompt>| -> Vec<Prompt> {
+            list.into_iter()
+                .filter(|p| seen.insert(p.id.clone()))
+                .take(SECTION_SIZE)
+                .collect()
+        };
+
+        // Fetch extra used prompts to make up for ones already listed above
+        Ok(Self {
+            favorites: unseen(prompts.favorites(SECTION_SIZE)?),
+            most_used: unseen(prompts.most_used(SECTION_SIZE * 2)?),
+            recent: unseen(prompts.recently_used(SECTION_SIZE * 3)?),
+        })
+    }
+
+    fn sections(&self) -> [(&'static str, &[Prompt]); 3] {
+        [
+            ("Favorites", &self.favorites),
+            ("Most Used", &self.most_used),
+            ("Recent", &self.recent),
+        ]
+    }
+}
+
+/// Handle clicks on the tray menu and keep it up to date
+pub fn init(app_handle: &AppHandle) {
+    let Some(tray) = app_handle.tray_by_id(TRAY_ID) else {
+        log::warn!("Tray icon not found, skipping the tray menu");
+        return;
+    };
+    tray.on_menu_event(handle_menu_event);
+    spawn_refresh(app_handle);
+
+    // The library can only be listed once it is open and unlocked
+    for event in ["database-ready", "vault-unlocked", "vault-locked"] {
+        let handle = app_handle.clone();
+        app_handle.listen(event, move |_| spawn_refresh(&handle));
+    }
+
+    let mut library_changes = app_handle.state::<Database>().subscribe_changes();
+    let handle = app_handle.clone();
+    tauri::async_runtime::spawn(async move {
+        use tokio::sync::broadcast::error::RecvError;
+        while let Ok(_) | Err(RecvError::Lagged(_)) = library_changes.recv().await {
+            refresh(&handle).await;
+        }
+    });
+}
+
+fn spawn_refresh(app_handle: &AppHandle) {
+    let handle = app_handle.clone();
+    tauri::async_runtime::spawn(async move { refresh(&handle).await });
+}
+
+/// Rebuild the menu from the current library
+async fn refresh(app_handle: &AppHandle) {
+    let Some(tray) = app_handle.tray_by_id(TRAY_ID) else {
+        return;
+    };
+    let prompts = app_handle
+        .state::<Database>()
+        .run_background(|conn| TrayPrompts::load(conn))
+        .await;
+    let result = build_menu(app_handle, &prompts).and_then(|menu| tray.set_menu(Some(menu)));
+    if let Err(e) = result {
+        log::warn!("Failed to update the tray menu: {}", e);
+    }
+}
+
+fn build_menu(
+    app_handle: &AppHandle,
+    prompts: &Result<TrayPrompts, DbError>,
+) -> tauri::Result<Menu<Wry>> {
+    let menu = Menu::new(app_handle)?;
+    let note = |text: &str| MenuItem::new(app_handle, text, false, None::<&str>);
+    let separator = || PredefinedMenuItem::separator(app_handle);
+
+    match prompts {
+        Ok(prompts) => {
+            let mut listed = false;
+            for (title, section) in prompts.sections() {
+                if section.is_empty() {
+                    continue;
+                }
+                if listed {
+                    menu.append(&separator()?)?;
+                }
+                menu.append(&note(title)?)?;
+                for prompt in section {
+                    menu.append(&MenuItem::with_id(
+                        app_handle,
+                        format!("{PROMPT_PREFIX}{}", prompt.id),
+                        label(prompt),
+                        true,
+                        None::<&str>,
+                    )?)?;
+                }
+                listed = true;
+            }
+            if !listed {
+                menu.append(&note("Favorite or use a prompt to list it here")?)?;
+            }
+        }
+        Err(DbError::Vault(VaultError::Locked)) => menu.append(&note("Library locked")?)?,
+        Err(_) => menu.append(&note("Library unavailable")?)?,
+    }
+
+    menu.append(&separator()?)?;
+    for (id, text) in [
+        (CAPTURE_ID, "Save Clipboard as Prompt"),
+        (OPEN_LIBRARY_ID, "Open Library"),
+        (SYNC_ID, "Sync Now"),
+    ] {
+        menu.append(&MenuItem::with_id(
+            app_handle,
+            id,
+            text,
+            true,
+            None::<&str>,
+        )?)?;
+    }
+    menu.append(&separator()?)?;
+    menu.append(&MenuItem::with_id(
+        app_handle,
+        QUIT_ID,
+        "Quit PromptPack",
+        true,
+        None::<&str>,
+    )?)?;
+    Ok(menu)
+}
+
+/// Header, or the start of the first line when there is none
+fn label(prompt: &Prompt) -> String {
+    repository::title(prompt.header.as_deref(), &prompt.text, LABEL_CHARS)
+}
+
+fn handle_menu_event(app_handle: &AppHandle, event: MenuEvent) {
+    match event.id().as_ref() {
+        CAPTURE_ID => shortcuts::spawn_capture(app_handle),
+        OPEN_LIBRARY_ID => show_main_window(app_handle),
+        SYNC_ID => {
+            show_main_window(app_handle);
+            let _ = app_handle.emit("sync-requested", ());
+        }
+        QUIT_ID => app_handle.exit(0),
+        id => {
+            if let Some(prompt_id) = id.strip_prefix(PROMPT_PREFIX) {
+                let handle = app_handle.clone();
+                let prompt_id = prompt_id.to_string();
+                tauri::async_runtime::spawn(async move {
+                    if let Err(e) = copy_prompt(&handle, prompt_id).await {
+                        log::warn!("Failed to copy prompt from the tray: {}", e);
+                    }
+                });
+            }
+        }
+    }
+}
+
+/// Copy a prompt's text and count it as used
+async fn copy_prompt(app_handle: &AppHandle, id: String) -> Result<(), CommandError> {
+    let database = app_handle.state::<Database>();
+    let prompt = database
+        .run(move |conn| {
+            PromptRepository::new(conn)
+                .get(&id)?
+                .ok_or(DbError::NotFound("Prompt"))
+        })
+        .await?;
+
+    app_handle.clipboard().write_text(prompt.text.as_str())?;
+    database
+        .run(move |conn| {
+            let now = chrono::Utc::now().timestamp_millis();
+            PromptRepository::new(conn).record_use(&prompt.id, now)
+        })
+        .await?;
+    Ok(())
+}
+
+pub fn show_main_window(app_handle: &AppHandle) {
+    if let Some(window) = app_handle.get_webview_window("main") {
+        let _ = window.unminimize();
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+}
@@ -0,0 +1,429 @@
+use crate::db::DbError;
+use rusqlite::Connection;
+use serde::Serialize;
+use thiserror::Error;
+
+pub const MAX_TEXT_CHARS: usize = 100_000;
+pub const MAX_HEADER_CHARS: usize = 200;
+pub const MAX_NAME_CHARS: usize = 100;
+pub const MAX_ICON_CHARS: usize = 16;
+pub const MAX_URL_CHARS: usize = 2048;
+
+/// Where a prompt came from. Stored as text in `prompts.source`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromptSource {
+    Manual,
+    Chatgpt,
+    Claude,
+    Gemini,
+    Perplexity,
+    Grok,
+    Deepseek,
+    Kimi,
+    Custom,
+    /// Saved from the clipboard with the capture shortcut
+    Clipboard,
+}
+
+impl PromptSource {
+    pub const ALL: [PromptSource; 10] = [
+        PromptSource::Manual,
+        PromptSource::Chatgpt,
+        PromptSource::Claude,
+        PromptSource::Gemini,
+        PromptSource::Perplexity,
+        PromptSource::Grok,
+        PromptSource::Deepseek,
+        PromptSource::Kimi,
+        PromptSource::Custom,
+        PromptSource::Clipboard,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PromptSource::Manual => "manual",
+            PromptSource::Chatgpt => "chatgpt",
+            PromptSource::Claude => "claude",
+            PromptSource::Gemini => "gemini",
+            PromptSource::Perplexity => "perplexity",
+            PromptSource::Grok => "grok",
+            PromptSource::Deepseek => "deepseek",
+            PromptSource::Kimi => "kimi",
+            PromptSource::Custom => "custom",
+            PromptSource::Clipboard => "clipboard",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|s| s.as_str() == value)
+    }
+}
+
+/// One rejected field
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("{}", summary(.fields))]
+pub struct ValidationError {
+    pub fields: Vec<FieldError>,
+}
+
+fn summary(fields: &[FieldError]) -> String {
+    fields
+        .iter()
+        .map(|f| format!("{}: {}", f.field, f.message))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Collects field errors so a form can show all of them at once
+#[derive(Debug, Default)]
+pub struct Validator {
+    errors: Vec<FieldError>,
+}
+
+impl Validator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn check(&mut self, field: &str, result: Result<(), String>) -> &mut Self {
+        if let Err(message) = result {
+            self.errors.push(FieldError {
+                field: field.to_string(),
+                message,
+            });
+        }
+        self
+    }
+
+    /// Required text that isn't blank
+    pub fn required(&mut self, field: &str, value: &str, max_chars: usize) -> &mut Self {
+        self.check(
+            field,
+            non_empty(value).and_then(|_| max_len(value, max_chars)),
+        )
+    }
+
+    pub fn optional(&mut self, field: &str, value: Option<&str>, max_chars: usize) -> &mut Self {
+        self.check(field, value.map_or(Ok(()), |v| max_len(v, max_chars)))
+    }
+
+    pub fn source(&mut self, field: &str, value: Option<&str>) -> &mut Self {
+        self.check(field, value.map_or(Ok(()), source))
+    }
+
+    pub fn url(&mut self, field: &str, value: Option<&str>) -> &mut Self {
+        self.check(field, value.map_or(Ok(()), url))
+    }
+
+    pub fn color(&mut self, field: &str, value: Option<&str>) -> &mut Self {
+        self.check(field, value.map_or(Ok(()), color))
+    }
+
+    /// A folder id that refers to a live folder
+    pub fn folder(
+        &mut self,
+        conn: &Connection,
+        field: &str,
+        value: Option<&str>,
+    ) -> Result<&mut Self, DbError> {
+        let result = match value {
+            Some(id) => live_folder(conn, id)?,
+            None => Ok(()),
+        };
+        Ok(self.check(field, result))
+    }
+
+    pub fn tags(
+        &mut self,
+        conn: &Connection,
+        field: &str,
+        ids: &[String],
+    ) -> Result<&mut Self, DbError> {
+        for id in ids {
+            let exists: bool = conn.query_row(
+                "SELECT EXISTS (SELECT 1 FROM tags WHERE id = ?1)",
+                [id],
+                |row| row.get(0),
+            )?;
+            if !exists {
+                self.check(field, Err(format!("Unknown tag {id}")));
+            }
+        }
+        Ok(self)
+    }
+
+    /// Tags referred to by name, as a filter does
+    pub fn tag_names(
+        &mut self,
+        conn: &Connection,
+        field: &str,
+        names: &[String],
+    ) -> Result<&mut Self, DbError> {
+        for name in names {
+            let exists: bool = conn.query_row(
+                "SELECT EXISTS (SELECT 1 FROM tags WHERE name = ?1)",
+                [name],
+                |row| row.get(0),
+            )?;
+            if !exists {
+                self.check(field, Err(format!("Unknown tag \"{name}\"")));
+            }
+        }
+        Ok(self)
+    }
+
+    pub fn finish(&mut self) -> Result<(), ValidationError> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationError {
+                fields: std::mem::take(&mut self.errors),
+            })
+        }
+    }
+}
+
+fn non_empty(value: &str) -> Result<(), String> {
+    if value.trim().is_empty() {
+        return Err("Must not be empty".to_string());
+    }
+    Ok(())
+}
+
+fn max_len(value: &str, max_chars: usize) -> Result<(), String> {
+    if value.chars().count() > max_chars {
+        return Err(format!("Must be at most {max_chars} characters"));
+    }
+    Ok(())
+}
+
+fn source(value: &str) -> Result<(), String> {
+    match PromptSource::parse(value) {
+        Some(_) => Ok(()),
+        None => Err(format!("Unknown source \"{value}\"")),
+    }
+}
+
+/// An http(s) URL, or empty for none
+fn url(value: &str) -> Result<(), String> {
+    if value.is_empty() {
+        return Ok(());
+    }
+    max_len(value, MAX_URL_CHARS)?;
+    let parsed = url::Url::parse(value).map_err(|e| format!("Invalid URL: {e}"))?;
+    match parsed.scheme() {
+        "http" | "https" if parsed.host().is_some() => Ok(()),
+        "http" | "https" => Err("URL has no host".to_string()),
+        scheme => Err(format!("Unsupported URL scheme \"{scheme}\"")),
+    }
+}
+
+/// `#rgb` or `#rrggbb`, or empty for none
+fn color(value: &str) -> Result<(), String> {
+    if value.is_empty() {
+        return Ok(());
+    }
+    let valid = value.strip_prefix('#').is_some_and(|hex| {
+        matches!(hex.len(), 3 | 6) && hex.chars().all(|c| c.is_ascii_hexdigit())
+    });
+    if !valid {
+        return Err("Must be a hex color like #7c5cff".to_string());
+    }
+    Ok(())
+}
+
+fn live_folder(conn: &Connection, id: &str) -> Result<Result<(), String>, DbError> {
+    let live: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM folders WHERE id = ?1 AND deleted_at IS NULL)",
+        [id],
+        |row| row.get(0),
+    )?;
+    Ok(if live {
+        Ok(())
+    } else {
+        Err("Folder not found".to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conn() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE folders (id TEXT PRIMARY KEY, deleted_at INTEGER);
+             CREATE TABLE tags (id TEXT PRIMARY KEY, name TEXT);
+             INSERT INTO folders VALUES ('live', NULL), ('trashed', 1);
+             INSERT INTO tags VALUES ('t1', 'work');",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn fields(result: Result<(), ValidationError>) -> Vec<String> {
+        result
+            .err()
+            .map(|e| e.fields.into_iter().map(|f| f.field).collect())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn required_text_must_not_be_blank() {
+        assert_eq!(
+            fields(Validator::new().required("text", "  \n", 10).finish()),
+            ["text"]
+        );
+        assert!(Validator::new().required("text", "hi", 10).finish().is_ok());
+    }
+
+    #[test]
+    fn text_length_is_counted_in_characters() {
+        assert!(Validator::new().required("text", "ééé", 3).finish().is_ok());
+        assert_eq!(
+            fields(Validator::new().required("text", "éééé", 3).finish()),
+            ["text"]
+        );
+        assert_eq!(
+            fields(
+                Validator::new()
+                    .optional("header", Some("abcd"), 3)
+                    .finish()
+            ),
+            ["header"]
+        );
+        assert!(Validator::new()
+            .optional("header", None, 3)
+            .finish()
+            .is_ok());
+    }
+
+    #[test]
+    fn source_must_be_known() {
+        for source in PromptSource::ALL {
+            assert!(Validator::new()
+                .source("source", Some(source.as_str()))
+                .finish()
+                .is_ok());
+        }
+        assert_eq!(
+            fields(Validator::new().source("source", Some("ChatGPT")).finish()),
+            ["source"]
+        );
+        assert_eq!(
+            fields(Validator::new().source("source", Some("")).finish()),
+            ["source"]
+        );
+    }
+
+    #[test]
+    fn url_must_be_http_with_a_host() {
+        for ok in [
+            "",
+            "https://chatgpt.com/c/123",
+            "http://localhost:3000/x?y=1",
+        ] {
+            assert!(
+                Validator::new().url("url", Some(ok)).finish().is_ok(),
+                "{ok}"
+            );
+        }
+        for bad in [
+            "chatgpt.com",
+            "javascript:alert(1)",
+            "file:///etc/passwd",
+            "https://",
+        ] {
+            assert_eq!(
+                fields(Validator::new().url("url", Some(bad)).finish()),
+                ["url"],
+                "{bad}"
+            );
+        }
+        let long = format!("https://example.com/{}", "a".repeat(MAX_URL_CHARS));
+        assert_eq!(
+            fields(Validator::new().url("url", Some(&long)).finish()),
+            ["url"]
+        );
+    }
+
+    #[test]
+    fn color_must_be_hex() {
+        for ok in ["", "#fff", "#7C5CFF", "#7c5cff"] {
+            assert!(
+                Validator::new().color("color", Some(ok)).finish().is_ok(),
+                "{ok}"
+            );
+        }
+        for bad in ["red", "7c5cff", "#7c5cf", "#7c5cfg", "#ffff", "rgb(1,2,3)"] {
+            assert_eq!(
+                fields(Validator::new().color("color", Some(bad)).finish()),
+                ["color"],
+                "{bad}"
+            );
+        }
+    }
+
+    #[test]
+    fn folder_must_be_live() {
+        let conn = conn();
+        let check = |id: Option<&str>| {
+            let mut v = Validator::new();
+            v.folder(&conn, "folder_id", id).unwrap();
+            fields(v.finish())
+        };
+        assert!(check(Some("live")).is_empty());
+        assert!(check(None).is_empty());
+        assert_eq!(check(Some("trashed")), ["folder_id"]);
+        assert_eq!(check(Some("missing")), ["folder_id"]);
+    }
+
+    #[test]
+    fn tags_must_exist() {
+        let conn = conn();
+        let mut v = Validator::new();
+        v.tags(&conn, "tag_ids", &["t1".to_string(), "nope".to_string()])
+            .unwrap();
+        let error = v.finish().unwrap_err();
+        assert_eq!(error.fields.len(), 1);
+        assert!(error.fields[0].message.contains("nope"));
+    }
+
+    #[test]
+    fn tag_names_must_exist() {
+        let conn = conn();
+        let mut v = Validator::new();
+        v.tag_names(&conn, "tags", &["work".to_string(), "t1".to_string()])
+            .unwrap();
+        let error = v.finish().unwrap_err();
+        assert_eq!(error.fields.len(), 1);
+        assert!(error.fields[0].message.contains("t1"));
+    }
+
+    #[test]
+    fn every_failing_field_is_reported() {
+        let error = Validator::new()
+            .required("text", "", MAX_TEXT_CHARS)
+            .source("source", Some("myspace"))
+            .color("color", Some("blue"))
+            .finish()
+            .unwrap_err();
+        assert_eq!(
+            error
+                .fields
+                .iter()
+                .map(|f| f.field.as_str())
+                .collect::<Vec<_>>(),
+            ["text", "source", "color"]
+        );
+        assert!(error
+            .to_string()
+            .starts_with("text: Must not be empty; source:"));
+    }
+}
@@ -0,0 +1,326 @@
+use rusqlite::{Connection, DatabaseName, ErrorCode};
+use std::fs::File;
+use std::io::{ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use thiserror::Error;
+
+// Every plaintext SQLite file starts with this header. SQLCipher encrypts the
+// whole file including page 1, so anything else means the vault is enabled.
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
+#[derive(Error, Debug)]
+pub enum VaultError {
+    #[error("Vault is locked")]
+    Locked,
+    #[error("Vault is not enabled")]
+    NotEnabled,
+    #[error("Vault is already enabled")]
+    AlreadyEnabled,
+    #[error("Password required")]
+    PasswordRequired,
+    #[error("Invalid password")]
+    InvalidPassword,
+    #[error("The library is in use by another program")]
+    InUse,
+    #[error("SQLite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Holds the vault key while the library is unlocked.
+///
+/// The key never touches disk. Locking simply forgets it, after which no new
+/// connection to an encrypted database can be opened.
+#[derive(Default)]
+pub struct VaultState {
+    key: Mutex<Option<String>>,
+}
+
+impl VaultState {
+    pub fn key(&self) -> Option<String> {
+        self.key.lock().ok().and_then(|k| k.clone())
+    }
+
+    pub fn is_unlocked(&self) -> bool {
+        self.key.lock().map(|k| k.is_some()).unwrap_or(false)
+    }
+
+    pub fn unlock(&self, key: String) {
+        if let Ok(mut k) = self.key.lock() {
+            *k = Some(key);
+        }
+    }
+
+    pub fn lock(&self) {
+        if let Ok(mut k) = self.key.lock() {
+            *k = None;
+        }
+    }
+}
+
+/// Check whether the database at `path` is encrypted.
+///
+/// A missing or empty file is treated as plaintext, since SQLite will create
+/// a fresh unencrypted database there.
+pub fn is_encrypted(path: &Path) -> Result<bool, VaultError> {
+    let mut file = match File::open(path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut header = [0u8; 16];
+    match file.read_exact(&mut header) {
+        Ok(()) => Ok(&header != SQLITE_HEADER),
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Apply the SQLCipher key to a freshly opened connection.
+///
+/// Must run before any other statement on the connection.
+pub fn apply_key(conn: &Connection, key: &str) -> rusqlite::Result<()> {
+    conn.pragma_update(None, "key", key)
+}
+
+/// Check that `key` opens the encrypted database at `path`
+pub fn verify_key(path: &Path, key: &str) -> Result<(), VaultError> {
+    if key.is_empty() {
+        return Err(VaultError::PasswordRequired);
+    }
+
+    let conn = Connection::open(path)?;
+    apply_key(&conn, key)?;
+    read_schema(&conn)
+}
+
+/// Encrypt a plaintext database in place (migration into vault mode)
+pub fn encrypt_database(path: &Path, key: &str) -> Result<(), VaultError> {
+    if key.is_empty() {
+        return Err(VaultError::PasswordRequired);
+    }
+    if is_encrypted(path)? {
+        return Err(VaultError::AlreadyEnabled);
+    }
+    export_database(path, None, Some(key))
+}
+
+/// Decrypt a vault database in place, turning vault mode off
+pub fn decrypt_database(path: &Path, key: &str) -> Result<(), VaultError> {
+    if !is_encrypted(path)? {
+        return Err(VaultError::NotEnabled);
+    }
+    verify_key(path, key)?;
+    export_database(path, Some(key), None)
+}
+
+/// Copy the database into a temporary file with a different key, verify the
+/// copy, then swap it over the original.
+///
+/// The app's own connections must be closed first. The WAL is checkpointed
+/// into the file beforehand, and the swap is refused while another process
+/// (the CLI, an MCP server) still has it open in a transaction.
+fn export_database(
+    path: &Path,
+    from_key: Option<&str>,
+    to_key: Option<&str>,
+) -> Result<(), VaultError> {
+    let tmp_path = sibling_path(path, "vault-tmp");
+    if tmp_path.exists() {
+        std::fs::remove_file(&tmp_path)?;
+    }
+
+    {
+        let conn = Connection::open(path)?;
+        if let Some(key) = from_key {
+            apply_key(&conn, key)?;
+        }
+        read_schema(&conn)?;
+        checkpoint(&conn)?;
+
+        // An empty KEY attaches the target as a plaintext database
+        conn.execute(
+            "ATTACH DATABASE ?1 AS vault_export KEY ?2",
+            rusqlite::params![tmp_path.to_string_lossy(), to_key.unwrap_or("")],
+        )?;
+        conn.query_row("SELECT sqlcipher_export('vault_export')", [], |_| Ok(()))?;
+
+        // sqlcipher_export does not carry the schema version over
+        let user_version: i64 = conn.query_row("PRAGMA main.user_version", [], |row| row.get(0))?;
+        conn.pragma_update(
+            Some(DatabaseName::Attached("vault_export")),
+            "user_version",
+            user_version,
+        )?;
+        conn.execute("DETACH DATABASE vault_export", [])?;
+    }
+
+    // Make sure the copy is readable before replacing anything
+    {
+        let conn = Connection::open(&tmp_path)?;
+        if let Some(key) = to_key {
+            apply_key(&conn, key)?;
+        }
+        read_schema(&conn)?;
+    }
+
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+/// Move everything in the WAL into the main file and empty it
+fn checkpoint(conn: &Connection) -> Result<(), VaultError> {
+    // (busy, frames in the log, frames checkpointed)
+    let (busy, _, _): (i64, i64, i64) =
+        conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?;
+    if busy != 0 {
+        return Err(VaultError::InUse);
+    }
+    Ok(())
+}
+
+/// Touch the schema so SQLCipher actually decrypts page 1
+fn read_schema(conn: &Connection) -> Result<(), VaultError> {
+    conn.query_row("SELECT count(*) FROM sqlite_master", [], |row| {
+        row.get::<_, i64>(0)
+    })
+    .map(|_| ())
+    .map_err(|e| match e.sqlite_error_code() {
+        Some(ErrorCode::NotADatabase) => VaultError::InvalidPassword,
+        _ => VaultError::Sqlite(e),
+    })
+}
+
+/// `promptpack.db` -> `promptpack.db-<suffix>`, matching SQLite's own naming
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push("-");
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    /// A migrated plaintext library in WAL mode, as the app leaves it, in a
+    /// scratch directory removed again on drop
+    struct Library(PathBuf);
+
+    impl Library {
+        fn new() -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("promptpack-vault-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let path = dir.join("promptpack.db");
+            let mut conn = Connection::open(&path).unwrap();
+            conn.pragma_update(None, "journal_mode", "WAL").unwrap();
+            db::migrate(&mut conn).unwrap();
+            conn.execute(
+                "INSERT INTO prompts (id, text, created_at, updated_at) VALUES ('p1', 'Hello', 1, 1)",
+                [],
+            )
+            .unwrap();
+            Self(path)
+        }
+
+        fn open(&self, key: Option<&str>) -> Connection {
+            let conn = Connection::open(&self.0).unwrap();
+            if let Some(key) = key {
+                apply_key(&conn, key).unwrap();
+            }
+            conn
+        }
+    }
+
+    impl Drop for Library {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(self.0.parent().unwrap());
+        }
+    }
+
+    fn user_version(conn: &Connection) -> i64 {
+        conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    fn text(conn: &Connection) -> String {
+        conn.query_row("SELECT text FROM prompts WHERE id = 'p1'", [], |row| {
+            row.get(0)
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn encrypt_and_decrypt_round_trip() {
+        let library = Library::new();
+        let version = user_version(&library.open(None));
+
+        encrypt_database(&library.0, "secret").unwrap();
+        assert!(is_encrypted(&library.0).unwrap());
+        assert!(matches!(
+            encrypt_database(&library.0, "secret"),
+            Err(VaultError::AlreadyEnabled)
+        ));
+        verify_key(&library.0, "secret").unwrap();
+        let conn = library.open(Some("secret"));
+        assert_eq!(user_version(&conn), version);
+        assert_eq!(text(&conn), "Hello");
+        drop(conn);
+
+        decrypt_database(&library.0, "secret").unwrap();
+        assert!(!is_encrypted(&library.0).unwrap());
+        let conn = library.open(None);
+        assert_eq!(user_version(&conn), version);
+        assert_eq!(text(&conn), "Hello");
+    }
+
+    #[test]
+    fn wrong_password_is_rejected() {
+        let library = Library::new();
+        assert!(matches!(
+            decrypt_database(&library.0, "secret"),
+            Err(VaultError::NotEnabled)
+        ));
+        encrypt_database(&library.0, "secret").unwrap();
+
+        assert!(matches!(
+            verify_key(&library.0, "guess"),
+            Err(VaultError::InvalidPassword)
+        ));
+        assert!(matches!(
+            decrypt_database(&library.0, "guess"),
+            Err(VaultError::InvalidPassword)
+        ));
+        assert!(is_encrypted(&library.0).unwrap());
+    }
+
+    #[test]
+    fn swap_waits_for_other_readers() {
+        let library = Library::new();
+        let reader = library.open(None);
+        reader
+            .execute_batch("BEGIN; SELECT count(*) FROM prompts;")
+            .unwrap();
+        library
+            .open(None)
+            .execute("UPDATE prompts SET text = 'Hi' WHERE id = 'p1'", [])
+            .unwrap();
+
+        assert!(matches!(
+            encrypt_database(&library.0, "secret"),
+            Err(VaultError::InUse)
+        ));
+        assert!(!is_encrypted(&library.0).unwrap());
+
+        reader.execute_batch("COMMIT").unwrap();
+        encrypt_database(&library.0, "secret").unwrap();
+        assert_eq!(text(&library.open(Some("secret"))), "Hi");
+    }
+}
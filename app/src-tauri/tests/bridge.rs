@@ -0,0 +1,228 @@
+use promptpack_lib::bridge::{self, BridgeServer};
+use promptpack_lib::db::{self, Database, DbStatus};
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+const TOKEN: &str = "pairing-token";
+
+/// A bridge over a fresh library file, removed again on drop
+struct Library {
+    server: BridgeServer,
+    client: Client,
+    path: PathBuf,
+}
+
+impl Library {
+    async fn start() -> Self {
+        let path =
+            std::env::temp_dir().join(format!("promptpack-bridge-{}.db", uuid::Uuid::new_v4()));
+        let mut conn = rusqlite::Connection::open(&path).unwrap();
+        db::migrate(&mut conn).unwrap();
+        drop(conn);
+
+        let database = Database::default();
+        database.open(&path, None).unwrap();
+        database.set_status(DbStatus::Ready);
+        let server = bridge::serve(Arc::new(database), 0, TOKEN.to_string())
+            .await
+            .unwrap();
+
+        Self {
+            server,
+            client: Client::new(),
+            path,
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.server.addr(), path)
+    }
+
+    fn get(&self, path: &str) -> reqwest::RequestBuilder {
+        self.client.get(self.url(path)).bearer_auth(TOKEN)
+    }
+
+    fn post(&self, path: &str) -> reqwest::RequestBuilder {
+        self.client.post(self.url(path)).bearer_auth(TOKEN)
+    }
+
+    async fn create(&self, body: Value) -> Value {
+        let response = self.post("/v1/prompts").json(&body).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        response.json().await.unwrap()
+    }
+}
+
+impl Drop for Library {
+    fn drop(&mut self) {
+        let _ = db::remove_database_files(&self.path);
+    }
+}
+
+#[tokio::test]
+async fn requests_need_the_pairing_token() {
+    let library = Library::start().await;
+
+    let ping = library
+        .client
+        .get(library.url("/v1/ping"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(ping.status(), StatusCode::OK);
+
+    let anonymous = library
+        .client
+        .get(library.url("/v1/prompts"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(anonymous.status(), StatusCode::UNAUTHORIZED);
+    let error: Value = anonymous.json().await.unwrap();
+    assert_eq!(error["code"], "invalid_token");
+
+    let wrong = library
+        .client
+        .get(library.url("/v1/prompts"))
+        .bearer_auth("guess")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(wrong.status(), StatusCode::UNAUTHORIZED);
+
+    library.server.set_token("rotated".to_string());
+    let stale = library.get("/v1/prompts").send().await.unwrap();
+    assert_eq!(stale.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn other_hosts_are_refused() {
+    let library = Library::start().await;
+    let rebound = library
+        .get("/v1/prompts")
+        .header("host", "attacker.example")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(rebound.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn created_prompts_are_listed_and_found() {
+    let library = Library::start().await;
+    let first = library
+        .create(json!({ "text": "Summarize this article" }))
+        .await;
+    library
+        .create(json!({ "text": "Translate to French", "header": "French" }))
+        .await;
+    assert_eq!(first["text"], "Summarize this article");
+
+    let page: Value = library
+        .get("/v1/prompts?limit=1")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(page["prompts"].as_array().unwrap().len(), 1);
+    let cursor = page["next_cursor"].as_str().unwrap();
+    let rest: Value = library
+        .get(&format!("/v1/prompts?limit=1&cursor={cursor}"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(rest["prompts"].as_array().unwrap().len(), 1);
+    assert_ne!(page["prompts"][0]["id"], rest["prompts"][0]["id"]);
+
+    let favorites: Value = library
+        .get("/v1/prompts?favorite=true")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(favorites["total"], 0);
+
+    let found: Value = library
+        .get("/v1/prompts/search?q=french")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(found.as_array().unwrap().len(), 1);
+    assert_eq!(found[0]["text"], "Translate to French");
+}
+
+#[tokio::test]
+async fn use_is_recorded() {
+    let library = Library::start().await;
+    let prompt = library
+        .create(json!({ "text": "Explain like I'm five" }))
+        .await;
+    let id = prompt["id"].as_str().unwrap();
+
+    let used: Value = library
+        .post(&format!("/v1/prompts/{id}/use"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(used["use_count"], 1);
+    assert!(used["last_used_at"].is_i64());
+
+    let missing = library.post("/v1/prompts/nope/use").send().await.unwrap();
+    assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn invalid_prompts_are_rejected() {
+    let library = Library::start().await;
+    let response = library
+        .post("/v1/prompts")
+        .json(&json!({ "text": "  " }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let error: Value = response.json().await.unwrap();
+    assert_eq!(error["code"], "validation");
+}
+
+#[tokio::test]
+async fn extension_origins_get_cors_headers() {
+    let library = Library::start().await;
+    let preflight = library
+        .client
+        .request(reqwest::Method::OPTIONS, library.url("/v1/prompts"))
+        .header("origin", "chrome-extension://abcdef")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(preflight.status(), StatusCode::NO_CONTENT);
+    assert_eq!(
+        preflight.headers()["access-control-allow-origin"],
+        "chrome-extension://abcdef"
+    );
+
+    let website = library
+        .get("/v1/prompts")
+        .header("origin", "https://example.com")
+        .send()
+        .await
+        .unwrap();
+    assert!(!website
+        .headers()
+        .contains_key("access-control-allow-origin"));
+}
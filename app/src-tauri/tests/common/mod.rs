@@ -0,0 +1,39 @@
+//! Fixtures shared by the integration tests. Each test crate uses only some
+//! of them.
+#![allow(dead_code)]
+
+use promptpack_lib::db;
+use promptpack_lib::repository::{CreateFolderInput, CreatePromptInput};
+use rusqlite::Connection;
+use std::path::Path;
+
+/// A connection to `path` with foreign keys on, as the app opens it
+pub fn connect(path: &Path) -> Connection {
+    let conn = Connection::open(path).unwrap();
+    conn.pragma_update(None, "foreign_keys", true).unwrap();
+    conn
+}
+
+/// A migrated in-memory library
+pub fn open() -> Connection {
+    let mut conn = Connection::open_in_memory().unwrap();
+    conn.pragma_update(None, "foreign_keys", true).unwrap();
+    db::migrate(&mut conn).unwrap();
+    conn
+}
+
+pub fn prompt(text: &str, folder_id: Option<&str>) -> CreatePromptInput {
+    CreatePromptInput {
+        text: text.to_string(),
+        folder_id: folder_id.map(str::to_string),
+        ..Default::default()
+    }
+}
+
+pub fn folder(name: &str, parent_id: Option<&str>) -> CreateFolderInput {
+    CreateFolderInput {
+        name: name.to_string(),
+        parent_id: parent_id.map(str::to_string),
+        ..Default::default()
+    }
+}
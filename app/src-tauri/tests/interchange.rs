@@ -0,0 +1,176 @@
+mod common;
+
+use common::{folder, open, prompt};
+use promptpack_lib::interchange::{self, CsvOptions, Field};
+use promptpack_lib::repository::{
+    CreatePromptInput, FolderRepository, PromptRecord, PromptRepository, UpdatePromptInput,
+};
+use rusqlite::Connection;
+use std::collections::HashMap;
+
+/// A library with a tagged favorite in `Team > Reviews` and an unfiled prompt
+fn library() -> Connection {
+    let conn = open();
+    let folders = FolderRepository::new(&conn);
+    let team = folders.create(folder("Team", None), 1).unwrap();
+    let reviews = folders
+        .create(folder("Reviews", Some(&team.id)), 1)
+        .unwrap();
+
+    let prompts = PromptRepository::new(&conn);
+    let review = prompts
+        .create(
+            CreatePromptInput {
+                header: Some("Code review".to_string()),
+                url: Some("https://example.com/guide".to_string()),
+                ..prompt("Review this diff, \"carefully\"", Some(&reviews.id))
+            },
+            2,
+        )
+        .unwrap();
+    prompts
+        .update(
+            &review.id,
+            UpdatePromptInput {
+                is_favorite: Some(true),
+                ..Default::default()
+            },
+            3,
+        )
+        .unwrap();
+    conn.execute_batch(&format!(
+        "INSERT INTO tags (id, name) VALUES ('t1', 'backend'), ('t2', 'rust');
+         INSERT INTO prompt_tags (prompt_id, tag_id) VALUES ('{0}', 't1'), ('{0}', 't2');",
+        review.id
+    ))
+    .unwrap();
+    prompts
+        .create(prompt("Summarize this\narticle", None), 4)
+        .unwrap();
+    conn
+}
+
+#[test]
+fn json_and_csv_exports_import_into_another_library() {
+    let source = library();
+    let records = PromptRepository::new(&source).records(None).unwrap();
+    let json = interchange::to_json(&records, 10).unwrap();
+    let csv = interchange::to_csv(&records).unwrap();
+
+    for set in [
+        interchange::from_json(&json).unwrap(),
+        interchange::from_csv(&csv, &CsvOptions::default()).unwrap(),
+    ] {
+        let target = open();
+        let prompts = PromptRepository::new(&target);
+        let report = prompts.import_records(&set, None, 20).unwrap();
+        assert_eq!(report.created, 2);
+        assert_eq!(report.folders_created, 2);
+        assert!(report.errors.is_empty());
+
+        let imported = prompts.records(None).unwrap();
+        for (imported, original) in imported.iter().zip(&records) {
+            assert_eq!(imported.updated_at, Some(20));
+            assert_eq!(
+                PromptRecord {
+                    updated_at: original.updated_at,
+                    ..imported.clone()
+                },
+                *original
+            );
+        }
+
+        let again = prompts.import_records(&set, None, 30).unwrap();
+        assert_eq!(again.unchanged, 2);
+    }
+}
+
+#[test]
+fn dry_run_reports_without_writing() {
+    let mut conn = library();
+    let data = "Title,Prompt,Labels\n\
+                Greeting,Say hello,\"social, short\"\n\
+                Blank,,\n\
+                Farewell,Say goodbye,social\n";
+    let options = CsvOptions {
+        columns: HashMap::from([
+            (Field::Header, "Title".to_string()),
+            (Field::Text, "Prompt".to_string()),
+            (Field::Tags, "Labels".to_string()),
+        ]),
+        delimiter: None,
+    };
+    let set = interchange::from_csv(data, &options).unwrap();
+
+    let tx = conn.transaction().unwrap();
+    let report = PromptRepository::new(&tx)
+        .import_records(&set, None, 10)
+        .unwrap();
+    tx.rollback().unwrap();
+
+    assert_eq!(report.created, 2);
+    assert_eq!(report.errors.len(), 1);
+    assert_eq!(report.errors[0].location, "Row 3");
+    assert!(report.errors[0].message.starts_with("text:"));
+
+    let prompts = PromptRepository::new(&conn);
+    assert_eq!(prompts.records(None).unwrap().len(), 2);
+    let tags: i64 = conn
+        .query_row("SELECT COUNT(*) FROM tags", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(tags, 2);
+}
+
+#[test]
+fn csv_rows_update_prompts_by_id() {
+    let conn = library();
+    let prompts = PromptRepository::new(&conn);
+    let review = prompts.records(None).unwrap().remove(0);
+    let id = review.id.clone().unwrap();
+
+    let data = format!("id,text,favorite,tags\n{id},Review it again,false,frontend\n");
+    let set = interchange::from_csv(&data, &CsvOptions::default()).unwrap();
+    let report = prompts.import_records(&set, None, 10).unwrap();
+    assert_eq!(report.updated, 1);
+
+    let updated = prompts.records(None).unwrap().remove(0);
+    assert_eq!(updated.text, "Review it again");
+    assert_eq!(updated.favorite, Some(false));
+    assert_eq!(updated.tags, Some(vec!["frontend".to_string()]));
+    // Columns the file doesn't have keep their values
+    assert_eq!(updated.header, review.header);
+    assert_eq!(updated.url, review.url);
+    assert_eq!(updated.folder, review.folder);
+}
+
+#[test]
+fn csv_keeps_separators_inside_names() {
+    let source = open();
+    let ci = FolderRepository::new(&source)
+        .create(folder("CI/CD", None), 1)
+        .unwrap();
+    let prompt = PromptRepository::new(&source)
+        .create(prompt("Fix the pipeline", Some(&ci.id)), 2)
+        .unwrap();
+    source
+        .execute_batch(&format!(
+            "INSERT INTO tags (id, name) VALUES ('t1', 'build,test'), ('t2', 'ops');
+             INSERT INTO prompt_tags (prompt_id, tag_id) VALUES ('{0}', 't1'), ('{0}', 't2');",
+            prompt.id
+        ))
+        .unwrap();
+    let csv = interchange::to_csv(&PromptRepository::new(&source).records(None).unwrap()).unwrap();
+
+    let target = open();
+    let set = interchange::from_csv(&csv, &CsvOptions::default()).unwrap();
+    let prompts = PromptRepository::new(&target);
+    let report = prompts.import_records(&set, None, 10).unwrap();
+    assert_eq!(report.folders_created, 1);
+
+    let imported = prompts.records(None).unwrap().remove(0);
+    assert_eq!(imported.folder, Some(vec!["CI/CD".to_string()]));
+    assert_eq!(
+        imported.tags,
+        Some(vec!["build,test".to_string(), "ops".to_string()])
+    );
+}
@@ -0,0 +1,164 @@
+mod common;
+
+use common::{folder, open, prompt};
+use promptpack_lib::markdown;
+use promptpack_lib::repository::{
+    CreatePromptInput, FolderRepository, ImportReport, PromptRepository,
+};
+use rusqlite::Connection;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A scratch directory, removed again on drop
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new() -> Self {
+        let path = std::env::temp_dir().join(format!("promptpack-md-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn code_review(text: &str, folder_id: Option<&str>) -> CreatePromptInput {
+    CreatePromptInput {
+        header: Some("Code review".to_string()),
+        ..prompt(text, folder_id)
+    }
+}
+
+fn import(conn: &Connection, dir: &Path, folder_id: Option<&str>) -> ImportReport {
+    let set = markdown::read(dir).unwrap();
+    PromptRepository::new(conn)
+        .import_records(&set, folder_id, 100)
+        .unwrap()
+}
+
+#[test]
+fn export_mirrors_the_folder_tree() {
+    let conn = open();
+    let folders = FolderRepository::new(&conn);
+    let team = folders.create(folder("Team", None), 1).unwrap();
+    let reviews = folders
+        .create(folder("Reviews: Q1", Some(&team.id)), 1)
+        .unwrap();
+    let prompts = PromptRepository::new(&conn);
+    prompts
+        .create(code_review("Review this diff", Some(&reviews.id)), 2)
+        .unwrap();
+    prompts
+        .create(prompt("Summarize this\nin one line", None), 3)
+        .unwrap();
+    prompts
+        .create(code_review("Review the tests", Some(&reviews.id)), 4)
+        .unwrap();
+
+    let out = TempDir::new();
+    let written = markdown::write(&out.0, &prompts.records(None).unwrap()).unwrap();
+    assert_eq!(written, 3);
+    let reviews_dir = out.0.join("Team").join("Reviews- Q1");
+    assert!(reviews_dir.join("Code review.md").is_file());
+    assert!(reviews_dir.join("Code review (2).md").is_file());
+    assert!(out.0.join("Summarize this.md").is_file());
+
+    let subtree = TempDir::new();
+    markdown::write(&subtree.0, &prompts.records(Some(&team.id)).unwrap()).unwrap();
+    assert!(subtree
+        .0
+        .join("Reviews- Q1")
+        .join("Code review.md")
+        .is_file());
+    assert!(!subtree.0.join("Summarize this.md").exists());
+}
+
+#[test]
+fn import_updates_prompts_by_id() {
+    let conn = open();
+    let prompts = PromptRepository::new(&conn);
+    let review = prompts
+        .create(code_review("Review this diff", None), 1)
+        .unwrap();
+    let gone = prompts.create(prompt("Old prompt", None), 1).unwrap();
+
+    let out = TempDir::new();
+    markdown::write(&out.0, &prompts.records(None).unwrap()).unwrap();
+    let file = out.0.join("Code review.md");
+    let edited = fs::read_to_string(&file)
+        .unwrap()
+        .replace("Review this diff", "Review this diff twice")
+        .replace("---\n\n", "tags:\n- backend\n---\n\n");
+    fs::write(&file, edited).unwrap();
+    fs::create_dir_all(out.0.join("Writing").join(".obsidian")).unwrap();
+    fs::write(out.0.join("Writing").join("Tone.md"), "Make it friendlier").unwrap();
+    fs::write(
+        out.0.join("Writing").join(".obsidian").join("x.md"),
+        "hidden",
+    )
+    .unwrap();
+    fs::write(out.0.join("Broken.md"), "---\ntags: [\n---\ntext").unwrap();
+    prompts.delete(&gone.id, 2).unwrap();
+
+    let report = import(&conn, &out.0, None);
+    assert_eq!(report.updated, 2);
+    assert_eq!(report.created, 1);
+    assert_eq!(report.folders_created, 1);
+    assert_eq!(report.errors.len(), 1);
+    assert_eq!(report.errors[0].location, "Broken.md");
+
+    let updated = prompts.get(&review.id).unwrap().unwrap();
+    assert_eq!(updated.text, "Review this diff twice");
+    assert_eq!(updated.header.as_deref(), Some("Code review"));
+    assert_eq!(updated.created_at, review.created_at);
+    assert_eq!(prompts.tag_names(&review.id).unwrap(), ["backend"]);
+    assert!(prompts.get(&gone.id).unwrap().unwrap().deleted_at.is_none());
+
+    let records = prompts.records(None).unwrap();
+    let tone = records
+        .iter()
+        .find(|r| r.text == "Make it friendlier")
+        .unwrap();
+    assert_eq!(tone.header.as_deref(), Some("Tone"));
+    assert_eq!(tone.folder, Some(vec!["Writing".to_string()]));
+
+    fs::remove_file(out.0.join("Broken.md")).unwrap();
+    let again = import(&conn, &out.0, None);
+    assert_eq!(again.unchanged, 2);
+    assert_eq!(again.created, 1);
+}
+
+#[test]
+fn import_into_a_folder_reuses_subfolders() {
+    let conn = open();
+    let folders = FolderRepository::new(&conn);
+    let team = folders.create(folder("Team", None), 1).unwrap();
+    let reviews = folders
+        .create(folder("Reviews", Some(&team.id)), 1)
+        .unwrap();
+
+    let out = TempDir::new();
+    fs::create_dir_all(out.0.join("Reviews").join("Deep")).unwrap();
+    fs::write(out.0.join("Reviews").join("A.md"), "First").unwrap();
+    fs::write(out.0.join("Reviews").join("Deep").join("B.md"), "Second").unwrap();
+
+    let report = import(&conn, &out.0, Some(&team.id));
+    assert_eq!(report.created, 2);
+    assert_eq!(report.folders_created, 1);
+
+    let prompts = PromptRepository::new(&conn);
+    let records = prompts.records(Some(&team.id)).unwrap();
+    let folder_of = |text: &str| {
+        let record = records.iter().find(|r| r.text == text).unwrap();
+        record.folder.clone().unwrap()
+    };
+    assert_eq!(folder_of("First"), ["Reviews"]);
+    assert_eq!(folder_of("Second"), ["Reviews", "Deep"]);
+    let first = records.iter().find(|r| r.text == "First").unwrap();
+    let first = prompts.get(first.id.as_deref().unwrap()).unwrap().unwrap();
+    assert_eq!(first.folder_id.as_deref(), Some(reviews.id.as_str()));
+}
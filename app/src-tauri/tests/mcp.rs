@@ -0,0 +1,255 @@
+mod common;
+
+use common::{connect, folder};
+use promptpack_lib::db::{self, DbError};
+use promptpack_lib::mcp::McpServer;
+use promptpack_lib::repository::{
+    CreatePromptInput, FolderRepository, PromptFilter, PromptRepository,
+};
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A library file with prompts in `Team > Reviews`, one tagged, and one
+/// unfiled prompt. Removed again on drop.
+struct Library {
+    path: PathBuf,
+    team: String,
+    review: String,
+    unfiled: String,
+}
+
+impl Library {
+    fn new() -> Self {
+        let path = std::env::temp_dir().join(format!("promptpack-mcp-{}.db", uuid::Uuid::new_v4()));
+        let mut conn = connect(&path);
+        db::migrate(&mut conn).unwrap();
+
+        let folders = FolderRepository::new(&conn);
+        let team = folders.create(folder("Team", None), 1).unwrap();
+        let reviews = folders
+            .create(folder("Reviews", Some(&team.id)), 1)
+            .unwrap();
+
+        let prompts = PromptRepository::new(&conn);
+        let review = prompts
+            .create(
+                CreatePromptInput {
+                    text: "Review this {Language} diff for bugs".to_string(),
+                    header: Some("Code review".to_string()),
+                    folder_id: Some(reviews.id.clone()),
+                    ..Default::default()
+                },
+                2,
+            )
+            .unwrap();
+        let unfiled = prompts
+            .create(
+                CreatePromptInput {
+                    text: "Summarize this article".to_string(),
+                    ..Default::default()
+                },
+                3,
+            )
+            .unwrap();
+        conn.execute_batch(&format!(
+            "INSERT INTO tags (id, name) VALUES ('t1', 'backend');
+             INSERT INTO prompt_tags (prompt_id, tag_id) VALUES ('{}', 't1');",
+            review.id
+        ))
+        .unwrap();
+
+        Self {
+            path,
+            team: team.id,
+            review: review.id,
+            unfiled: unfiled.id,
+        }
+    }
+
+    fn server(&self, scope: PromptFilter) -> McpServer {
+        let path = self.path.clone();
+        McpServer::new(move || Ok(connect(&path)), scope)
+    }
+}
+
+impl Drop for Library {
+    fn drop(&mut self) {
+        let _ = db::remove_database_files(&self.path);
+    }
+}
+
+fn request(server: &McpServer, method: &str, params: Value) -> Value {
+    let message = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+    let reply = server.handle(&message.to_string()).unwrap();
+    assert_eq!(reply["id"], 1);
+    reply
+}
+
+fn names(list: &Value) -> Vec<&str> {
+    list["result"]["prompts"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|p| p["name"].as_str().unwrap())
+        .collect()
+}
+
+#[test]
+fn prompts_are_listed_with_their_variables() {
+    let library = Library::new();
+    let server = library.server(PromptFilter::default());
+
+    let init = request(
+        &server,
+        "initialize",
+        json!({ "protocolVersion": "2025-03-26" }),
+    );
+    assert_eq!(init["result"]["protocolVersion"], "2025-03-26");
+    assert!(init["result"]["capabilities"]["prompts"].is_object());
+    assert!(server
+        .handle(r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#)
+        .is_none());
+
+    let list = request(&server, "prompts/list", Value::Null);
+    assert_eq!(names(&list), [&library.unfiled, &library.review]);
+    let review = &list["result"]["prompts"][1];
+    assert_eq!(review["title"], "Code review");
+    assert_eq!(
+        review["description"],
+        "Folder: Team / Reviews. Tags: backend"
+    );
+    assert_eq!(review["arguments"][0]["name"], "Language");
+    assert!(list["result"]["prompts"][0].get("description").is_none());
+}
+
+#[test]
+fn get_renders_arguments_and_counts_a_use() {
+    let library = Library::new();
+    let server = library.server(PromptFilter::default());
+
+    let reply = request(
+        &server,
+        "prompts/get",
+        json!({ "name": library.review, "arguments": { "Language": "Rust" } }),
+    );
+    let message = &reply["result"]["messages"][0];
+    assert_eq!(message["role"], "user");
+    assert_eq!(message["content"]["text"], "Review this Rust diff for bugs");
+
+    let conn = connect(&library.path);
+    let prompt = PromptRepository::new(&conn)
+        .get(&library.review)
+        .unwrap()
+        .unwrap();
+    assert_eq!(prompt.use_count, 1);
+
+    let missing = request(&server, "prompts/get", json!({ "name": "nope" }));
+    assert_eq!(missing["error"]["code"], -32602);
+}
+
+#[test]
+fn scope_hides_other_prompts() {
+    let library = Library::new();
+    let server = library.server(PromptFilter {
+        folder_id: Some(library.team.clone()),
+        include_subfolders: true,
+        ..Default::default()
+    });
+
+    let list = request(&server, "prompts/list", json!({}));
+    assert_eq!(names(&list), [&library.review]);
+
+    let hidden = request(&server, "prompts/get", json!({ "name": library.unfiled }));
+    assert_eq!(hidden["error"]["code"], -32602);
+
+    let search = request(
+        &server,
+        "tools/call",
+        json!({ "name": "search_prompts", "arguments": { "query": "summarize" } }),
+    );
+    assert_eq!(search["result"]["structuredContent"]["prompts"], json!([]));
+
+    let tagged = library.server(PromptFilter {
+        tags: vec!["frontend".to_string()],
+        ..Default::default()
+    });
+    assert!(names(&request(&tagged, "prompts/list", json!({}))).is_empty());
+}
+
+#[test]
+fn search_tool_finds_prompts() {
+    let library = Library::new();
+    let server = library.server(PromptFilter::default());
+
+    let tools = request(&server, "tools/list", json!({}));
+    assert_eq!(tools["result"]["tools"][0]["name"], "search_prompts");
+
+    let search = request(
+        &server,
+        "tools/call",
+        json!({ "name": "search_prompts", "arguments": { "query": "diff", "tags": ["backend"] } }),
+    );
+    let found = &search["result"]["structuredContent"]["prompts"];
+    assert_eq!(found.as_array().unwrap().len(), 1);
+    assert_eq!(found[0]["id"], library.review.as_str());
+    assert_eq!(found[0]["folder"], json!(["Team", "Reviews"]));
+    assert_eq!(found[0]["variables"], json!(["Language"]));
+    assert!(search["result"]["content"][0]["text"]
+        .as_str()
+        .unwrap()
+        .starts_with("## Code review"));
+
+    let empty = request(
+        &server,
+        "tools/call",
+        json!({ "name": "search_prompts", "arguments": { "query": " " } }),
+    );
+    assert_eq!(empty["result"]["isError"], true);
+
+    let unknown = request(
+        &server,
+        "tools/call",
+        json!({ "name": "delete_everything" }),
+    );
+    assert_eq!(unknown["error"]["code"], -32602);
+}
+
+#[test]
+fn unavailable_library_is_reported_until_it_opens() {
+    let library = Library::new();
+    let path = library.path.clone();
+    let ready = Arc::new(AtomicBool::new(false));
+    let opened = ready.clone();
+    let server = McpServer::new(
+        move || match opened.load(Ordering::SeqCst) {
+            true => Ok(connect(&path)),
+            false => Err(DbError::NotReady.into()),
+        },
+        PromptFilter::default(),
+    );
+
+    let failed = request(&server, "prompts/list", json!({}));
+    assert_eq!(failed["error"]["data"]["code"], "database_not_ready");
+
+    ready.store(true, Ordering::SeqCst);
+    let list = request(&server, "prompts/list", json!({}));
+    assert_eq!(names(&list).len(), 2);
+}
+
+#[test]
+fn malformed_messages_get_errors() {
+    let library = Library::new();
+    let server = library.server(PromptFilter::default());
+
+    let parse = server.handle("{not json").unwrap();
+    assert_eq!(parse["error"]["code"], -32700);
+    assert_eq!(parse["id"], Value::Null);
+
+    let unknown = request(&server, "resources/list", json!({}));
+    assert_eq!(unknown["error"]["code"], -32601);
+
+    let bad_params = request(&server, "prompts/get", json!({ "arguments": {} }));
+    assert_eq!(bad_params["error"]["code"], -32602);
+}
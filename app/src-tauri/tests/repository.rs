@@ -0,0 +1,575 @@
+mod common;
+
+use common::{folder, open, prompt};
+use promptpack_lib::db::DbError;
+use promptpack_lib::journal::{self, Direction};
+use promptpack_lib::repository::{
+    self, CreateFolderInput, CreatePromptInput, DeleteFolderMode, FolderRepository, Prompt,
+    PromptPageQuery, PromptRepository, UpdateFolderInput, UpdatePromptInput,
+};
+
+fn invalid_fields(error: DbError) -> Vec<String> {
+    match error {
+        DbError::Validation(e) => e.fields.into_iter().map(|f| f.field).collect(),
+        other => panic!("expected a validation error, got {other:?}"),
+    }
+}
+
+#[test]
+fn created_prompt_can_be_read_back() {
+    let conn = open();
+    let prompts = PromptRepository::new(&conn);
+
+    let created = prompts.create(prompt("Summarize this", None), 10).unwrap();
+    assert_eq!(created.source, "manual");
+    assert_eq!(created.sync_status, "local-only");
+
+    let stored = prompts.get(&created.id).unwrap().unwrap();
+    assert_eq!(stored.text, "Summarize this");
+    assert_eq!(stored.created_at, 10);
+    assert_eq!(stored.updated_at, 10);
+    assert!(prompts.get("missing").unwrap().is_none());
+}
+
+#[test]
+fn invalid_prompt_reports_every_field() {
+    let conn = open();
+    let input = CreatePromptInput {
+        text: " ".to_string(),
+        source: Some("myspace".to_string()),
+        url: Some("javascript:alert(1)".to_string()),
+        folder_id: Some("missing".to_string()),
+        ..Default::default()
+    };
+
+    let error = PromptRepository::new(&conn).create(input, 1).unwrap_err();
+    assert_eq!(
+        invalid_fields(error),
+        ["text", "source", "url", "folder_id"]
+    );
+    let count: i64 = conn
+        .query_row("SELECT COUNT(*) FROM prompts", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(count, 0);
+}
+
+#[test]
+fn update_changes_only_given_fields() {
+    let conn = open();
+    let prompts = PromptRepository::new(&conn);
+    let created = prompts
+        .create(
+            CreatePromptInput {
+                header: Some("Header".to_string()),
+                ..prompt("Old text", None)
+            },
+            1,
+        )
+        .unwrap();
+
+    let input = UpdatePromptInput {
+        text: Some("New text".to_string()),
+        is_favorite: Some(true),
+        ..Default::default()
+    };
+    let updated = prompts.update(&created.id, input, 2).unwrap();
+    assert_eq!(updated.text, "New text");
+    assert_eq!(updated.header.as_deref(), Some("Header"));
+    assert!(updated.is_favorite);
+    assert_eq!(updated.updated_at, 2);
+
+    let error = prompts
+        .update("missing", UpdatePromptInput::default(), 3)
+        .unwrap_err();
+    assert!(matches!(error, DbError::NotFound("Prompt")));
+}
+
+#[test]
+fn deleted_prompt_moves_to_trash_and_back() {
+    let conn = open();
+    let prompts = PromptRepository::new(&conn);
+    let created = prompts.create(prompt("Draft an email", None), 1).unwrap();
+
+    prompts.delete(&created.id, 5).unwrap();
+    assert_eq!(
+        prompts.get(&created.id).unwrap().unwrap().deleted_at,
+        Some(5)
+    );
+    assert_eq!(prompts.trashed().unwrap().len(), 1);
+    assert!(prompts.search("email").unwrap().is_empty());
+
+    let restored = prompts.restore(&created.id, 6).unwrap();
+    assert_eq!(restored.deleted_at, None);
+    assert!(prompts.trashed().unwrap().is_empty());
+    assert_eq!(prompts.search("email").unwrap().len(), 1);
+}
+
+#[test]
+fn search_matches_words_and_prefixes() {
+    let conn = open();
+    let prompts = PromptRepository::new(&conn);
+    prompts
+        .create(prompt("Translate to French", None), 1)
+        .unwrap();
+    prompts.create(prompt("Write unit tests", None), 2).unwrap();
+
+    let hits = prompts.search("transl").unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].text, "Translate to French");
+    assert!(prompts.search("   ").unwrap().is_empty());
+}
+
+#[test]
+fn find_by_text_ignores_surrounding_whitespace_and_trash() {
+    let conn = open();
+    let prompts = PromptRepository::new(&conn);
+    let created = prompts.create(prompt("Fix the typos", None), 1).unwrap();
+
+    let found = prompts
+        .find_by_text("\n  Fix the typos\t")
+        .unwrap()
+        .unwrap();
+    assert_eq!(found.id, created.id);
+    assert!(prompts.find_by_text("Fix the typo").unwrap().is_none());
+
+    prompts.delete(&created.id, 2).unwrap();
+    assert!(prompts.find_by_text("Fix the typos").unwrap().is_none());
+}
+
+#[test]
+fn record_use_bumps_the_count() {
+    let conn = open();
+    let prompts = PromptRepository::new(&conn);
+    let created = prompts.create(prompt("Explain", None), 1).unwrap();
+
+    prompts.record_use(&created.id, 7).unwrap();
+    let used = prompts.record_use(&created.id, 8).unwrap();
+    assert_eq!(used.use_count, 2);
+    assert_eq!(used.last_used_at, Some(8));
+}
+
+#[test]
+fn used_and_favorite_prompts_are_ranked() {
+    let conn = open();
+    let prompts = PromptRepository::new(&conn);
+    let once = prompts.create(prompt("Used once", None), 1).unwrap();
+    let twice = prompts.create(prompt("Used twice", None), 2).unwrap();
+    let favorite = prompts.create(prompt("Favorite", None), 3).unwrap();
+    prompts.create(prompt("Never used", None), 4).unwrap();
+    let input = UpdatePromptInput {
+        is_favorite: Some(true),
+        ..Default::default()
+    };
+    prompts.update(&favorite.id, input, 5).unwrap();
+
+    prompts.record_use(&twice.id, 10).unwrap();
+    prompts.record_use(&twice.id, 11).unwrap();
+    prompts.record_use(&once.id, 12).unwrap();
+
+    let ids = |list: Vec<Prompt>| list.into_iter().map(|p| p.id).collect::<Vec<_>>();
+    assert_eq!(ids(prompts.favorites(5).unwrap()), [favorite.id]);
+    assert_eq!(
+        ids(prompts.most_used(5).unwrap()),
+        [twice.id.clone(), once.id.clone()]
+    );
+    assert_eq!(ids(prompts.recently_used(1).unwrap()), [once.id]);
+}
+
+#[test]
+fn pages_follow_the_cursor() {
+    let conn = open();
+    let prompts = PromptRepository::new(&conn);
+    for i in 0..5 {
+        prompts
+            .create(prompt(&format!("Prompt {i}"), None), i)
+            .unwrap();
+    }
+
+    let mut query = PromptPageQuery {
+        limit: Some(2),
+        ..Default::default()
+    };
+    let mut seen = Vec::new();
+    loop {
+        let page = prompts.page(&query, 100).unwrap();
+        assert_eq!(page.total, 5);
+        seen.extend(page.prompts.into_iter().map(|p| p.text));
+        match page.next_cursor {
+            Some(cursor) => query.cursor = Some(cursor),
+            None => break,
+        }
+    }
+    assert_eq!(
+        seen,
+        ["Prompt 4", "Prompt 3", "Prompt 2", "Prompt 1", "Prompt 0"]
+    );
+}
+
+#[test]
+fn import_skips_entries_without_text() {
+    let conn = open();
+    let entries = serde_json::json!([
+        { "text": "One", "source": "claude" },
+        { "header": "No text" },
+        { "text": "Two", "header": "Second" },
+    ]);
+
+    let prompts = PromptRepository::new(&conn);
+    let imported = prompts.import(entries.as_array().unwrap(), 1).unwrap();
+    assert_eq!(imported.prompts.len(), 2);
+    assert_eq!(imported.prompts[0].source, "claude");
+    assert_eq!(imported.prompts[1].header.as_deref(), Some("Second"));
+    assert!(prompts.get(&imported.prompts[1].id).unwrap().is_some());
+    assert_eq!(imported.errors.len(), 1);
+    assert_eq!(imported.errors[0].location, "prompts[1]");
+}
+
+#[test]
+fn import_rejects_entries_create_would_reject() {
+    let conn = open();
+    let entries = serde_json::json!([
+        { "text": "x".repeat(100_001) },
+        { "text": "Bogus source", "source": "<script>" },
+        { "text": "Long header", "header": "h".repeat(201) },
+        { "text": "Fine", "source": "gemini" },
+    ]);
+
+    let prompts = PromptRepository::new(&conn);
+    let imported = prompts.import(entries.as_array().unwrap(), 1).unwrap();
+    assert_eq!(imported.prompts.len(), 1);
+    assert_eq!(imported.prompts[0].text, "Fine");
+    let skipped: Vec<_> = imported
+        .errors
+        .iter()
+        .map(|e| e.location.as_str())
+        .collect();
+    assert_eq!(skipped, ["prompts[0]", "prompts[1]", "prompts[2]"]);
+    assert!(imported.errors[1].message.starts_with("source:"));
+
+    let stored: i64 = conn
+        .query_row("SELECT COUNT(*) FROM prompts", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(stored, 1);
+}
+
+#[test]
+fn new_folders_go_after_their_siblings() {
+    let conn = open();
+    let folders = FolderRepository::new(&conn);
+    let a = folders.create(folder("A", None), 1).unwrap();
+    let b = folders.create(folder("B", None), 2).unwrap();
+    let child = folders.create(folder("Child", Some(&a.id)), 3).unwrap();
+
+    assert!(b.sort_order > a.sort_order);
+    assert_eq!(child.parent_id.as_deref(), Some(a.id.as_str()));
+    let tree = folders.tree().unwrap();
+    let names: Vec<_> = tree.iter().map(|n| n.folder.name.as_str()).collect();
+    assert_eq!(names, ["A", "B"]);
+    assert_eq!(tree[0].children[0].folder.id, child.id);
+}
+
+#[test]
+fn invalid_folder_is_rejected() {
+    let conn = open();
+    let input = CreateFolderInput {
+        name: String::new(),
+        color: Some("blue".to_string()),
+        parent_id: Some("missing".to_string()),
+        ..Default::default()
+    };
+
+    let error = FolderRepository::new(&conn).create(input, 1).unwrap_err();
+    assert_eq!(invalid_fields(error), ["name", "color", "parent_id"]);
+}
+
+#[test]
+fn folder_update_keeps_unset_fields() {
+    let conn = open();
+    let folders = FolderRepository::new(&conn);
+    let created = folders
+        .create(
+            CreateFolderInput {
+                icon: Some("📁".to_string()),
+                ..folder("Work", None)
+            },
+            1,
+        )
+        .unwrap();
+
+    let input = UpdateFolderInput {
+        name: Some("Office".to_string()),
+        ..Default::default()
+    };
+    let updated = folders.update(&created.id, input, 2).unwrap();
+    assert_eq!(updated.name, "Office");
+    assert_eq!(updated.icon.as_deref(), Some("📁"));
+}
+
+#[test]
+fn tree_counts_prompts_in_subfolders() {
+    let conn = open();
+    let folders = FolderRepository::new(&conn);
+    let prompts = PromptRepository::new(&conn);
+    let root = folders.create(folder("Root", None), 1).unwrap();
+    let child = folders.create(folder("Child", Some(&root.id)), 2).unwrap();
+    prompts
+        .create(prompt("In root", Some(&root.id)), 3)
+        .unwrap();
+    prompts
+        .create(prompt("In child", Some(&child.id)), 4)
+        .unwrap();
+    prompts
+        .create(prompt("Also in child", Some(&child.id)), 5)
+        .unwrap();
+
+    let tree = folders.tree().unwrap();
+    assert_eq!(tree.len(), 1);
+    assert_eq!(tree[0].prompt_count, 1);
+    assert_eq!(tree[0].total_prompt_count, 3);
+    assert_eq!(tree[0].children[0].prompt_count, 2);
+
+    assert_eq!(prompts.in_folder(&root.id, false).unwrap().len(), 1);
+    assert_eq!(prompts.in_folder(&root.id, true).unwrap().len(), 3);
+}
+
+#[test]
+fn deleted_folder_takes_its_contents_to_the_trash() {
+    let conn = open();
+    let folders = FolderRepository::new(&conn);
+    let prompts = PromptRepository::new(&conn);
+    let root = folders.create(folder("Root", None), 1).unwrap();
+    let child = folders.create(folder("Child", Some(&root.id)), 2).unwrap();
+    let inside = prompts
+        .create(prompt("Inside", Some(&child.id)), 3)
+        .unwrap();
+
+    let result = folders
+        .delete(&root.id, &DeleteFolderMode::Trash, 10)
+        .unwrap();
+    assert_eq!((result.folders_trashed, result.prompts_trashed), (2, 1));
+    assert!(folders.list().unwrap().is_empty());
+    assert_eq!(folders.trashed().unwrap().len(), 2);
+
+    folders.restore(&root.id, 11).unwrap();
+    assert_eq!(folders.list().unwrap().len(), 2);
+    assert_eq!(prompts.get(&inside.id).unwrap().unwrap().deleted_at, None);
+}
+
+#[test]
+fn deleted_folder_can_hand_its_contents_to_its_parent() {
+    let conn = open();
+    let folders = FolderRepository::new(&conn);
+    let prompts = PromptRepository::new(&conn);
+    let root = folders.create(folder("Root", None), 1).unwrap();
+    let middle = folders.create(folder("Middle", Some(&root.id)), 2).unwrap();
+    let leaf = folders.create(folder("Leaf", Some(&middle.id)), 3).unwrap();
+    let inside = prompts
+        .create(prompt("Inside", Some(&middle.id)), 4)
+        .unwrap();
+
+    let result = folders
+        .delete(&middle.id, &DeleteFolderMode::MoveToParent, 10)
+        .unwrap();
+    assert_eq!((result.folders_moved, result.prompts_moved), (1, 1));
+
+    let leaf = folders.get(&leaf.id).unwrap().unwrap();
+    assert_eq!(leaf.parent_id.as_deref(), Some(root.id.as_str()));
+    let inside = prompts.get(&inside.id).unwrap().unwrap();
+    assert_eq!(inside.folder_id.as_deref(), Some(root.id.as_str()));
+    assert_eq!(inside.deleted_at, None);
+}
+
+#[test]
+fn folder_cannot_move_into_itself() {
+    let conn = open();
+    let folders = FolderRepository::new(&conn);
+    let root = folders.create(folder("Root", None), 1).unwrap();
+    let child = folders.create(folder("Child", Some(&root.id)), 2).unwrap();
+
+    assert!(folders.move_to(&root.id, Some(&child.id), None, 3).is_err());
+    folders.move_to(&child.id, None, Some(0), 4).unwrap();
+
+    let names: Vec<_> = folders
+        .list()
+        .unwrap()
+        .into_iter()
+        .map(|f| f.name)
+        .collect();
+    assert_eq!(names, ["Child", "Root"]);
+}
+
+#[test]
+fn title_falls_back_to_the_first_line() {
+    assert_eq!(repository::title(Some(" Review "), "Text", 10), "Review");
+    assert_eq!(repository::title(Some("  "), "Draft\nmore", 10), "Draft");
+    assert_eq!(
+        repository::title(None, "Translate to French", 9),
+        "Translate…"
+    );
+    assert_eq!(repository::title(None, "", 10), "");
+}
+
+#[test]
+fn undo_refuses_to_overwrite_changes_made_elsewhere() {
+    let conn = open();
+    let prompts = PromptRepository::new(&conn);
+    let created = prompts.create(prompt("Draft", None), 1).unwrap();
+    let edit = UpdatePromptInput {
+        text: Some("Edited".to_string()),
+        ..Default::default()
+    };
+    prompts.update(&created.id, edit, 2).unwrap();
+    prompts.record_use(&created.id, 3).unwrap();
+
+    // Usage counts don't block an undo, other writes do
+    conn.execute(
+        "UPDATE prompts SET header = 'From the CLI' WHERE id = ?1",
+        [&created.id],
+    )
+    .unwrap();
+    let error = journal::apply(&conn, Direction::Undo, 4).unwrap_err();
+    assert!(matches!(error, DbError::Conflict(_)));
+    let current = prompts.get(&created.id).unwrap().unwrap();
+    assert_eq!(current.text, "Edited");
+    assert_eq!(current.header.as_deref(), Some("From the CLI"));
+
+    conn.execute(
+        "UPDATE prompts SET header = NULL WHERE id = ?1",
+        [&created.id],
+    )
+    .unwrap();
+    journal::apply(&conn, Direction::Undo, 5).unwrap().unwrap();
+    let current = prompts.get(&created.id).unwrap().unwrap();
+    assert_eq!(current.text, "Draft");
+    assert_eq!(current.use_count, 1);
+}
+
+#[test]
+fn undoing_a_create_leaves_a_sync_tombstone() {
+    let conn = open();
+    let prompts = PromptRepository::new(&conn);
+    let created = prompts.create(prompt("Draft", None), 1).unwrap();
+    let tombstones = || -> i64 {
+        conn.query_row(
+            "SELECT COUNT(*) FROM sync_tombstones WHERE prompt_id = ?1",
+            [&created.id],
+            |row| row.get(0),
+        )
+        .unwrap()
+    };
+
+    journal::apply(&conn, Direction::Undo, 2).unwrap().unwrap();
+    assert!(prompts.get(&created.id).unwrap().is_none());
+    assert_eq!(tombstones(), 1);
+
+    journal::apply(&conn, Direction::Redo, 3).unwrap().unwrap();
+    assert!(prompts.get(&created.id).unwrap().is_some());
+    assert_eq!(tombstones(), 0);
+}
+
+#[test]
+fn edits_and_deletes_undo_and_redo() {
+    let conn = open();
+    let prompts = PromptRepository::new(&conn);
+    let created = prompts.create(prompt("Draft", None), 1).unwrap();
+    let edit = UpdatePromptInput {
+        text: Some("Edited".to_string()),
+        ..Default::default()
+    };
+    prompts.update(&created.id, edit, 2).unwrap();
+    prompts.delete(&created.id, 3).unwrap();
+    assert_eq!(
+        journal::undo_state(&conn).unwrap().undo.as_deref(),
+        Some("Delete prompt")
+    );
+
+    let undone = journal::apply(&conn, Direction::Undo, 4).unwrap().unwrap();
+    assert_eq!(undone.label, "Delete prompt");
+    assert_eq!(prompts.get(&created.id).unwrap().unwrap().deleted_at, None);
+    journal::apply(&conn, Direction::Undo, 5).unwrap().unwrap();
+    assert_eq!(prompts.get(&created.id).unwrap().unwrap().text, "Draft");
+
+    let redone = journal::apply(&conn, Direction::Redo, 6).unwrap().unwrap();
+    assert_eq!(redone.label, "Edit prompt");
+    assert_eq!(prompts.get(&created.id).unwrap().unwrap().text, "Edited");
+    journal::apply(&conn, Direction::Redo, 7).unwrap().unwrap();
+    assert!(prompts
+        .get(&created.id)
+        .unwrap()
+        .unwrap()
+        .deleted_at
+        .is_some());
+    assert!(journal::apply(&conn, Direction::Redo, 8).unwrap().is_none());
+}
+
+#[test]
+fn folder_move_undoes_and_redoes() {
+    let conn = open();
+    let folders = FolderRepository::new(&conn);
+    let first = folders.create(folder("First", None), 1).unwrap();
+    let second = folders.create(folder("Second", None), 2).unwrap();
+    folders
+        .move_to(&second.id, Some(&first.id), None, 3)
+        .unwrap();
+
+    journal::apply(&conn, Direction::Undo, 4).unwrap().unwrap();
+    let moved = folders.get(&second.id).unwrap().unwrap();
+    assert_eq!(
+        (moved.parent_id, moved.sort_order),
+        (None, second.sort_order)
+    );
+
+    journal::apply(&conn, Direction::Redo, 5).unwrap().unwrap();
+    let moved = folders.get(&second.id).unwrap().unwrap();
+    assert_eq!(moved.parent_id.as_deref(), Some(first.id.as_str()));
+}
+
+#[test]
+fn new_operation_clears_the_redo_stack() {
+    let conn = open();
+    let prompts = PromptRepository::new(&conn);
+    let created = prompts.create(prompt("Draft", None), 1).unwrap();
+    prompts.delete(&created.id, 2).unwrap();
+    journal::apply(&conn, Direction::Undo, 3).unwrap().unwrap();
+    assert_eq!(
+        journal::undo_state(&conn).unwrap().redo.as_deref(),
+        Some("Delete prompt")
+    );
+
+    prompts.create(prompt("Another", None), 4).unwrap();
+    let state = journal::undo_state(&conn).unwrap();
+    assert_eq!(state.undo.as_deref(), Some("Create prompt"));
+    assert_eq!(state.redo, None);
+    assert!(journal::apply(&conn, Direction::Redo, 5).unwrap().is_none());
+}
+
+#[test]
+fn journal_keeps_only_the_newest_operations() {
+    let conn = open();
+    let prompts = PromptRepository::new(&conn);
+    let extra = 5;
+    for i in 0..journal::MAX_OPERATIONS + extra {
+        prompts
+            .create(prompt(&format!("Prompt {i}"), None), i)
+            .unwrap();
+    }
+    let count: i64 = conn
+        .query_row("SELECT COUNT(*) FROM operation_journal", [], |row| {
+            row.get(0)
+        })
+        .unwrap();
+    assert_eq!(count, journal::MAX_OPERATIONS);
+
+    let mut undone = 0;
+    while journal::apply(&conn, Direction::Undo, 1000)
+        .unwrap()
+        .is_some()
+    {
+        undone += 1;
+    }
+    assert_eq!(undone, journal::MAX_OPERATIONS);
+    for i in 0..extra {
+        let text = format!("Prompt {i}");
+        assert!(prompts.find_by_text(&text).unwrap().is_some());
+    }
+}
@@ -0,0 +1,234 @@
+import { useState, useEffect, useRef } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { Search, Copy, ArrowLeft } from 'lucide-react';
+import { parseTemplateVariables } from '../../lib/templateParser';
+import { errorMessage } from '../../types';
+
+// Prompt as returned by the get_prompts / search_prompts commands
+interface StoredPrompt {
+  id: string;
+  text: string;
+  header: string | null;
+  use_count: number;
+}
+
+interface PromptPage {
+  prompts: StoredPrompt[];
+  next_cursor: string | null;
+  total: number;
+  preview: boolean;
+}
+
+interface PickResult {
+  text: string;
+  missing: string[];
+  pasted: boolean;
+}
+
+const RECENT_LIMIT = 50;
+
+function promptTitle(prompt: StoredPrompt): string {
+  const header = prompt.header?.trim();
+  if (header) return header;
+  return prompt.text.split('\n', 1)[0];
+}
+
+/**
+ * Hotkey window: search prompts, fill in {Variables}, and copy (or paste)
+ * the result with pick_prompt.
+ */
+export function QuickPicker() {
+  const [query, setQuery] = useState('');
+  const [prompts, setPrompts] = useState<StoredPrompt[]>([]);
+  const [selected, setSelected] = useState(0);
+  const [picking, setPicking] = useState<StoredPrompt | null>(null);
+  const [variables, setVariables] = useState<string[]>([]);
+  const [values, setValues] = useState<Record<string, string>>({});
+  const [error, setError] = useState<string | null>(null);
+
+  const searchRef = useRef<HTMLInputElement>(null);
+  const firstInputRef = useRef<HTMLInputElement>(null);
+
+  // Most used prompts without a query, full-text matches with one
+  useEffect(() => {
+    let cancelled = false;
+    const timer = setTimeout(async () => {
+      try {
+        const found = query.trim()
+          ? await invoke<StoredPrompt[]>('search_prompts', { query })
+          : (
+              await invoke<PromptPage>('get_prompts', {
+                query: { sort: 'use_count', direction: 'desc', limit: RECENT_LIMIT },
+              })
+            ).prompts;
+        if (!cancelled) {
+          setPrompts(found);
+          setSelected(0);
+          setError(null);
+        }
+      } catch (err) {
+        if (!cancelled) setError(errorMessage(err));
+      }
+    }, 120);
+    return () => {
+      cancelled = true;
+      clearTimeout(timer);
+    };
+  }, [query]);
+
+  // The window is hidden rather than closed, so start over each time it shows
+  useEffect(() => {
+    const handleFocus = () => {
+      setPicking(null);
+      setQuery('');
+      setError(null);
+      searchRef.current?.focus();
+    };
+    window.addEventListener('focus', handleFocus);
+    return () => window.removeEventListener('focus', handleFocus);
+  }, []);
+
+  useEffect(() => {
+    if (picking) firstInputRef.current?.focus();
+    else searchRef.current?.focus();
+  }, [picking]);
+
+  const hide = () => {
+    invoke('hide_quick_picker').catch((err) => setError(errorMessage(err)));
+  };
+
+  const pick = async (prompt: StoredPrompt, filled: Record<string, string>) => {
+    try {
+      await invoke<PickResult>('pick_prompt', { id: prompt.id, values: filled });
+      setPicking(null);
+      setQuery('');
+    } catch (err) {
+      setError(errorMessage(err));
+    }
+  };
+
+  const choose = (prompt: StoredPrompt) => {
+    const found = parseTemplateVariables(prompt.text);
+    if (found.length === 0) {
+      pick(prompt, {});
+      return;
+    }
+    setVariables(found);
+    setValues(Object.fromEntries(found.map((v) => [v, ''])));
+    setPicking(prompt);
+  };
+
+  const handleSearchKeyDown = (e: React.KeyboardEvent) => {
+    if (e.key === 'ArrowDown') {
+      e.preventDefault();
+      setSelected((i) => Math.min(i + 1, prompts.length - 1));
+    } else if (e.key === 'ArrowUp') {
+      e.preventDefault();
+      setSelected((i) => Math.max(i - 1, 0));
+    } else if (e.key === 'Enter') {
+      e.preventDefault();
+      if (prompts[selected]) choose(prompts[selected]);
+    } else if (e.key === 'Escape') {
+      e.preventDefault();
+      hide();
+    }
+  };
+
+  const handleFormKeyDown = (e: React.KeyboardEvent) => {
+    if (e.key === 'Enter' && picking) {
+      e.preventDefault();
+      pick(picking, values);
+    } else if (e.key === 'Escape') {
+      e.preventDefault();
+      setPicking(null);
+    }
+  };
+
+  return (
+    <div className="h-screen flex flex-col bg-[var(--card)] text-[var(--foreground)] border border-[var(--border)] rounded-xl overflow-hidden">
+      {picking ? (
+        <>
+          {/* Variable form */}
+          <div className="flex items-center gap-2 p-3 border-b border-[var(--border)]">
+            <button
+              onClick={() => setPicking(null)}
+              className="p-1.5 rounded-lg hover:bg-[var(--accent)] text-[var(--muted-foreground)] transition-colors"
+            >
+              <ArrowLeft size={18} />
+            </button>
+            <h2 className="font-semibold truncate">{promptTitle(picking)}</h2>
+          </div>
+          <div className="flex-1 p-3 space-y-3 overflow-y-auto" onKeyDown={handleFormKeyDown}>
+            {variables.map((varName, index) => (
+              <div key={varName}>
+                <label className="block text-sm font-medium mb-1">{varName}</label>
+                <input
+                  ref={index === 0 ? firstInputRef : undefined}
+                  type="text"
+                  value={values[varName]}
+                  onChange={(e) =>
+                    setValues((prev) => ({ ...prev, [varName]: e.target.value }))
+                  }
+                  placeholder={`Enter ${varName}...`}
+                  className="w-full px-3 py-2 rounded-lg bg-[var(--background)] border border-[var(--border)] placeholder:text-[var(--muted-foreground)] focus:outline-none focus:ring-2 focus:ring-[var(--ring)]"
+                />
+              </div>
+            ))}
+          </div>
+          <div className="flex justify-end p-3 border-t border-[var(--border)]">
+            <button
+              onClick={() => pick(picking, values)}
+              className="flex items-center gap-2 px-4 py-2 bg-[var(--primary)] text-[var(--primary-foreground)] rounded-lg hover:opacity-90 transition-opacity"
+            >
+              <Copy size={16} />
+              <span>Copy</span>
+            </button>
+          </div>
+        </>
+      ) : (
+        <>
+          {/* Search */}
+          <div className="flex items-center gap-2 px-3 border-b border-[var(--border)]">
+            <Search size={18} className="text-[var(--muted-foreground)]" />
+            <input
+              ref={searchRef}
+              type="text"
+              value={query}
+              onChange={(e) => setQuery(e.target.value)}
+              onKeyDown={handleSearchKeyDown}
+              placeholder="Search prompts..."
+              className="flex-1 py-3 bg-transparent placeholder:text-[var(--muted-foreground)] focus:outline-none"
+            />
+          </div>
+          <ul className="flex-1 overflow-y-auto p-1">
+            {prompts.map((prompt, index) => (
+              <li
+                key={prompt.id}
+                onMouseEnter={() => setSelected(index)}
+                onClick={() => choose(prompt)}
+                className={`px-3 py-2 rounded-lg cursor-pointer ${
+                  index === selected ? 'bg-[var(--accent)]' : ''
+                }`}
+              >
+                <div className="text-sm font-medium truncate">{promptTitle(prompt)}</div>
+                <div className="text-xs text-[var(--muted-foreground)] truncate">
+                  {prompt.text}
+                </div>
+              </li>
+            ))}
+            {prompts.length === 0 && (
+              <li className="px-3 py-6 text-sm text-center text-[var(--muted-foreground)]">
+                {query.trim() ? 'No matching prompts' : 'No prompts yet'}
+              </li>
+            )}
+          </ul>
+        </>
+      )}
+      {error && (
+        <div className="px-3 py-2 text-sm text-red-400 border-t border-[var(--border)]">
+          {error}
+        </div>
+      )}
+    </div>
+  );
+}
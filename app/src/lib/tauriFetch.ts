@@ -1,4 +1,5 @@
 import { invoke } from '@tauri-apps/api/core';
+import { errorMessage } from '../types';
 
 interface ProxyFetchResponse {
   status: number;
@@ -37,6 +38,7 @@ export async function tauriFetch(url: string, init?: RequestInit): Promise<Respo
       headers: result.headers,
     });
   } catch (err) {
-    throw new TypeError(`Network request failed: ${err}`);
+    // Like fetch(), reject with a TypeError; the CommandError stays as the cause
+    throw new TypeError(`Network request failed: ${errorMessage(err)}`, { cause: err });
   }
 }
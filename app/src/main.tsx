@@ -2,13 +2,17 @@ import { StrictMode } from 'react'
 import { createRoot } from 'react-dom/client'
 import './index.css'
 import App from './App.tsx'
+import { QuickPicker } from './components/QuickPicker'
 import { useSettingsStore } from './stores/settingsStore'
 
 // Initialize theme before render
 useSettingsStore.getState().initTheme()
 
+// The hotkey picker loads the same bundle as index.html?window=picker
+const isPicker = new URLSearchParams(window.location.search).get('window') === 'picker'
+
 createRoot(document.getElementById('root')!).render(
   <StrictMode>
-    <App />
+    {isPicker ? <QuickPicker /> : <App />}
   </StrictMode>,
 )
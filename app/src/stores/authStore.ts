@@ -5,6 +5,22 @@ import { listen } from '@tauri-apps/api/event';
 import { CONVEX_URL } from '../lib/constants';
 import { tauriFetch } from '../lib/tauriFetch';
 import { useSyncStore } from './syncStore';
+import { errorMessage, isCommandError } from '../types';
+
+// Message for a failed auth command, with plainer wording where the user
+// has something to do about it
+function authErrorMessage(error: unknown): string {
+  if (isCommandError(error)) {
+    switch (error.code) {
+      case 'invalid_token':
+      case 'token_expired':
+        return 'Your sign-in link is no longer valid. Please sign in again.';
+      case 'network':
+        return 'Could not reach PromptPack. Check your connection and try again.';
+    }
+  }
+  return errorMessage(error);
+}
 
 // Helper to fetch user's billing tier from the backend
 async function fetchUserTier(clerkId: string): Promise<string> {
@@ -77,7 +93,7 @@ export const useAuthStore = create<AuthState>()(
           await invoke('open_auth_window');
         } catch (error) {
           set({
-            error: error instanceof Error ? error.message : String(error),
+            error: authErrorMessage(error),
             isLoading: false,
           });
         }
@@ -124,7 +140,7 @@ export const useAuthStore = create<AuthState>()(
           await invoke('close_auth_window');
         } catch (error) {
           set({
-            error: error instanceof Error ? error.message : String(error),
+            error: authErrorMessage(error),
             isLoading: false,
           });
         }
@@ -138,8 +154,14 @@ export const useAuthStore = create<AuthState>()(
           useSyncStore.getState().clearCache();
           set({ session: null, isLoading: false, error: null });
         } catch (error) {
+          if (isCommandError(error) && (error.code === 'invalid_token' || error.code === 'token_expired')) {
+            // The session is already gone on the backend, so finish signing out here
+            useSyncStore.getState().clearCache();
+            set({ session: null, isLoading: false, error: null });
+            return;
+          }
           set({
-            error: error instanceof Error ? error.message : String(error),
+            error: authErrorMessage(error),
             isLoading: false,
           });
         }
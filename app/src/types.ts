@@ -13,6 +13,54 @@ export type SyncStatus = 'pending' | 'synced' | 'local-only';
 
 export type UserTier = 'free' | 'pro' | 'studio';
 
+// Stable codes of a CommandError (see src-tauri/src/error.rs)
+export type ErrorCode =
+  | 'not_found'
+  | 'validation'
+  | 'password_required'
+  | 'invalid_password'
+  | 'vault_locked'
+  | 'vault_not_enabled'
+  | 'vault_already_enabled'
+  | 'database_busy'
+  | 'database_not_ready'
+  | 'database_init_failed'
+  | 'database_corrupt'
+  | 'database'
+  | 'invalid_file'
+  | 'crypto'
+  | 'invalid_token'
+  | 'token_expired'
+  | 'network'
+  | 'io'
+  | 'settings'
+  | 'conflict'
+  | 'shortcut_conflict'
+  | 'clipboard'
+  | 'internal';
+
+// What every Tauri command rejects with
+export interface CommandError {
+  code: ErrorCode;
+  message: string;
+  details: Record<string, unknown> | null;
+}
+
+export function isCommandError(error: unknown): error is CommandError {
+  return (
+    typeof error === 'object' &&
+    error !== null &&
+    typeof (error as CommandError).code === 'string' &&
+    typeof (error as CommandError).message === 'string'
+  );
+}
+
+// Readable message for anything a command or fetch threw
+export function errorMessage(error: unknown): string {
+  if (isCommandError(error) || error instanceof Error) return error.message;
+  return String(error);
+}
+
 export interface Prompt {
   id: string;
   text: string;